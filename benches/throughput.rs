@@ -0,0 +1,33 @@
+use cbom::bench::Fixtures;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+// Sized like a large OS image
+const PACKAGES: usize = 5000;
+const FILES: usize = 20000;
+
+fn throughput(c: &mut Criterion) {
+    let fixtures = Fixtures::new(PACKAGES, FILES).expect("Failed to generate fixtures");
+
+    let mut group = c.benchmark_group("extraction");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(fixtures.layer_size()));
+    group.bench_function("extraction + hashing", |b| b.iter(|| fixtures.extract()));
+    group.finish();
+
+    let mut group = c.benchmark_group("packages");
+    group.throughput(Throughput::Bytes(fixtures.apk_database_size()));
+    group.bench_function("apk database", |b| b.iter(|| fixtures.read_apk_database()));
+    group.throughput(Throughput::Bytes(fixtures.detector_input_size()));
+    for detector in Fixtures::detectors() {
+        group.bench_function(detector, |b| b.iter(|| fixtures.detect(detector)));
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("files");
+    group.throughput(Throughput::Elements(FILES as u64));
+    group.bench_function("file dedup", |b| b.iter(|| fixtures.dedup_files()));
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
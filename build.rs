@@ -0,0 +1,24 @@
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Records the commit the binary was built from and when, for the SBOM
+// metadata. SOURCE_DATE_EPOCH pins the build time for reproducible builds.
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=CBOM_GIT_COMMIT={}", commit);
+
+    let built_at = std::env::var("SOURCE_DATE_EPOCH").ok().unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default().to_string()
+    });
+    println!("cargo:rustc-env=CBOM_BUILD_EPOCH={}", built_at);
+}
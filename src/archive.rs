@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tar::Archive;
+use tracing::{info, instrument};
+use crate::progress::{Progress, ProgressReporter};
+use crate::registry::{layer_commands, layer_created, HistoryEntry, ImageReference, Result, RuntimeConfig};
+use crate::source::AnalyzedImage;
+use crate::{analyze_layer_files, analyze_layer_for_packages, cache, checks, detectors, ExtractOptions, Healthcheck, Layer};
+
+// One image in the manifest.json `docker save` writes at the top of the
+// archive; its layer tarballs are listed in the same order as the image's
+// RootFS layers
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ManifestEntry {
+    config: String,
+    #[serde(default)]
+    repo_tags: Option<Vec<String>>,
+    layers: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageConfig {
+    os: Option<String>,
+    architecture: Option<String>,
+    variant: Option<String>,
+    created: Option<String>,
+    #[serde(default)]
+    config: RuntimeConfig,
+    #[serde(default)]
+    history: Vec<HistoryEntry>,
+    rootfs: Option<RootFs>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RootFs {
+    #[serde(default)]
+    diff_ids: Vec<String>,
+}
+
+// Where each file of an image archive starts and how long it is, so layers
+// and the config can be read without unpacking the archive. Legacy
+// <id>/layer.tar entries may be symlinks to OCI blobs.
+struct ArchiveIndex {
+    manifest: Vec<ManifestEntry>,
+    entries: HashMap<String, (u64, u64)>,
+    links: HashMap<String, String>,
+}
+
+impl ArchiveIndex {
+    fn read(archive_path: &Path) -> Result<ArchiveIndex> {
+        let file = File::open(archive_path).map_err(|e| format!("cannot read {}: {}", archive_path.display(), e))?;
+        let mut archive = Archive::new(file);
+        let mut manifest = None;
+        let mut entries = HashMap::new();
+        let mut links = HashMap::new();
+        let not_a_tar = |e: std::io::Error| format!("cannot read {} as a tar archive: {}", archive_path.display(), e);
+        for entry in archive.entries().map_err(not_a_tar)? {
+            let mut entry = entry.map_err(not_a_tar)?;
+            let path = checks::normalize_path(&entry.path()?.display().to_string()).to_string();
+            if let Ok(Some(target)) = entry.link_name() {
+                links.insert(path.clone(), detectors::resolve_link_target(&path, &target.display().to_string(), entry.header().entry_type().is_hard_link()));
+            } else if path == "manifest.json" {
+                manifest = Some(serde_json::from_reader(&mut entry)?);
+            } else {
+                entries.insert(path, (entry.raw_file_position(), entry.size()));
+            }
+        }
+        let manifest = manifest.ok_or_else(|| format!("{} has no manifest.json; is it a `docker save` archive?", archive_path.display()))?;
+        Ok(ArchiveIndex { manifest, entries, links })
+    }
+
+    fn position(&self, path: &str) -> Result<(u64, u64)> {
+        let mut resolved = checks::normalize_path(path).to_string();
+        for _ in 0..detectors::MAX_LINK_DEPTH {
+            match self.links.get(&resolved) {
+                Some(target) => resolved = target.clone(),
+                None => break,
+            }
+        }
+        self.entries.get(&resolved).copied().ok_or_else(|| format!("{} is missing from the image archive", path).into())
+    }
+
+    // The image a name picks out of the archive: the one tagged with it, or
+    // the only image when the name does not match a tag
+    fn image(&self, image_name: &str) -> Result<&ManifestEntry> {
+        let tags = |entry: &ManifestEntry| entry.repo_tags.clone().unwrap_or_default();
+        let canonical = ImageReference::parse(image_name).canonical_name();
+        let tagged = self
+            .manifest
+            .iter()
+            .find(|entry| tags(entry).iter().any(|tag| tag == image_name || ImageReference::parse(tag).canonical_name() == canonical));
+        if let Some(entry) = tagged {
+            return Ok(entry);
+        }
+        match self.manifest.as_slice() {
+            [entry] => Ok(entry),
+            [] => Err("the image archive lists no images".into()),
+            entries => Err(format!(
+                "the image archive holds {} images; name one of its tags: {}",
+                entries.len(),
+                entries.iter().flat_map(tags).collect::<Vec<_>>().join(", ")
+            )
+            .into()),
+        }
+    }
+}
+
+// Where each layer tarball starts in an exported image and how long it is, in
+// layer order
+pub fn image_archive_layers(archive_path: &Path) -> Result<Vec<(u64, u64)>> {
+    let index = ArchiveIndex::read(archive_path)?;
+    let image = index.manifest.first().ok_or("the exported image has no manifest.json")?;
+    image.layers.iter().map(|layer| index.position(layer)).collect()
+}
+
+// A reader over one layer tarball inside an exported image, decompressing it
+// when the archive holds gzip-compressed OCI blobs
+pub fn archive_layer_reader(archive_path: &Path, (offset, size): (u64, u64)) -> Result<Box<dyn Read>> {
+    let mut file = File::open(archive_path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut reader = BufReader::new(file.take(size));
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        return Ok(Box::new(flate2::read::GzDecoder::new(reader)));
+    }
+    Ok(Box::new(reader))
+}
+
+// The name an archive's image goes by when none is given: its first tag
+pub fn image_name(archive_path: &Path) -> Result<Option<String>> {
+    let index = ArchiveIndex::read(archive_path)?;
+    Ok(index.manifest.first().and_then(|entry| entry.repo_tags.as_ref()?.first().cloned()))
+}
+
+// Reads an image from a `docker save` tarball, for machines without a
+// daemon. The image ID is the digest of the image config, as Docker computes it.
+#[instrument(name = "analyze", skip_all, fields(image = %image_name, source = "archive"))]
+pub fn analyze_image_archive(archive_path: &Path, image_name: &str, options: ExtractOptions, progress: &ProgressReporter) -> Result<AnalyzedImage> {
+    let index = ArchiveIndex::read(archive_path)?;
+    let image = index.image(image_name)?;
+
+    let mut config_data = Vec::new();
+    archive_layer_reader(archive_path, index.position(&image.config)?)?.read_to_end(&mut config_data)?;
+    let config: ImageConfig = serde_json::from_slice(&config_data).map_err(|e| format!("cannot read the image config {}: {}", image.config, e))?;
+    let image_id = format!("sha256:{:x}", Sha256::digest(&config_data));
+
+    let os_guess = config.os.clone().unwrap_or_else(|| "Unknown".to_string());
+    let platform = match (&config.os, &config.architecture, &config.variant) {
+        (Some(os), Some(architecture), Some(variant)) => Some(format!("{}/{}/{}", os, architecture, variant)),
+        (Some(os), Some(architecture), None) => Some(format!("{}/{}", os, architecture)),
+        _ => None,
+    };
+    let diff_ids = config.rootfs.as_ref().map(|rootfs| rootfs.diff_ids.clone()).unwrap_or_default();
+
+    let mut layers = Vec::new();
+    let total = image.layers.len();
+    for (index_in_image, layer_path) in image.layers.iter().enumerate() {
+        let layer_id = diff_ids.get(index_in_image).cloned().unwrap_or_else(|| layer_path.clone());
+        progress.report(Progress::LayerStarted { index: index_in_image, total, layer_id: layer_id.clone() });
+        let created = config.created.clone().unwrap_or_else(|| "Unknown".to_string());
+        let layer = cache::analyze_layer(options, &layer_id, &created, &os_guess, || {
+            let extracted = analyze_layer_files(archive_layer_reader(archive_path, index.position(layer_path)?)?, options)?;
+            let detected = analyze_layer_for_packages(&extracted, options.root_prefix);
+            Ok(Layer {
+                layer_id: layer_id.clone(),
+                created: created.clone(),
+                os_guess: os_guess.clone(),
+                pkg_format: detected.format.to_string(),
+                packages: detected.packages,
+                files: extracted.files,
+                notices: detected.notices,
+                analyzed_output: String::new(),
+                created_by: None,
+                contents: extracted.contents,
+                links: extracted.links,
+                dir_modes: extracted.dir_modes,
+                root_prefix: options.root_prefix,
+            })
+        })?;
+        progress.report(Progress::LayerFinished { index: index_in_image, total, completed: index_in_image + 1, files: layer.files.len() });
+        layers.push(layer);
+    }
+
+    info!(layers = layers.len(), "image analyzed");
+    progress.report(Progress::Finished { layers: layers.len(), cached: false });
+    Ok(AnalyzedImage {
+        platform,
+        layer_commands: layer_commands(&config.history, layers.len()),
+        layer_created: layer_created(&config.history, layers.len()),
+        layers,
+        healthcheck: config.config.healthcheck.as_ref().and_then(Healthcheck::from_config),
+        entrypoint: config.config.entrypoint.clone(),
+        cmd: config.config.cmd.clone(),
+        labels: config.config.labels.clone().unwrap_or_default(),
+        digest: None,
+        image_id: Some(image_id),
+    })
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use data_encoding::BASE64;
+use serde::Serialize;
+use crate::registry::Result;
+
+const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+// The predicate type cosign and policy engines expect of SPDX SBOMs
+const SPDX_PREDICATE_TYPE: &str = "https://spdx.dev/Document";
+const PAYLOAD_TYPE: &str = "application/vnd.in-toto+json";
+
+// An in-toto statement: a predicate about the artifacts named as subjects
+#[derive(Debug, Serialize)]
+pub struct Statement {
+    #[serde(rename = "_type")]
+    kind: &'static str,
+    subject: Vec<Subject>,
+    #[serde(rename = "predicateType")]
+    predicate_type: &'static str,
+    predicate: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct Subject {
+    name: String,
+    digest: BTreeMap<String, String>,
+}
+
+// A DSSE envelope: the base64 payload and signatures over its
+// pre-authentication encoding
+#[derive(Debug, Serialize)]
+pub struct Envelope {
+    #[serde(rename = "payloadType")]
+    payload_type: &'static str,
+    payload: String,
+    signatures: Vec<EnvelopeSignature>,
+}
+
+#[derive(Debug, Serialize)]
+struct EnvelopeSignature {
+    keyid: String,
+    sig: String,
+}
+
+impl Statement {
+    // States that the image with `image_digest` (algorithm:hex, as in
+    // image_digest) has the SBOM `spdx_document`, an SPDX JSON document
+    pub fn spdx(image_name: &str, image_digest: &str, spdx_document: &str) -> Result<Statement> {
+        let (algorithm, hex) = image_digest
+            .split_once(':')
+            .filter(|(algorithm, hex)| !algorithm.is_empty() && !hex.is_empty())
+            .ok_or_else(|| format!("{} has no image digest to name as the attestation subject", image_name))?;
+        Ok(Statement {
+            kind: STATEMENT_TYPE,
+            subject: vec![Subject {
+                name: image_name.to_string(),
+                digest: BTreeMap::from([(algorithm.to_string(), hex.to_string())]),
+            }],
+            predicate_type: SPDX_PREDICATE_TYPE,
+            predicate: serde_json::from_str(spdx_document)?,
+        })
+    }
+}
+
+impl Envelope {
+    // Signs the statement with `sign`, which returns a base64 signature of
+    // the bytes it is given, under the key fingerprint `keyid`
+    pub fn sign(statement: &Statement, keyid: String, sign: impl FnOnce(&[u8]) -> String) -> Envelope {
+        let payload = serde_json::to_vec(statement).expect("Failed to serialize in-toto statement");
+        let sig = sign(&pre_authentication_encoding(PAYLOAD_TYPE, &payload));
+        Envelope {
+            payload_type: PAYLOAD_TYPE,
+            payload: BASE64.encode(&payload),
+            signatures: vec![EnvelopeSignature { keyid, sig }],
+        }
+    }
+
+    // One envelope per line, as .intoto.jsonl files hold them
+    pub fn to_jsonl(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize DSSE envelope") + "\n"
+    }
+}
+
+// PAE(type, body) = "DSSEv1" SP LEN(type) SP type SP LEN(body) SP body, with
+// lengths in decimal
+fn pre_authentication_encoding(payload_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut encoded = format!("DSSEv1 {} {} {} ", payload_type.len(), payload_type, payload.len()).into_bytes();
+    encoded.extend_from_slice(payload);
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pre_authentication_encoding_matches_the_dsse_spec() {
+        // The example in the DSSE protocol description
+        assert_eq!(
+            pre_authentication_encoding("http://example.com/HelloWorld", b"hello world"),
+            b"DSSEv1 29 http://example.com/HelloWorld 11 hello world"
+        );
+    }
+
+    #[test]
+    fn pre_authentication_encoding_counts_bytes() {
+        assert_eq!(pre_authentication_encoding("", b""), b"DSSEv1 0  0 ");
+        assert_eq!(pre_authentication_encoding(PAYLOAD_TYPE, "é".as_bytes()), "DSSEv1 28 application/vnd.in-toto+json 2 é".as_bytes());
+    }
+
+    #[test]
+    fn statement_names_the_image_digest_as_subject() {
+        let statement = Statement::spdx("alpine:3.20", "sha256:abc", r#"{"spdxVersion":"SPDX-2.3"}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&statement).unwrap(),
+            r#"{"_type":"https://in-toto.io/Statement/v1","subject":[{"name":"alpine:3.20","digest":{"sha256":"abc"}}],"predicateType":"https://spdx.dev/Document","predicate":{"spdxVersion":"SPDX-2.3"}}"#
+        );
+        assert!(Statement::spdx("alpine:3.20", "", "{}").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use crate::registry::Result;
+use crate::{analyze_layer_files, analyze_layer_for_packages, dedup_files, detectors, ExtractOptions, ExtractedFiles, Layer};
+
+type Detector = fn(&mut [Layer]);
+
+const DETECTORS: [(&str, Detector); 4] = [
+    ("busybox applets", detectors::detect_busybox_applets),
+    ("conda", detectors::detect_conda_packages),
+    ("composer", detectors::detect_composer_packages),
+    ("install reasons", detectors::detect_install_reasons),
+];
+
+// Generated inputs for the benchmarks in benches/: a layer tarball, an apk
+// database and a layer holding the files each package detector reads. The
+// fixtures are deterministic so numbers from different commits can be
+// compared.
+pub struct Fixtures {
+    layer_tar: Vec<u8>,
+    apk_layer: ExtractedFiles,
+    layer: Layer,
+}
+
+impl Fixtures {
+    pub fn new(packages: usize, files: usize) -> Result<Fixtures> {
+        let layer_tar = layer_tar(files)?;
+        let layer = extracted_layer(&layer_tar, packages);
+        Ok(Fixtures {
+            layer_tar,
+            apk_layer: ExtractedFiles {
+                contents: HashMap::from([("lib/apk/db/installed".to_string(), apk_database(packages).into_bytes())]),
+                ..ExtractedFiles::default()
+            },
+            layer,
+        })
+    }
+
+    pub fn layer_size(&self) -> u64 {
+        self.layer_tar.len() as u64
+    }
+
+    pub fn apk_database_size(&self) -> u64 {
+        self.apk_layer.contents.values().map(|data| data.len() as u64).sum()
+    }
+
+    pub fn detector_input_size(&self) -> u64 {
+        self.layer.contents.values().map(|data| data.len() as u64).sum()
+    }
+
+    pub fn detectors() -> impl Iterator<Item = &'static str> {
+        DETECTORS.iter().map(|(name, _)| *name)
+    }
+
+    // Extracts and hashes every file of the layer
+    pub fn extract(&self) {
+        analyze_layer_files(Cursor::new(&self.layer_tar), ExtractOptions::default()).expect("Failed to read generated layer");
+    }
+
+    pub fn read_apk_database(&self) {
+        analyze_layer_for_packages(&self.apk_layer, None);
+    }
+
+    // Runs the detector named as in detectors()
+    pub fn detect(&self, name: &str) {
+        let (_, detector) = DETECTORS.iter().find(|(detector, _)| *detector == name).expect("Unknown detector");
+        detector(&mut [self.layer.clone()]);
+    }
+
+    pub fn dedup_files(&self) {
+        dedup_files(self.layer.files.clone());
+    }
+}
+
+// A layer with binaries, a busybox install with applet symlinks, and many
+// small files of repeated content so dedup has work to do
+fn layer_tar(files: usize) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut append = |path: &str, data: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, data)
+    };
+
+    append("bin/busybox", &[0x7f, b'E', b'L', b'F', 2, 1, 1, 0])?;
+    for index in 0..files {
+        // Sizes cycle from a few hundred bytes to 64 KiB
+        let size = 256 << (index % 9);
+        let data = vec![(index % 251) as u8; size];
+        append(&format!("usr/share/bench/{}/file-{}.dat", index % 100, index), &data)?;
+    }
+    let mut builder = builder;
+    for applet in ["sh", "ls", "cat", "cp", "mv", "rm", "grep", "sed", "awk", "vi"] {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_cksum();
+        builder.append_link(&mut header, format!("bin/{}", applet), "/bin/busybox")?;
+    }
+    Ok(builder.into_inner()?)
+}
+
+fn apk_database(packages: usize) -> String {
+    (0..packages)
+        .map(|index| {
+            format!(
+                "C:Q1{:028}=\nP:bench-package-{}\nV:1.{}.{}-r0\nA:x86_64\nS:{}\nI:{}\nT:Synthetic package\nL:MIT\no:bench-origin-{}\nt:1700000000\nD:so:libc.musl-x86_64.so.1\n\n",
+                index,
+                index,
+                index / 10,
+                index % 10,
+                1024 + index,
+                4096 + index,
+                index / 4
+            )
+        })
+        .collect()
+}
+
+fn extracted_layer(layer_tar: &[u8], packages: usize) -> Layer {
+    let extracted = analyze_layer_files(Cursor::new(layer_tar), ExtractOptions::default()).expect("Failed to read generated layer");
+    let mut layer = Layer {
+        layer_id: "sha256:bench".to_string(),
+        created: String::new(),
+        os_guess: "linux".to_string(),
+        pkg_format: "apk".to_string(),
+        packages: Vec::new(),
+        files: extracted.files,
+        notices: Vec::new(),
+        analyzed_output: String::new(),
+        created_by: None,
+        contents: extracted.contents,
+        links: extracted.links,
+        dir_modes: extracted.dir_modes,
+        root_prefix: None,
+    };
+
+    for index in 0..packages {
+        let meta = format!(
+            r#"{{"name":"bench-{}","version":"1.{}","build":"py311_0","channel":"https://conda.anaconda.org/conda-forge/linux-64","subdir":"linux-64","license":"MIT","files":["lib/python3.11/site-packages/bench_{}/__init__.py"]}}"#,
+            index, index, index
+        );
+        layer.contents.insert(format!("opt/conda/conda-meta/bench-{}-1.{}-py311_0.json", index, index), meta.into_bytes());
+    }
+    let composer_packages: Vec<String> = (0..packages)
+        .map(|index| format!(r#"{{"name":"bench/package-{}","version":"v1.{}","license":["MIT"]}}"#, index, index))
+        .collect();
+    layer.contents.insert(
+        "var/www/html/vendor/composer/installed.json".to_string(),
+        format!(r#"{{"packages":[{}]}}"#, composer_packages.join(",")).into_bytes(),
+    );
+    let world: Vec<String> = (0..packages).step_by(10).map(|index| format!("bench-package-{}", index)).collect();
+    layer.contents.insert("etc/apk/world".to_string(), world.join("\n").into_bytes());
+    layer
+}
@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use data_encoding::BASE64;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use crate::progress::{Progress, ProgressReporter};
+use crate::registry::Result;
+use crate::source::{AnalyzedImage, ImageSource};
+use crate::{build_commit, detectors, ExtractOptions, Healthcheck, Layer};
+
+// Version of the cached entries' layout. Bump it whenever Layer, Package or
+// anything else stored in an entry changes, so entries written before the
+// change are analyzed afresh instead of misread.
+const CACHE_FORMAT: u32 = 1;
+
+// Analysis results of immutable images, keyed by repo digest
+pub struct ResultCache {
+    dir: PathBuf,
+}
+
+// Analysis results of single layers, keyed by layer digest, which images
+// sharing base layers reuse
+#[derive(Debug)]
+pub struct LayerCache {
+    dir: PathBuf,
+}
+
+// What an analysis depends on besides the image: the cache format, the tool
+// version and commit, the set of detectors (which decides what file contents
+// extraction keeps) and the extraction options. A cached analysis is only
+// reused when they match.
+#[derive(Serialize, Deserialize, PartialEq)]
+struct AnalysisSettings {
+    // Entries from before the format was recorded have 0
+    #[serde(default)]
+    format: u32,
+    tool_version: String,
+    #[serde(default)]
+    commit: Option<String>,
+    detectors: Vec<String>,
+    max_file_size: Option<u64>,
+    #[serde(default)]
+    root_prefix: Option<String>,
+    #[serde(default)]
+    license_texts: bool,
+    #[serde(default)]
+    web_assets: bool,
+    #[serde(default)]
+    include_globs: Vec<String>,
+    #[serde(default)]
+    exclude_globs: Vec<String>,
+}
+
+impl AnalysisSettings {
+    fn new(options: ExtractOptions) -> AnalysisSettings {
+        AnalysisSettings {
+            format: CACHE_FORMAT,
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            commit: build_commit(),
+            detectors: detectors::DETECTORS.iter().map(|name| name.to_string()).collect(),
+            max_file_size: options.max_file_size,
+            root_prefix: options.root_prefix.map(str::to_string),
+            license_texts: options.license_texts,
+            web_assets: options.web_assets,
+            include_globs: options.filter.map_or_else(Vec::new, |filter| filter.include_patterns.clone()),
+            exclude_globs: options.filter.map_or_else(Vec::new, |filter| filter.exclude_patterns.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    #[serde(flatten)]
+    settings: AnalysisSettings,
+    platform: Option<String>,
+    healthcheck: Option<Healthcheck>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    entrypoint: Option<Vec<String>>,
+    #[serde(default)]
+    cmd: Option<Vec<String>>,
+    #[serde(default)]
+    layer_commands: Vec<Option<String>>,
+    #[serde(default)]
+    layer_created: Vec<Option<String>>,
+    #[serde(default)]
+    digest: Option<String>,
+    #[serde(default)]
+    image_id: Option<String>,
+    layers: Vec<CachedLayer>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LayerEntry {
+    #[serde(flatten)]
+    settings: AnalysisSettings,
+    layer: CachedLayer,
+}
+
+// A layer along with the in-memory data checks and detectors read, which the
+// SBOM itself leaves out
+#[derive(Serialize, Deserialize)]
+struct CachedLayer {
+    layer: Layer,
+    contents: BTreeMap<String, String>,
+    links: BTreeMap<String, String>,
+    dir_modes: BTreeMap<String, u32>,
+}
+
+impl CachedLayer {
+    fn new(layer: &Layer) -> CachedLayer {
+        CachedLayer {
+            layer: layer.clone(),
+            contents: layer.contents.iter().map(|(path, data)| (path.clone(), BASE64.encode(data))).collect(),
+            links: layer.links.clone().into_iter().collect(),
+            dir_modes: layer.dir_modes.clone().into_iter().collect(),
+        }
+    }
+
+    fn restore(self, options: ExtractOptions) -> Option<Layer> {
+        let contents = self
+            .contents
+            .into_iter()
+            .map(|(path, data)| Some((path, BASE64.decode(data.as_bytes()).ok()?)))
+            .collect::<Option<_>>()?;
+        Some(Layer {
+            contents,
+            links: self.links.into_iter().collect(),
+            dir_modes: self.dir_modes.into_iter().collect(),
+            root_prefix: options.root_prefix,
+            ..self.layer
+        })
+    }
+}
+
+// $XDG_CACHE_HOME/cbom, or ~/.cache/cbom
+pub fn default_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CACHE_HOME").map(PathBuf::from) {
+        Some(cache_home) if cache_home.is_absolute() => Some(cache_home.join("cbom")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache").join("cbom")),
+    }
+}
+
+// Removes every cached image and layer analysis under `dir`, returning how
+// many entries there were
+pub fn clear(dir: &Path) -> Result<usize> {
+    let mut removed = 0;
+    for subdir in ["results", "layers"] {
+        let path = dir.join(subdir);
+        match fs::read_dir(&path) {
+            Ok(entries) => removed += entries.count(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("cannot read {}: {}", path.display(), e).into()),
+        }
+        fs::remove_dir_all(&path).map_err(|e| format!("cannot remove {}: {}", path.display(), e))?;
+    }
+    Ok(removed)
+}
+
+// Writes to a temporary file first so concurrent runs never read a partial
+// entry
+fn write_entry(dir: &Path, path: PathBuf, entry: &impl Serialize) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(&serde_json::to_vec(entry)?)?;
+    file.persist(path)?;
+    Ok(())
+}
+
+fn read_entry<T: for<'de> Deserialize<'de>>(path: &Path) -> Option<T> {
+    match fs::read(path).map(|data| serde_json::from_slice(&data)) {
+        Ok(Ok(entry)) => Some(entry),
+        Ok(Err(e)) => {
+            warn!(path = %path.display(), error = %e, "ignoring unreadable cache entry");
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+impl ResultCache {
+    pub fn new(dir: &str) -> ResultCache {
+        ResultCache {
+            dir: PathBuf::from(dir).join("results"),
+        }
+    }
+
+    // `key` is the repo digest, qualified by platform when the digest covers
+    // several (an image index)
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key.replace([':', '/'], "_")))
+    }
+
+    pub fn load(&self, key: &str, options: ExtractOptions) -> Option<AnalyzedImage> {
+        let path = self.entry_path(key);
+        let entry: CacheEntry = read_entry(&path)?;
+        if entry.settings != AnalysisSettings::new(options) {
+            debug!(path = %path.display(), "cache entry is stale");
+            return None;
+        }
+
+        let layers = entry.layers.into_iter().map(|cached| cached.restore(options)).collect::<Option<_>>()?;
+        Some(AnalyzedImage {
+            platform: entry.platform,
+            layers,
+            healthcheck: entry.healthcheck,
+            labels: entry.labels,
+            entrypoint: entry.entrypoint,
+            cmd: entry.cmd,
+            layer_commands: entry.layer_commands,
+            layer_created: entry.layer_created,
+            digest: entry.digest,
+            image_id: entry.image_id,
+        })
+    }
+
+    pub fn store(&self, key: &str, image: &AnalyzedImage, options: ExtractOptions) -> Result<()> {
+        let entry = CacheEntry {
+            settings: AnalysisSettings::new(options),
+            platform: image.platform.clone(),
+            healthcheck: image.healthcheck.clone(),
+            labels: image.labels.clone(),
+            entrypoint: image.entrypoint.clone(),
+            cmd: image.cmd.clone(),
+            layer_commands: image.layer_commands.clone(),
+            layer_created: image.layer_created.clone(),
+            digest: image.digest.clone(),
+            image_id: image.image_id.clone(),
+            layers: image.layers.iter().map(CachedLayer::new).collect(),
+        };
+        write_entry(&self.dir, self.entry_path(key), &entry)
+    }
+}
+
+impl LayerCache {
+    pub fn new(dir: &Path) -> LayerCache {
+        LayerCache { dir: dir.join("layers") }
+    }
+
+    // Only layers identified by the digest of their contents are cached
+    fn entry_path(&self, layer_id: &str) -> Option<PathBuf> {
+        let digest = layer_id.strip_prefix("sha256:")?;
+        digest.bytes().all(|b| b.is_ascii_hexdigit()).then(|| self.dir.join(format!("sha256_{}.json", digest)))
+    }
+
+    fn load(&self, layer_id: &str, options: ExtractOptions) -> Option<Layer> {
+        let path = self.entry_path(layer_id)?;
+        let entry: LayerEntry = read_entry(&path)?;
+        if entry.settings != AnalysisSettings::new(options) {
+            debug!(path = %path.display(), "cache entry is stale");
+            return None;
+        }
+        entry.layer.restore(options)
+    }
+
+    fn store(&self, layer: &Layer, options: ExtractOptions) -> Result<()> {
+        let Some(path) = self.entry_path(&layer.layer_id) else { return Ok(()) };
+        write_entry(&self.dir, path, &LayerEntry { settings: AnalysisSettings::new(options), layer: CachedLayer::new(layer) })
+    }
+}
+
+// The analysis of a layer from an earlier run, if the layer cache has one,
+// with this image's creation time and OS
+pub fn cached_layer(options: ExtractOptions, layer_id: &str, created: &str, os_guess: &str) -> Option<Layer> {
+    let layer = options.layer_cache?.load(layer_id, options)?;
+    debug!(layer = %layer_id, "using cached layer analysis");
+    Some(Layer { created: created.to_string(), os_guess: os_guess.to_string(), ..layer })
+}
+
+pub fn store_layer(options: ExtractOptions, layer: &Layer) {
+    if let Some(cache) = options.layer_cache {
+        if let Err(e) = cache.store(layer, options) {
+            warn!(layer = %layer.layer_id, error = %e, "cannot write layer analysis to the cache");
+        }
+    }
+}
+
+// Analyzes a layer with `analyze`, unless the layer cache has its analysis.
+// Only layers `analyze` read in full are cached; an error is returned as is.
+pub fn analyze_layer(options: ExtractOptions, layer_id: &str, created: &str, os_guess: &str, analyze: impl FnOnce() -> Result<Layer>) -> Result<Layer> {
+    if let Some(layer) = cached_layer(options, layer_id, created, os_guess) {
+        return Ok(layer);
+    }
+    let layer = analyze()?;
+    store_layer(options, &layer);
+    Ok(layer)
+}
+
+// Analyzes the image, reusing the cached result for its repo digest when there
+// is one. Images without a repo digest are always analyzed.
+pub async fn analyze_cached(
+    source: &ImageSource,
+    image_name: &str,
+    options: ExtractOptions,
+    cache: Option<&ResultCache>,
+    progress: &ProgressReporter,
+) -> Result<AnalyzedImage> {
+    let Some(cache) = cache else {
+        return source.analyze(image_name, options, progress).await;
+    };
+    let key = match source.cache_key(image_name).await {
+        Ok(key) => key,
+        Err(e) => {
+            warn!(error = %e, "cannot determine repo digest; analyzing without the cache");
+            None
+        }
+    };
+    let Some(key) = key else {
+        return source.analyze(image_name, options, progress).await;
+    };
+
+    if let Some(image) = cache.load(&key, options) {
+        info!(key = %key, "using cached analysis");
+        progress.report(Progress::Finished { layers: image.layers.len(), cached: true });
+        return Ok(image);
+    }
+    let image = source.analyze(image_name, options, progress).await?;
+    if let Err(e) = cache.store(&key, &image, options) {
+        warn!(key = %key, error = %e, "cannot write analysis to the cache");
+    }
+    Ok(image)
+}
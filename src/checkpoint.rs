@@ -0,0 +1,73 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use crate::registry::Result;
+
+const CHECKPOINT_FILE: &str = ".cbom-progress.json";
+
+// Records which images (and platforms) of a run have had their SBOM written
+// to --output-dir, so that --resume can skip them after an interruption.
+pub struct Checkpoint {
+    path: PathBuf,
+    completed: Vec<Completed>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile {
+    completed: Vec<Completed>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Completed {
+    image: String,
+    platform: Option<String>,
+    // Output names can contain the date, so the written path is kept
+    output: String,
+}
+
+impl Checkpoint {
+    // Without `resume` the run starts over and earlier progress is discarded
+    pub fn open(dir: &str, resume: bool) -> Checkpoint {
+        let path = Path::new(dir).join(CHECKPOINT_FILE);
+        let completed = match resume {
+            true => match fs::read(&path).map(|data| serde_json::from_slice::<CheckpointFile>(&data)) {
+                Ok(Ok(file)) => file.completed,
+                Ok(Err(e)) => {
+                    warn!(path = %path.display(), error = %e, "ignoring unreadable checkpoint");
+                    Vec::new()
+                }
+                Err(_) => Vec::new(),
+            },
+            false => Vec::new(),
+        };
+        Checkpoint { path, completed }
+    }
+
+    // The output written for an image when it completed, provided `valid`
+    // still accepts it
+    pub fn completed_output(&self, image: &str, platform: Option<&str>, valid: impl Fn(&str) -> bool) -> Option<&str> {
+        self.completed
+            .iter()
+            .find(|entry| entry.image == image && entry.platform.as_deref() == platform)
+            .map(|entry| entry.output.as_str())
+            .filter(|output| valid(output))
+    }
+
+    pub fn mark_completed(&mut self, image: &str, platform: Option<&str>, output: &str) -> Result<()> {
+        self.completed.retain(|entry| !(entry.image == image && entry.platform.as_deref() == platform));
+        self.completed.push(Completed {
+            image: image.to_string(),
+            platform: platform.map(str::to_string),
+            output: output.to_string(),
+        });
+
+        // Written through a temporary file so a kill mid-write leaves the
+        // previous checkpoint intact
+        let file = CheckpointFile { completed: self.completed.clone() };
+        let temp = self.path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec_pretty(&file)?)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,544 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use chrono::{DateTime, Utc};
+use x509_parser::pem::Pem;
+use x509_parser::prelude::{FromDer, X509Certificate};
+use serde::Deserialize;
+use crate::{detectors, osv};
+use crate::systemd::Service;
+use crate::{CopiedSource, Healthcheck, Layer, Notice, NoticeLocation, Package, SKIPPED_CHECKSUM};
+
+pub const DEFAULT_TOOLCHAIN_PACKAGES: &[&str] = &[
+    "autoconf",
+    "automake",
+    "binutils",
+    "build-base",
+    "build-essential",
+    "clang",
+    "cmake",
+    "cpp",
+    "g++",
+    "gcc",
+    "libtool",
+    "make",
+];
+
+// Packages a team has accepted. Allowlisted packages are left out of policy
+// checks and gating but stay in the SBOM inventory.
+#[derive(Debug, Default)]
+pub struct Allowlist {
+    entries: Vec<(String, Option<String>)>,
+}
+
+impl Allowlist {
+    // Adds a NAME or NAME@VERSION entry
+    pub fn add(&mut self, entry: &str) {
+        let entry = match entry.split_once('@') {
+            Some((name, version)) => (name.to_string(), Some(version.to_string())),
+            None => (entry.to_string(), None),
+        };
+        self.entries.push(entry);
+    }
+
+    // Reads one NAME[@VERSION] entry per line; blank lines and lines starting
+    // with '#' are ignored.
+    pub fn add_file(&mut self, path: &str) -> std::io::Result<()> {
+        for line in fs::read_to_string(path)?.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                self.add(line);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn allows(&self, package: &Package) -> bool {
+        self.entries.iter().any(|(name, version)| {
+            *name == package.name && version.as_ref().is_none_or(|version| *version == package.version)
+        })
+    }
+}
+
+// Build toolchains are rarely needed at runtime and widen the attack surface of
+// the final image, so flag any that were left installed. Only the packages
+// still installed at the end count, each once, on the layer that lists them.
+pub fn check_toolchain_packages(layers: &mut [Layer], toolchain: &[String], allowlist: &Allowlist) {
+    let mut found = BTreeMap::new();
+    for (index, package) in crate::final_packages(layers) {
+        if toolchain.contains(&package.name) && !allowlist.allows(package) {
+            found.entry(format!("{} {}", package.name, package.version)).or_insert(index);
+        }
+    }
+    for (package, index) in found {
+        layers[index].notices.push(Notice {
+            message: format!(
+                "Build toolchain package {} is present in the final image; it is rarely needed at runtime and increases attack surface",
+                package
+            ),
+            level: "warning".to_string(),
+            location: None,
+            rule: Some("toolchain-package".to_string()),
+        });
+    }
+}
+
+// Known-malicious packages and files, read from a JSON array of entries. Each
+// entry names a package (`name`, optionally pinned to a `version`), a `purl`,
+// or a file `sha256`; `description` is shown in the finding.
+#[derive(Debug, Default)]
+pub struct MalwareDb {
+    entries: Vec<MalwareEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MalwareEntry {
+    name: Option<String>,
+    version: Option<String>,
+    purl: Option<String>,
+    sha256: Option<String>,
+    description: Option<String>,
+}
+
+impl MalwareDb {
+    pub fn load(path: &str) -> Result<MalwareDb, String> {
+        let data = fs::read(path).map_err(|e| e.to_string())?;
+        let mut entries: Vec<MalwareEntry> = serde_json::from_slice(&data).map_err(|e| e.to_string())?;
+        for (index, entry) in entries.iter_mut().enumerate() {
+            if entry.name.is_none() && entry.purl.is_none() && entry.sha256.is_none() {
+                return Err(format!("entry {} has none of name, purl or sha256", index));
+            }
+            entry.sha256 = entry.sha256.take().map(|sha256| normalize_sha256(&sha256));
+        }
+        Ok(MalwareDb { entries })
+    }
+
+    fn match_package(&self, package: &Package) -> Option<&MalwareEntry> {
+        self.entries.iter().find(|entry| {
+            let by_purl = match (&entry.purl, &package.purl) {
+                // An entry without qualifiers matches any qualifiers
+                (Some(wanted), Some(purl)) => purl == wanted || purl.split_once('?').is_some_and(|(base, _)| base == wanted),
+                _ => false,
+            };
+            let by_name = entry.name.as_ref().is_some_and(|name| {
+                *name == package.name && entry.version.as_ref().is_none_or(|version| *version == package.version)
+            });
+            let by_checksum = entry.sha256.as_ref().is_some_and(|sha256| *sha256 == normalize_sha256(&package.checksum));
+            by_purl || by_name || by_checksum
+        })
+    }
+
+    fn match_checksum(&self, checksum: &str) -> Option<&MalwareEntry> {
+        self.entries.iter().find(|entry| entry.sha256.as_deref() == Some(checksum))
+    }
+}
+
+fn normalize_sha256(checksum: &str) -> String {
+    checksum.strip_prefix("sha256:").unwrap_or(checksum).to_ascii_lowercase()
+}
+
+// Reports every package and file in the malware database as a critical
+// finding. Allowlists deliberately do not apply. Returns whether anything
+// matched.
+pub fn check_malware(layers: &mut [Layer], malware_db: &MalwareDb) -> bool {
+    let mut found = false;
+    for layer in layers {
+        let mut notices = Vec::new();
+        for package in &layer.packages {
+            if let Some(entry) = malware_db.match_package(package) {
+                notices.push(format!("Known malicious package {} {}{}", package.name, package.version, describe(entry)));
+            }
+        }
+        for file in layer.files.iter().filter(|file| file.file_type == "file" && file.checksum != SKIPPED_CHECKSUM) {
+            if let Some(entry) = malware_db.match_checksum(&file.checksum) {
+                notices.push(format!("Known malicious file /{} (sha256 {}){}", normalize_path(&file.path), file.checksum, describe(entry)));
+            }
+        }
+
+        found |= !notices.is_empty();
+        layer.notices.extend(notices.into_iter().map(|message| Notice {
+            message,
+            level: "critical".to_string(),
+            location: None,
+            rule: Some("malware".to_string()),
+        }));
+    }
+    found
+}
+
+fn describe(entry: &MalwareEntry) -> String {
+    entry.description.as_ref().map(|description| format!(": {}", description)).unwrap_or_default()
+}
+
+const LOCAL_HOSTS: &[&str] = &["localhost", "127.0.0.1", "0.0.0.0", "[::1]"];
+const SHELLS: &[&str] = &["sh", "bash", "ash", "dash", "zsh"];
+
+// A healthcheck runs repeatedly inside every container started from the image.
+// Probing an external URL makes container health depend on (and leak to) a
+// third party, and piping output into a shell or eval runs whatever it returns.
+pub fn check_healthcheck(layers: &mut [Layer], healthcheck: &Healthcheck) {
+    let Some(layer) = layers.last_mut() else {
+        return;
+    };
+    let script = match healthcheck.command.split_first() {
+        Some((form, args)) if form == "CMD" || form == "CMD-SHELL" => args.join(" "),
+        _ => healthcheck.command.join(" "),
+    };
+    let words: Vec<&str> = script.split_whitespace().collect();
+    let command_name = |word: &str| word.rsplit('/').next().unwrap_or(word).to_string();
+
+    if words.iter().any(|word| matches!(command_name(word).as_str(), "curl" | "wget")) {
+        for url in words.iter().filter_map(|word| word.trim_matches(|c| c == '\'' || c == '"').split_once("://")) {
+            let host = url.1.split(['/', '?']).next().unwrap_or_default();
+            let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+            let host = match host.rsplit_once(':') {
+                Some((name, port)) if !name.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => name,
+                _ => host,
+            };
+            if !host.is_empty() && !host.starts_with('$') && !LOCAL_HOSTS.contains(&host) {
+                layer.notices.push(Notice {
+                    message: format!("Healthcheck contacts external host {}; container health depends on a service outside the container", host),
+                    level: "warning".to_string(),
+                    location: None,
+                    rule: Some("healthcheck".to_string()),
+                });
+            }
+        }
+    }
+
+    // `a || b` is not a pipe
+    let pipes_to_shell = script.replace("||", ";").split('|').skip(1).any(|part| {
+        part.split_whitespace().next().is_some_and(|word| SHELLS.contains(&command_name(word).as_str()))
+    });
+    if pipes_to_shell || words.contains(&"eval") {
+        layer.notices.push(Notice {
+            message: format!("Healthcheck executes dynamically produced commands: {}", script),
+            level: "warning".to_string(),
+            location: None,
+            rule: Some("healthcheck".to_string()),
+        });
+    }
+}
+
+// A shell-form ENTRYPOINT or CMD runs as `sh -c ...`, making the shell PID 1.
+// Shells neither forward SIGTERM to the command nor reap zombies, so `docker
+// stop` waits out its timeout and kills the container, unless an init runs
+// first.
+pub fn check_pid1_signal_handling(layers: &mut [Layer], entrypoint: Option<&[String]>, cmd: Option<&[String]>) {
+    let process: Vec<&String> = entrypoint.unwrap_or_default().iter().chain(cmd.unwrap_or_default()).collect();
+    let Some(program) = process.first() else {
+        return;
+    };
+    let name = program.rsplit('/').next().unwrap_or(program);
+    let shell_form = matches!(name, "sh" | "bash" | "ash" | "dash" | "zsh") && process.get(1).is_some_and(|arg| *arg == "-c");
+    let init_first = detectors::INIT_SYSTEMS
+        .iter()
+        .any(|(init, binaries)| name == *init || binaries.iter().any(|binary| normalize_path(program) == *binary) || (*init == "s6" && program.as_str() == "/init"));
+    if !shell_form || init_first {
+        return;
+    }
+    let Some(layer) = layers.last_mut() else {
+        return;
+    };
+    layer.notices.push(Notice {
+        message: format!(
+            "The container runs `{}` as PID 1 without an init; signals such as SIGTERM will not reach the command. Use the exec form, or start an init (tini, dumb-init) or run with --init",
+            process.iter().map(|arg| arg.as_str()).collect::<Vec<_>>().join(" ")
+        ),
+        level: "warning".to_string(),
+        location: None,
+        rule: Some("pid1-signals".to_string()),
+    });
+}
+
+// Enabled systemd services start when the image boots as a VM or a systemd
+// container. Network-facing daemons running as root are the most exposed.
+pub fn check_systemd_services(layers: &mut [Layer], services: &[Service]) {
+    for service in services.iter().filter(|service| service.state == "enabled") {
+        let command = service.exec_start.first().map_or(service.unit.as_str(), String::as_str);
+        let (message, level) = match (service.network_facing, service.runs_as_root) {
+            (true, true) => (format!("Enabled service {} runs network-facing daemon `{}` as root", service.unit, command), "warning"),
+            (true, false) => (format!("Enabled service {} runs network-facing daemon `{}`", service.unit, command), "info"),
+            (false, true) => (format!("Enabled service {} runs `{}` as root", service.unit, command), "info"),
+            (false, false) => continue,
+        };
+        let path = normalize_path(&service.path);
+        let Some(layer) = layers.iter_mut().rev().find(|layer| layer.contents.contains_key(path)) else {
+            continue;
+        };
+        layer.notices.push(Notice {
+            message,
+            level: level.to_string(),
+            location: None,
+            rule: Some("systemd-service".to_string()),
+        });
+    }
+}
+
+// Content ADDed from a URL or git repository at build time is outside the
+// build context and is not reproducible unless pinned with --checksum.
+pub fn check_remote_sources(layers: &mut [Layer], dockerfile_path: &str, copies: &[CopiedSource]) {
+    let Some(layer) = layers.last_mut() else {
+        return;
+    };
+    for copy in copies.iter().filter(|copy| copy.origin == "remote") {
+        let location = Some(NoticeLocation {
+            file: dockerfile_path.to_string(),
+            line: copy.line,
+        });
+        let notice = match &copy.checksum {
+            Some(checksum) => Notice {
+                message: format!("ADD fetches {} into {} at build time, pinned to {}", copy.source, copy.destination, checksum),
+                level: "info".to_string(),
+                location,
+                rule: Some("remote-source".to_string()),
+            },
+            None => Notice {
+                message: format!(
+                    "ADD fetches {} into {} at build time without --checksum; the content is unverified and may change between builds",
+                    copy.source, copy.destination
+                ),
+                level: "warning".to_string(),
+                location,
+                rule: Some("remote-source".to_string()),
+            },
+        };
+        layer.notices.push(notice);
+    }
+}
+
+// A world-writable directory without the sticky bit lets any user delete or
+// replace files other users (or root) put there. Later layers can fix the
+// permissions, so only each directory's final mode is checked, and the finding
+// goes to the layer that set it.
+pub fn check_world_writable_dirs(layers: &mut [Layer]) {
+    let mut final_modes: HashMap<&str, (usize, u32)> = HashMap::new();
+    for (index, layer) in layers.iter().enumerate() {
+        for (path, mode) in &layer.dir_modes {
+            final_modes.insert(path, (index, *mode));
+        }
+    }
+
+    let mut findings: Vec<(usize, String)> = final_modes
+        .into_iter()
+        .filter(|(_, (_, mode))| mode & 0o002 != 0 && mode & 0o1000 == 0)
+        .map(|(path, (index, mode))| {
+            (index, format!("Directory /{} is world-writable without the sticky bit (mode {:04o})", path, mode))
+        })
+        .collect();
+    findings.sort();
+    for (index, message) in findings {
+        layers[index].notices.push(Notice {
+            message,
+            level: "warning".to_string(),
+            location: None,
+            rule: Some("world-writable-dir".to_string()),
+        });
+    }
+}
+
+const PACKAGE_CACHE_DIRS: &[&str] = &[
+    "var/cache/apk",
+    "var/cache/apt",
+    "var/cache/dnf",
+    "var/cache/yum",
+    "var/cache/zypp",
+    "var/lib/apt/lists",
+];
+
+// Leftover package manager caches only add size and stale metadata to an image.
+pub fn check_package_caches(layers: &mut [Layer]) {
+    for layer in layers {
+        for cache_dir in PACKAGE_CACHE_DIRS {
+            let prefix = format!("{}/", cache_dir);
+            let (count, size) = layer
+                .files
+                .iter()
+                .filter(|file| file.file_type == "file" && layer.rooted(&file.path).is_some_and(|path| path.starts_with(&prefix)))
+                .fold((0, 0), |(count, size), file| (count + 1, size + file.size));
+            if size > 0 {
+                layer.notices.push(Notice {
+                    message: format!(
+                        "Package manager cache /{} was not cleaned up: {} files wasting {}",
+                        cache_dir,
+                        count,
+                        format_size(size)
+                    ),
+                    level: "low".to_string(),
+                    location: None,
+                    rule: Some("package-cache".to_string()),
+                });
+            }
+        }
+    }
+}
+
+pub fn is_certificate_path(path: &str) -> bool {
+    path.ends_with(".crt") || path.ends_with(".pem") || path.ends_with(".cer")
+}
+
+// Certificates bundled into an image (pinned roots, internal CAs) silently
+// expire. Files that are not PEM or DER certificates, such as PEM private
+// keys, are skipped.
+pub fn check_certificates(layers: &mut [Layer], expiry_window_days: i64) {
+    let now = Utc::now().timestamp();
+    let window = expiry_window_days * 24 * 60 * 60;
+
+    for layer in layers {
+        let mut paths: Vec<&String> = layer.contents.keys().filter(|path| is_certificate_path(path)).collect();
+        paths.sort();
+
+        let mut notices = Vec::new();
+        for path in paths {
+            for (subject, not_after) in certificate_expiry_dates(&layer.contents[path]) {
+                let date = DateTime::<Utc>::from_timestamp(not_after, 0)
+                    .map(|date| date.format("%Y-%m-%d").to_string())
+                    .unwrap_or_else(|| not_after.to_string());
+                let message = if not_after < now {
+                    format!("Certificate {} in /{} expired on {}", subject, path, date)
+                } else if not_after - now < window {
+                    format!("Certificate {} in /{} expires on {}, within {} days", subject, path, date, expiry_window_days)
+                } else {
+                    continue;
+                };
+                notices.push(Notice {
+                    message,
+                    level: "warning".to_string(),
+                    location: None,
+                    rule: Some("certificate-expiry".to_string()),
+                });
+            }
+        }
+        layer.notices.extend(notices);
+    }
+}
+
+// Returns (subject, notAfter as a Unix timestamp) for every certificate in a
+// PEM bundle or DER file.
+fn certificate_expiry_dates(data: &[u8]) -> Vec<(String, i64)> {
+    let expiry = |der: &[u8]| {
+        X509Certificate::from_der(der)
+            .ok()
+            .map(|(_, cert)| (cert.subject().to_string(), cert.validity().not_after.timestamp()))
+    };
+
+    if let Some(certificate) = expiry(data) {
+        return vec![certificate];
+    }
+    Pem::iter_from_buffer(data)
+        .filter_map(|pem| pem.ok())
+        .filter(|pem| pem.label == "CERTIFICATE")
+        .filter_map(|pem| expiry(&pem.contents))
+        .collect()
+}
+
+pub fn normalize_path(path: &str) -> &str {
+    path.trim_start_matches("./").trim_start_matches('/')
+}
+
+// Path relative to the root filesystem under `root_prefix` (the image root if
+// None), or None for paths outside it
+pub fn strip_root<'a>(path: &'a str, root_prefix: Option<&str>) -> Option<&'a str> {
+    let path = normalize_path(path).trim_end_matches('/');
+    match root_prefix {
+        None | Some("") => Some(path),
+        Some(prefix) if path == prefix => Some(""),
+        Some(prefix) => path.strip_prefix(prefix)?.strip_prefix('/'),
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+pub const SEVERITY_LEVELS: &[&str] = &["info", "low", "medium", "high", "critical"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    // Notice levels predate severities, so "warning" and "error" map onto the
+    // middle of the scale.
+    pub fn from_level(level: &str) -> Severity {
+        match level.to_ascii_lowercase().as_str() {
+            "critical" => Severity::Critical,
+            "high" | "error" => Severity::High,
+            "medium" | "warning" | "warn" => Severity::Medium,
+            "low" => Severity::Low,
+            _ => Severity::Info,
+        }
+    }
+}
+
+// The most severe of the findings and of the known vulnerabilities of the
+// packages in the final image, leaving out allowlisted packages
+pub fn highest_severity(layers: &[Layer], allowlist: &Allowlist) -> Option<Severity> {
+    let vulnerabilities = crate::final_packages(layers)
+        .into_iter()
+        .filter(|(_, package)| !allowlist.allows(package))
+        .flat_map(|(_, package)| &package.vulnerabilities)
+        .map(osv::vulnerability_severity);
+    layers
+        .iter()
+        .flat_map(|layer| &layer.notices)
+        .map(|notice| Severity::from_level(&notice.level))
+        .chain(vulnerabilities)
+        .max()
+}
+
+// Whether a finding is at or above the display threshold. The threshold only
+// decides what is printed and annotated: the SBOM keeps every notice, and
+// gating sees them all.
+pub fn is_shown(notice: &Notice, min_severity: Severity) -> bool {
+    Severity::from_level(&notice.level) >= min_severity
+}
+
+// Prints findings as GitHub Actions workflow commands so the runner shows them
+// as annotations on the run and, for findings with a location, inline on the
+// pull request. The runner reads workflow commands from stderr as well as
+// stdout, which keeps stdout free for the SBOM.
+pub fn print_github_annotations(layers: &[Layer], title: &str, min_severity: Severity) {
+    for notice in layers.iter().flat_map(|layer| &layer.notices).filter(|notice| is_shown(notice, min_severity)) {
+        let command = match Severity::from_level(&notice.level) {
+            Severity::Critical | Severity::High => "error",
+            Severity::Medium => "warning",
+            Severity::Low | Severity::Info => "notice",
+        };
+        eprintln!("{}", github_annotation(command, title, &notice.message, notice.location.as_ref()));
+    }
+}
+
+pub fn github_annotation(command: &str, title: &str, message: &str, location: Option<&NoticeLocation>) -> String {
+    let mut properties = Vec::new();
+    if let Some(location) = location {
+        properties.push(format!("file={}", escape_property(&location.file)));
+        properties.push(format!("line={}", location.line));
+    }
+    properties.push(format!("title={}", escape_property(title)));
+    format!("::{} {}::{}", command, properties.join(","), escape_data(message))
+}
+
+fn escape_data(value: &str) -> String {
+    value.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use crate::checks::format_size;
+use crate::registry::Result;
+use crate::{load_sbom, Sbom};
+
+const MAX_LISTED: usize = 20;
+
+struct Candidate {
+    image_name: String,
+    os_guess: String,
+    size: u64,
+    provided: usize,
+    missing: Vec<String>,
+}
+
+// Compares the image against SBOMs of candidate base images (as written by
+// `cbom analyze`) found in `dir`. Packages are matched by name, which is
+// only meaningful within one distribution family, so the report is advisory.
+pub fn compare_base_images(sbom: &Sbom, dir: &Path) -> Result<String> {
+    let packages = os_package_names(sbom);
+    // The bottom layer is usually the base image's root filesystem
+    let current_base_size = sbom.layers.first().map_or(0, layer_size);
+    let current_os = sbom.layers.first().map_or("Unknown", |layer| layer.os_guess.as_str());
+
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("cannot read {}: {}", dir.display(), e))?
+        .collect::<std::io::Result<_>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut candidates = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+        if !matches!(extension, "json" | "yaml" | "yml" | "toml") {
+            continue;
+        }
+        let candidate = load_sbom(&path.display().to_string()).map_err(|e| format!("cannot load {}: {}", path.display(), e))?;
+        if candidate.image_name == sbom.image_name {
+            continue;
+        }
+
+        let base_packages = os_package_names(&candidate);
+        candidates.push(Candidate {
+            os_guess: candidate.layers.first().map_or("Unknown", |layer| layer.os_guess.as_str()).to_string(),
+            size: candidate.layers.iter().map(layer_size).sum(),
+            provided: packages.intersection(&base_packages).count(),
+            missing: packages.difference(&base_packages).cloned().collect(),
+            image_name: candidate.image_name,
+        });
+    }
+    candidates.sort_by_key(|candidate| (candidate.missing.len(), candidate.size));
+
+    let mut report = format!(
+        "Base image comparison (advisory: packages are matched by name only; check the results before switching bases)\n\
+         Current: {} packages, bottom layer {} ({})\n",
+        packages.len(),
+        format_size(current_base_size),
+        current_os
+    );
+    if candidates.is_empty() {
+        report.push_str(&format!("  No candidate SBOMs found in {}\n", dir.display()));
+    }
+    for candidate in &candidates {
+        let savings = match current_base_size.checked_sub(candidate.size) {
+            Some(savings) => format!("saves ~{} before added packages", format_size(savings)),
+            None => format!("{} larger", format_size(candidate.size - current_base_size)),
+        };
+        let os_note = if candidate.os_guess == current_os { String::new() } else { format!(", different OS {}", candidate.os_guess) };
+        report.push_str(&format!(
+            "  {}: provides {} of {} packages, {} to add, base size {} ({}{})\n",
+            candidate.image_name,
+            candidate.provided,
+            packages.len(),
+            candidate.missing.len(),
+            format_size(candidate.size),
+            savings,
+            os_note
+        ));
+        if !candidate.missing.is_empty() {
+            let mut listed = candidate.missing.iter().take(MAX_LISTED).cloned().collect::<Vec<_>>().join(", ");
+            if candidate.missing.len() > MAX_LISTED {
+                listed.push_str(&format!(", and {} more", candidate.missing.len() - MAX_LISTED));
+            }
+            report.push_str(&format!("    to add: {}\n", listed));
+        }
+    }
+    Ok(report)
+}
+
+// Packages from the OS package database; ecosystem packages found by
+// detectors come along with the application, not the base
+fn os_package_names(sbom: &Sbom) -> BTreeSet<String> {
+    sbom.layers
+        .iter()
+        .flat_map(|layer| &layer.packages)
+        .filter(|package| package.is_os_package())
+        .map(|package| package.name.clone())
+        .collect()
+}
+
+fn layer_size(layer: &crate::Layer) -> u64 {
+    layer
+        .files
+        .iter()
+        .filter(|file| file.file_type == "file")
+        .map(|file| file.size * (1 + file.duplicate_paths.len() as u64))
+        .sum()
+}
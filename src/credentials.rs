@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use bollard::auth::DockerCredentials;
+use data_encoding::BASE64;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+// The server address Docker Hub credentials are stored under
+const DOCKER_HUB_SERVER: &str = "https://index.docker.io/v1/";
+const DOCKER_HUB_HOSTS: &[&str] = &["docker.io", "index.docker.io", "registry-1.docker.io"];
+
+// Credentials for pulling from a registry
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // An OAuth refresh token, which `docker login` stores for registries such
+    // as Azure Container Registry; it is exchanged for a registry token
+    pub identity_token: Option<String>,
+    // A bearer token sent to the registry as is
+    pub registry_token: Option<String>,
+}
+
+impl Credentials {
+    pub fn docker_credentials(&self, registry: &str) -> DockerCredentials {
+        DockerCredentials {
+            username: self.username.clone(),
+            password: self.password.clone(),
+            serveraddress: Some(server_address(registry)),
+            identitytoken: self.identity_token.clone(),
+            registrytoken: self.registry_token.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+// Credentials given with --username and --password or --registry-token
+static OVERRIDES: OnceLock<Credentials> = OnceLock::new();
+
+// Makes every later lookup return `credentials` instead of what the Docker
+// config has
+pub fn set_overrides(credentials: Credentials) {
+    let _ = OVERRIDES.set(credentials);
+}
+
+// The credentials for `registry`, a host as in an image reference: those
+// given on the command line, then those the Docker config has for it through
+// a credential helper or its auths. None means pulling anonymously.
+pub fn lookup(registry: &str) -> Option<Credentials> {
+    if let Some(credentials) = OVERRIDES.get() {
+        return Some(credentials.clone());
+    }
+    let config = docker_config()?;
+    let helper = config.cred_helpers.iter().find(|(server, _)| same_registry(server, registry)).map(|(_, helper)| helper).or(config.creds_store.as_ref());
+    if let Some(helper) = helper {
+        if let Some(credentials) = run_helper(helper, registry) {
+            return Some(credentials);
+        }
+    }
+    let entry = config.auths.iter().find(|(server, _)| same_registry(server, registry)).map(|(_, entry)| entry)?;
+    entry.credentials()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DockerConfig {
+    #[serde(default)]
+    auths: BTreeMap<String, AuthEntry>,
+    creds_store: Option<String>,
+    #[serde(default)]
+    cred_helpers: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuthEntry {
+    // base64 of USERNAME:PASSWORD
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    identitytoken: Option<String>,
+    registrytoken: Option<String>,
+}
+
+impl AuthEntry {
+    // Entries kept by a credentials store are empty placeholders
+    fn credentials(&self) -> Option<Credentials> {
+        let decoded = self
+            .auth
+            .as_deref()
+            .filter(|auth| !auth.is_empty())
+            .and_then(|auth| BASE64.decode(auth.as_bytes()).ok())
+            .map(|decoded| String::from_utf8_lossy(&decoded).into_owned());
+        let (username, password) = match decoded.as_deref().and_then(|decoded| decoded.split_once(':')) {
+            Some((username, password)) => (Some(username.to_string()), Some(password.to_string())),
+            None => (self.username.clone(), self.password.clone()),
+        };
+        let credentials = Credentials {
+            username: username.filter(|username| !username.is_empty()),
+            password,
+            identity_token: self.identitytoken.clone().filter(|token| !token.is_empty()),
+            registry_token: self.registrytoken.clone().filter(|token| !token.is_empty()),
+        };
+        (credentials.username.is_some() || credentials.identity_token.is_some() || credentials.registry_token.is_some()).then_some(credentials)
+    }
+}
+
+// What a credential helper's `get` prints
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperCredentials {
+    username: String,
+    secret: String,
+}
+
+// $DOCKER_CONFIG/config.json, or ~/.docker/config.json
+fn docker_config() -> Option<DockerConfig> {
+    let dir = match env::var_os("DOCKER_CONFIG").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".docker"),
+    };
+    let path = dir.join("config.json");
+    let data = fs::read(&path).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(config) => Some(config),
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "ignoring unreadable Docker config");
+            None
+        }
+    }
+}
+
+// Asks docker-credential-HELPER for the registry's credentials. A helper
+// that is missing or has none yields None.
+fn run_helper(helper: &str, registry: &str) -> Option<Credentials> {
+    let program = format!("docker-credential-{}", helper);
+    let child = Command::new(&program).arg("get").stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            warn!(helper = %program, error = %e, "cannot run the Docker credential helper");
+            return None;
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(server_address(registry).as_bytes());
+    }
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        debug!(helper = %program, registry, message = %String::from_utf8_lossy(&output.stdout).trim(), "no credentials from the Docker credential helper");
+        return None;
+    }
+    let found: HelperCredentials = serde_json::from_slice(&output.stdout).ok()?;
+    // Helpers return identity tokens under the username <token>
+    Some(match found.username.as_str() {
+        "<token>" => Credentials { identity_token: Some(found.secret), ..Default::default() },
+        _ => Credentials { username: Some(found.username), password: Some(found.secret), ..Default::default() },
+    })
+}
+
+// The address the Docker CLI stores a registry's credentials under
+fn server_address(registry: &str) -> String {
+    match DOCKER_HUB_HOSTS.contains(&registry) {
+        true => DOCKER_HUB_SERVER.to_string(),
+        false => registry.to_string(),
+    }
+}
+
+// Docker config keys are hosts, optionally with a scheme and a path
+fn same_registry(server: &str, registry: &str) -> bool {
+    let host = server.trim_start_matches("https://").trim_start_matches("http://");
+    let host = host.split('/').next().unwrap_or(host);
+    host == registry || (DOCKER_HUB_HOSTS.contains(&host) && DOCKER_HUB_HOSTS.contains(&registry))
+}
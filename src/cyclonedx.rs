@@ -0,0 +1,186 @@
+use std::collections::HashSet;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::{Package, Sbom, TOOL_VERSION};
+
+const SPEC_VERSION: &str = "1.5";
+
+// A CycloneDX 1.5 JSON BOM: the image is the described component and every
+// package of every layer is one of its components
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Bom {
+    bom_format: &'static str,
+    spec_version: &'static str,
+    serial_number: String,
+    version: u32,
+    metadata: BomMetadata,
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Serialize)]
+struct BomMetadata {
+    timestamp: String,
+    tools: Tools,
+    component: Component,
+}
+
+#[derive(Debug, Serialize)]
+struct Tools {
+    components: Vec<Component>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Component {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(rename = "bom-ref", skip_serializing_if = "Option::is_none")]
+    bom_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    supplier: Option<Supplier>,
+    name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    version: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hashes: Vec<Hash>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    licenses: Vec<LicenseChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    purl: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    properties: Vec<Property>,
+}
+
+#[derive(Debug, Serialize)]
+struct Supplier {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Hash {
+    alg: &'static str,
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum LicenseChoice {
+    License(License),
+    Expression(String),
+}
+
+// A license by SPDX identifier, or by name when it has none
+#[derive(Debug, Serialize)]
+struct License {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Property {
+    name: &'static str,
+    value: String,
+}
+
+pub fn generate_cyclonedx(sbom: &Sbom) -> String {
+    let image = Component {
+        kind: "container",
+        bom_ref: Some(sbom.image_name.clone()),
+        name: sbom.image_name.clone(),
+        version: sbom.image_digest.clone(),
+        properties: sbom.platform.iter().map(|platform| Property { name: "cbom:platform", value: platform.clone() }).collect(),
+        ..Default::default()
+    };
+
+    // A package that several layers carry unchanged is listed once, from the
+    // lowest layer that has it
+    let mut seen = HashSet::new();
+    let mut components = Vec::new();
+    for layer in &sbom.layers {
+        for package in &layer.packages {
+            let bom_ref = bom_ref(package);
+            if !seen.insert(bom_ref.clone()) {
+                continue;
+            }
+            let mut properties = vec![Property { name: "cbom:layer_id", value: layer.layer_id.clone() }];
+            if !package.source.is_empty() {
+                properties.push(Property { name: "cbom:source", value: package.source.clone() });
+            }
+            components.push(Component {
+                kind: "library",
+                bom_ref: Some(bom_ref),
+                supplier: Some(&package.vendor).filter(|vendor| !vendor.is_empty()).map(|vendor| Supplier { name: vendor.clone() }),
+                name: package.name.clone(),
+                version: package.version.clone(),
+                hashes: sha256_hash(&package.checksum).into_iter().collect(),
+                licenses: license_choices(package),
+                purl: package.purl.clone(),
+                properties,
+            });
+        }
+    }
+
+    let bom = Bom {
+        bom_format: "CycloneDX",
+        spec_version: SPEC_VERSION,
+        serial_number: serial_number(sbom),
+        version: 1,
+        metadata: BomMetadata {
+            timestamp: sbom.creation_info.created.clone(),
+            tools: Tools {
+                components: vec![Component {
+                    kind: "application",
+                    name: "cbom".to_string(),
+                    version: TOOL_VERSION.to_string(),
+                    ..Default::default()
+                }],
+            },
+            component: image,
+        },
+        components,
+    };
+    serde_json::to_string_pretty(&bom).unwrap()
+}
+
+// The purl where the package has one, so Dependency-Track can match it;
+// otherwise its name and version
+fn bom_ref(package: &Package) -> String {
+    package.purl.clone().unwrap_or_else(|| format!("{}@{}", package.name, package.version))
+}
+
+// Only checksums that are plain SHA-256 digests; package databases also
+// record other algorithms (apk's Q1 SHA-1, for one)
+fn sha256_hash(checksum: &str) -> Option<Hash> {
+    let digest = checksum.strip_prefix("sha256:").unwrap_or(checksum);
+    (digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit())).then(|| Hash {
+        alg: "SHA-256",
+        content: digest.to_ascii_lowercase(),
+    })
+}
+
+// Compound SPDX expressions go in as an expression and single SPDX licenses by
+// identifier; licenses that are not SPDX expressions keep their declared name
+fn license_choices(package: &Package) -> Vec<LicenseChoice> {
+    match &package.spdx_license {
+        Some(expression) if expression.contains([' ', '(', '+']) => {
+            vec![LicenseChoice::Expression(expression.clone())]
+        }
+        Some(id) => vec![LicenseChoice::License(License { id: Some(id.clone()), name: None })],
+        None if package.license.trim().is_empty() => Vec::new(),
+        None => vec![LicenseChoice::License(License { id: None, name: Some(package.license.trim().to_string()) })],
+    }
+}
+
+// A urn:uuid derived from the document, so the same SBOM always gets the same
+// serial number
+fn serial_number(sbom: &Sbom) -> String {
+    let seed = format!("{}\n{}\n{}\n{}", sbom.namespace, sbom.image_name, sbom.image_digest, sbom.creation_info.created);
+    let mut bytes: [u8; 16] = Sha256::digest(seed.as_bytes())[..16].try_into().unwrap();
+    // Version 4 layout with the RFC 4122 variant
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("urn:uuid:{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
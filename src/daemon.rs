@@ -0,0 +1,143 @@
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use bollard::{Docker, API_DEFAULT_VERSION};
+use tracing::debug;
+use crate::registry::Result;
+
+const TIMEOUT_SECS: u64 = 120;
+
+// The endpoint the first successful connection used, so later connections in
+// the same run skip the search
+static ENDPOINT: OnceLock<String> = OnceLock::new();
+
+// The daemon address given with the global --host option
+static HOST: OnceLock<String> = OnceLock::new();
+
+// Points every later connection at the daemon listening on `host`, which
+// takes precedence over DOCKER_HOST as the docker CLI's -H does
+pub fn set_host(host: &str) {
+    let _ = HOST.set(host.to_string());
+}
+
+// The daemon address given with --host or DOCKER_HOST, with where it came from
+pub fn explicit_host() -> Option<(String, String)> {
+    match HOST.get() {
+        Some(host) => Some((host.clone(), format!("--host {}", host))),
+        None => env::var("DOCKER_HOST").ok().map(|host| (host.clone(), format!("DOCKER_HOST={}", host))),
+    }
+}
+
+// Where the daemon listens when neither --host nor DOCKER_HOST is set, in the order tried:
+// the standard socket, Docker Desktop (macOS, then Linux, then the macOS
+// location before Desktop 4.13) and rootless Docker; on Windows, the engine
+// pipe and Docker Desktop's Linux engine pipe
+pub fn default_endpoints() -> Vec<String> {
+    let mut endpoints = Vec::new();
+    #[cfg(unix)]
+    {
+        endpoints.push("unix:///var/run/docker.sock".to_string());
+        if let Some(home) = env::var_os("HOME").map(PathBuf::from) {
+            for socket in [".docker/run/docker.sock", ".docker/desktop/docker.sock", "Library/Containers/com.docker.docker/Data/docker.raw.sock"] {
+                endpoints.push(format!("unix://{}", home.join(socket).display()));
+            }
+        }
+        if let Some(runtime_dir) = env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+            endpoints.push(format!("unix://{}", runtime_dir.join("docker.sock").display()));
+        }
+    }
+    #[cfg(windows)]
+    {
+        endpoints.push("npipe:////./pipe/docker_engine".to_string());
+        endpoints.push("npipe:////./pipe/dockerDesktopLinuxEngine".to_string());
+    }
+    endpoints
+}
+
+// Connects to the Docker daemon. --host, then DOCKER_HOST, are honored first,
+// as the docker CLI does; otherwise the platform's usual endpoints are tried
+// until one answers, and the error lists each one with why it failed.
+pub async fn connect() -> Result<Docker> {
+    if let Some((host, origin)) = explicit_host() {
+        let docker = connect_to(&host).map_err(|e| format!("cannot connect to the Docker daemon at {}: {}", origin, e))?;
+        if ENDPOINT.get().is_none() {
+            docker.ping().await.map_err(|e| format!("cannot connect to the Docker daemon at {}: {}", origin, e))?;
+            let _ = ENDPOINT.set(host);
+        }
+        return Ok(docker);
+    }
+    if let Some(endpoint) = ENDPOINT.get() {
+        return Ok(Docker::connect_with_local(endpoint, TIMEOUT_SECS, API_DEFAULT_VERSION)?);
+    }
+
+    let mut tried = Vec::new();
+    for endpoint in default_endpoints() {
+        if let Some(path) = endpoint.strip_prefix("unix://") {
+            if !PathBuf::from(path).exists() {
+                tried.push(format!("  {}: no such socket", endpoint));
+                continue;
+            }
+        }
+        let docker = match Docker::connect_with_local(&endpoint, TIMEOUT_SECS, API_DEFAULT_VERSION) {
+            Ok(docker) => docker,
+            Err(e) => {
+                tried.push(format!("  {}: {}", endpoint, e));
+                continue;
+            }
+        };
+        match docker.ping().await {
+            Ok(_) => {
+                debug!(endpoint = %endpoint, "connected to the Docker daemon");
+                let _ = ENDPOINT.set(endpoint);
+                return Ok(docker);
+            }
+            Err(e) => tried.push(format!("  {}: {}", endpoint, e)),
+        }
+    }
+    Err(format!(
+        "cannot connect to the Docker daemon; tried:\n{}\nStart Docker (or Docker Desktop), or pass --host or set DOCKER_HOST to the daemon's address",
+        tried.join("\n")
+    )
+    .into())
+}
+
+// A client for an explicitly given daemon address. TCP connections use TLS
+// when DOCKER_CERT_PATH names a directory holding ca.pem, cert.pem and
+// key.pem, when DOCKER_TLS_VERIFY is set (with certificates in ~/.docker by
+// default) or when the address is https://.
+fn connect_to(host: &str) -> Result<Docker> {
+    #[cfg(unix)]
+    if host.starts_with("unix://") {
+        return Ok(Docker::connect_with_unix(host, TIMEOUT_SECS, API_DEFAULT_VERSION)?);
+    }
+    #[cfg(windows)]
+    if host.starts_with("npipe://") {
+        return Ok(Docker::connect_with_named_pipe(host, TIMEOUT_SECS, API_DEFAULT_VERSION)?);
+    }
+    if !["tcp://", "http://", "https://"].iter().any(|scheme| host.starts_with(scheme)) {
+        return Err(format!("unsupported address {}; use tcp://HOST:PORT or unix:///PATH", host).into());
+    }
+    match tls_cert_path(host) {
+        Some(cert_path) => Ok(Docker::connect_with_ssl(
+            host,
+            &cert_path.join("key.pem"),
+            &cert_path.join("cert.pem"),
+            &cert_path.join("ca.pem"),
+            TIMEOUT_SECS,
+            API_DEFAULT_VERSION,
+        )
+        .map_err(|e| format!("{} (TLS certificates from {})", e, cert_path.display()))?),
+        None => Ok(Docker::connect_with_http(host, TIMEOUT_SECS, API_DEFAULT_VERSION)?),
+    }
+}
+
+fn tls_cert_path(host: &str) -> Option<PathBuf> {
+    if let Some(cert_path) = env::var_os("DOCKER_CERT_PATH").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(cert_path));
+    }
+    let verify = env::var_os("DOCKER_TLS_VERIFY").is_some_and(|value| !value.is_empty());
+    if verify || host.starts_with("https://") {
+        return env::var_os("HOME").map(|home| PathBuf::from(home).join(".docker"));
+    }
+    None
+}
@@ -0,0 +1,638 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::Deserialize;
+use crate::checks::normalize_path;
+use crate::stanza::read_stanzas;
+use crate::{Confidence, Detection, Layer, Notice, Package};
+
+// Every detector, in the order they run. Cached analysis results are only
+// reused with the same list, so add new detectors here.
+pub const DETECTORS: &[&str] = &["os-release", "shell-init", "busybox", "conda", "composer", "install-reason", "web-assets", "systemd", "go", "maven"];
+
+const BUSYBOX_BINARIES: &[&str] = &["bin/busybox", "bin/busybox.static", "usr/bin/busybox", "usr/bin/busybox.static"];
+const APPLET_DIRS: &[&str] = &["bin", "sbin", "usr/bin", "usr/sbin"];
+pub const MAX_LINK_DEPTH: usize = 16;
+
+pub fn is_busybox_path(path: &str) -> bool {
+    BUSYBOX_BINARIES.contains(&normalize_path(path))
+}
+
+// Resolves a link target to a path relative to the image root. Hard link
+// targets are already archive paths; symlink targets are relative to the
+// directory holding the link unless absolute.
+pub fn resolve_link_target(path: &str, target: &str, hard_link: bool) -> String {
+    let path = normalize_path(path);
+    let joined = if hard_link || target.starts_with('/') {
+        target.to_string()
+    } else {
+        match path.rsplit_once('/') {
+            Some((dir, _)) => format!("{}/{}", dir, target),
+            None => target.to_string(),
+        }
+    };
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in joined.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    components.join("/")
+}
+
+// Busybox images often ship without a package database entry for the applets
+// it provides: `ls`, `sh` and friends are just links to the busybox binary.
+// Record those links as commands provided by the busybox package, creating
+// the package if no package database listed it.
+pub fn detect_busybox_applets(layers: &mut [Layer]) {
+    // Later layers override links from earlier ones
+    let mut links: HashMap<&str, &str> = HashMap::new();
+    let mut binary = None;
+    for (index, layer) in layers.iter().enumerate() {
+        links.extend(layer.links.iter().map(|(path, target)| (path.as_str(), target.as_str())));
+        for file in &layer.files {
+            let Some(path) = layer.rooted(&file.path) else {
+                continue;
+            };
+            if is_busybox_path(path) && (file.file_type == "file" || layer.links.contains_key(path)) {
+                binary = Some((index, path.to_string()));
+            }
+        }
+    }
+    let Some((binary_layer, binary_path)) = binary else {
+        return;
+    };
+
+    let resolve = |path: &str| -> String {
+        let mut path = path;
+        for _ in 0..MAX_LINK_DEPTH {
+            match links.get(path) {
+                Some(target) => path = target,
+                None => break,
+            }
+        }
+        path.to_string()
+    };
+    let binary_target = resolve(&binary_path);
+
+    let mut commands = BTreeSet::new();
+    for path in links.keys().copied().chain(std::iter::once(binary_target.as_str())) {
+        let Some((dir, name)) = path.rsplit_once('/') else {
+            continue;
+        };
+        if APPLET_DIRS.contains(&dir) && !is_busybox_path(path) && resolve(path) == binary_target {
+            commands.insert(name.to_string());
+        }
+    }
+    if commands.is_empty() {
+        return;
+    }
+
+    let linkage = layers
+        .iter()
+        .rev()
+        .find_map(|layer| layer.contents.get(&binary_path).or_else(|| layer.contents.get(&binary_target)))
+        .and_then(|elf| elf_is_dynamic(elf))
+        .map(|dynamic| if dynamic { "dynamically linked" } else { "statically linked" })
+        .unwrap_or("of unknown linkage");
+
+    let package_layer = layers
+        .iter()
+        .rposition(|layer| layer.packages.iter().any(is_busybox_package))
+        .unwrap_or(binary_layer);
+    let layer = &mut layers[package_layer];
+    let index = match layer.packages.iter().position(is_busybox_package) {
+        Some(index) => index,
+        None => {
+            layer.packages.push(Package {
+                name: "busybox".to_string(),
+                version: "unknown".to_string(),
+                source: format!("/{}", binary_path),
+                license: "GPL-2.0-only".to_string(),
+                vendor: String::new(),
+                checksum: String::new(),
+                commands: Vec::new(),
+                purl: None,
+                install_reason: None,
+                depends: Vec::new(),
+                provides: Vec::new(),
+                built_at: None,
+                detection: Some(Detection::new(&format!("/{}", binary_path), "busybox", Confidence::Medium)),
+                spdx_license: None,
+                vulnerabilities: Vec::new(),
+            });
+            layer.packages.len() - 1
+        }
+    };
+    layer.notices.push(Notice {
+        message: format!("/{} is {} and provides {} applets", binary_path, linkage, commands.len()),
+        level: "info".to_string(),
+        location: None,
+        rule: None,
+    });
+    layer.packages[index].commands = commands.into_iter().collect();
+}
+
+// Package records conda writes for every package installed into an
+// environment, at <prefix>/conda-meta/<name>-<version>-<build>.json
+#[derive(Debug, Deserialize)]
+struct CondaMeta {
+    name: String,
+    version: String,
+    license: Option<String>,
+    build: Option<String>,
+    channel: Option<String>,
+    subdir: Option<String>,
+    sha256: Option<String>,
+    md5: Option<String>,
+}
+
+pub fn is_conda_meta_path(path: &str) -> bool {
+    let path = normalize_path(path);
+    let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    path.ends_with(".json") && (dir == "conda-meta" || dir.ends_with("/conda-meta"))
+}
+
+// Conda (and mamba) packages never show up in the OS package database. Each
+// environment prefix, such as /opt/conda or /opt/conda/envs/<name>, is
+// recorded as the package source so packages from different environments
+// stay apart.
+pub fn detect_conda_packages(layers: &mut [Layer]) {
+    for layer in layers {
+        let mut paths: Vec<&String> = layer.contents.keys().filter(|path| is_conda_meta_path(path)).collect();
+        paths.sort();
+
+        let mut packages = Vec::new();
+        for path in paths {
+            let meta: CondaMeta = match serde_json::from_slice(&layer.contents[path]) {
+                Ok(meta) => meta,
+                Err(e) => {
+                    layer.notices.push(Notice {
+                        message: format!("Unreadable conda package record /{}: {}", path, e),
+                        level: "info".to_string(),
+                        location: None,
+                        rule: None,
+                    });
+                    continue;
+                }
+            };
+            let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+            let prefix = dir.strip_suffix("conda-meta").unwrap_or(dir).trim_end_matches('/');
+            let channel = meta.channel.as_deref().map(|channel| conda_channel_name(channel, meta.subdir.as_deref()));
+
+            let mut qualifiers = Vec::new();
+            if let Some(build) = &meta.build {
+                qualifiers.push(format!("build={}", build));
+            }
+            if let Some(channel) = &channel {
+                qualifiers.push(format!("channel={}", channel));
+            }
+            if let Some(subdir) = &meta.subdir {
+                qualifiers.push(format!("subdir={}", subdir));
+            }
+            let mut purl = format!("pkg:conda/{}@{}", meta.name, meta.version);
+            if !qualifiers.is_empty() {
+                purl = format!("{}?{}", purl, qualifiers.join("&"));
+            }
+
+            packages.push(Package {
+                name: meta.name,
+                version: meta.version,
+                source: format!("/{}", prefix),
+                license: meta.license.unwrap_or_default(),
+                vendor: channel.unwrap_or_default(),
+                checksum: meta.sha256.or(meta.md5).unwrap_or_default(),
+                commands: Vec::new(),
+                purl: Some(purl),
+                install_reason: None,
+                depends: Vec::new(),
+                provides: Vec::new(),
+                built_at: None,
+                detection: Some(Detection::new(&format!("/{}", path), "conda", Confidence::High)),
+                spdx_license: None,
+                vulnerabilities: Vec::new(),
+            });
+        }
+        layer.packages.extend(packages);
+    }
+}
+
+// https://conda.anaconda.org/conda-forge/linux-64 -> conda-forge
+fn conda_channel_name(channel: &str, subdir: Option<&str>) -> String {
+    let channel = channel.trim_end_matches('/');
+    let channel = subdir.and_then(|subdir| channel.strip_suffix(subdir)).unwrap_or(channel).trim_end_matches('/');
+    channel.rsplit('/').next().unwrap_or(channel).to_string()
+}
+
+const COMPOSER_INSTALLED: &str = "vendor/composer/installed.json";
+
+// Composer 1 writes installed.json as a bare array of packages; Composer 2
+// wraps it in an object
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ComposerInstalled {
+    Packages { packages: Vec<ComposerPackage> },
+    Legacy(Vec<ComposerPackage>),
+}
+
+#[derive(Debug, Deserialize)]
+struct ComposerPackage {
+    name: String,
+    version: String,
+    #[serde(default)]
+    license: Vec<String>,
+    dist: Option<ComposerDist>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComposerDist {
+    shasum: Option<String>,
+}
+
+pub fn is_composer_installed_path(path: &str) -> bool {
+    let path = normalize_path(path);
+    path == COMPOSER_INSTALLED || path.ends_with(&format!("/{}", COMPOSER_INSTALLED))
+}
+
+// PHP dependencies installed by Composer. The application root the vendor
+// directory belongs to is recorded as the package source.
+pub fn detect_composer_packages(layers: &mut [Layer]) {
+    for layer in layers {
+        let mut paths: Vec<&String> = layer.contents.keys().filter(|path| is_composer_installed_path(path)).collect();
+        paths.sort();
+
+        let mut packages = Vec::new();
+        for path in paths {
+            let installed = match serde_json::from_slice(&layer.contents[path]) {
+                Ok(ComposerInstalled::Packages { packages }) | Ok(ComposerInstalled::Legacy(packages)) => packages,
+                Err(e) => {
+                    layer.notices.push(Notice {
+                        message: format!("Unreadable Composer package list /{}: {}", path, e),
+                        level: "info".to_string(),
+                        location: None,
+                        rule: None,
+                    });
+                    continue;
+                }
+            };
+            let root = path.strip_suffix(COMPOSER_INSTALLED).unwrap_or_default().trim_end_matches('/');
+
+            for package in installed {
+                let vendor = package.name.split_once('/').map(|(vendor, _)| vendor.to_string()).unwrap_or_default();
+                packages.push(Package {
+                    purl: Some(format!("pkg:composer/{}@{}", package.name.to_ascii_lowercase(), package.version)),
+                    name: package.name,
+                    version: package.version,
+                    source: format!("/{}", root),
+                    // Multiple licenses mean the package may be used under any of them
+                    license: package.license.join(" OR "),
+                    vendor,
+                    checksum: package.dist.and_then(|dist| dist.shasum).unwrap_or_default(),
+                    commands: Vec::new(),
+                    install_reason: None,
+                    depends: Vec::new(),
+                    provides: Vec::new(),
+                    built_at: None,
+                    detection: Some(Detection::new(&format!("/{}", path), "composer", Confidence::High)),
+                    spdx_license: None,
+                    vulnerabilities: Vec::new(),
+                });
+            }
+        }
+        layer.packages.extend(packages);
+    }
+}
+
+const ASSET_MANIFESTS: &[&str] = &["asset-manifest.json", "stats.json", ".vite/manifest.json"];
+
+pub fn is_asset_manifest_path(path: &str) -> bool {
+    let path = normalize_path(path);
+    !path.contains("node_modules/")
+        && ASSET_MANIFESTS.iter().any(|manifest| path == *manifest || path.ends_with(&format!("/{}", manifest)))
+}
+
+// Frontend builds bundle their dependencies into a few JavaScript files and
+// ship without node_modules, so no package database lists them. Build tools
+// leave manifests (webpack stats.json, asset-manifest.json, Vite's
+// manifest.json) that mention the node_modules paths of bundled modules. This
+// is best-effort: versions are only known when the path records them (pnpm),
+// and packages also installed in a node_modules directory of the image are
+// left to the npm package database.
+pub fn detect_bundled_assets(layers: &mut [Layer]) {
+    let installed: BTreeSet<String> = layers
+        .iter()
+        .flat_map(|layer| layer.files.iter().filter_map(|file| layer.rooted(&file.path)))
+        .filter(|path| path.ends_with("/package.json"))
+        .filter_map(|path| node_module(path.strip_suffix("package.json")?).map(|(name, _)| name))
+        .collect();
+
+    for layer in layers {
+        let mut paths: Vec<&String> = layer.contents.keys().filter(|path| is_asset_manifest_path(path)).collect();
+        paths.sort();
+
+        let mut packages = Vec::new();
+        let mut notices = Vec::new();
+        for path in paths {
+            let manifest: serde_json::Value = match serde_json::from_slice(&layer.contents[path]) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    notices.push(Notice {
+                        message: format!("Unreadable asset manifest /{}: {}", path, e),
+                        level: "info".to_string(),
+                        location: None,
+                        rule: None,
+                    });
+                    continue;
+                }
+            };
+            let mut bundled = BTreeMap::new();
+            collect_node_modules(&manifest, &mut bundled);
+            bundled.retain(|name, _| !installed.contains(name));
+            if bundled.is_empty() {
+                continue;
+            }
+
+            notices.push(Notice {
+                message: format!("Found {} bundled libraries in /{} (best-effort, from the build manifest)", bundled.len(), path),
+                level: "info".to_string(),
+                location: None,
+                rule: None,
+            });
+            for (name, version) in bundled {
+                let purl_name = name.replacen('@', "%40", usize::from(name.starts_with('@')));
+                packages.push(Package {
+                    purl: Some(match &version {
+                        Some(version) => format!("pkg:npm/{}@{}", purl_name, version),
+                        None => format!("pkg:npm/{}", purl_name),
+                    }),
+                    name,
+                    version: version.unwrap_or_else(|| "unknown".to_string()),
+                    source: format!("bundled in /{}", path),
+                    license: String::new(),
+                    vendor: String::new(),
+                    checksum: String::new(),
+                    commands: Vec::new(),
+                    install_reason: None,
+                    depends: Vec::new(),
+                    provides: Vec::new(),
+                    built_at: None,
+                    detection: Some(Detection::new(&format!("/{}", path), "web-assets", Confidence::Low)),
+                    spdx_license: None,
+                    vulnerabilities: Vec::new(),
+                });
+            }
+        }
+        layer.packages.extend(packages);
+        layer.notices.extend(notices);
+    }
+}
+
+// Gathers packages from every node_modules path in the manifest's keys and
+// string values, keeping a version whenever one of the paths has it
+fn collect_node_modules(value: &serde_json::Value, bundled: &mut BTreeMap<String, Option<String>>) {
+    fn add(text: &str, bundled: &mut BTreeMap<String, Option<String>>) {
+        if let Some((name, version)) = node_module(text) {
+            let entry = bundled.entry(name).or_insert(None);
+            if entry.is_none() {
+                *entry = version;
+            }
+        }
+    }
+    match value {
+        serde_json::Value::String(text) => add(text, bundled),
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_node_modules(item, bundled);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for (key, item) in map {
+                add(key, bundled);
+                collect_node_modules(item, bundled);
+            }
+        }
+        _ => {}
+    }
+}
+
+// ./node_modules/@babel/runtime/helpers/esm/extends.js -> (@babel/runtime, None)
+// node_modules/.pnpm/react@18.2.0/node_modules/react/index.js -> (react, Some(18.2.0))
+fn node_module(path: &str) -> Option<(String, Option<String>)> {
+    let (before, after) = path.rsplit_once("node_modules/")?;
+    let mut segments = after.split('/');
+    let first = segments.next()?;
+    let name = match first.starts_with('@') {
+        true => format!("{}/{}", first, segments.next()?),
+        false => first.to_string(),
+    };
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~');
+    if first.starts_with('.') || !name.trim_start_matches('@').replacen('/', "", 1).chars().all(valid) {
+        return None;
+    }
+
+    // pnpm stores packages as .pnpm/<name>@<version>[_<peers>]/node_modules/<name>
+    let version = before
+        .rsplit_once(".pnpm/")
+        .and_then(|(_, store)| store.split('/').next())
+        .and_then(|entry| entry.strip_prefix(&name.replace('/', "+")))
+        .and_then(|rest| rest.strip_prefix('@'))
+        .map(|version| version.split('_').next().unwrap_or(version).to_string());
+    Some((name, version))
+}
+
+const APK_WORLD: &str = "etc/apk/world";
+const APT_EXTENDED_STATES: &str = "var/lib/apt/extended_states";
+
+pub fn is_install_reason_path(path: &str) -> bool {
+    matches!(normalize_path(path), APK_WORLD | APT_EXTENDED_STATES)
+}
+
+// Marks packages as explicitly requested or pulled in as a dependency. apk
+// lists requested packages in /etc/apk/world, with optional version
+// constraints and repository tags; apt marks automatically installed packages
+// in extended_states. The newest copy of each file describes the image.
+pub fn detect_install_reasons(layers: &mut [Layer]) {
+    let latest = |path: &str| layers.iter().rev().find_map(|layer| layer.contents.get(path)).map(|data| String::from_utf8_lossy(data).into_owned());
+
+    let world: Option<BTreeSet<String>> = latest(APK_WORLD).map(|world| {
+        world
+            .split_whitespace()
+            .map(|entry| entry.split(['=', '<', '>', '~', '@']).next().unwrap_or(entry).to_string())
+            .collect()
+    });
+    let auto_installed: Option<BTreeSet<String>> = latest(APT_EXTENDED_STATES).map(|states| {
+        read_stanzas(&states)
+            .iter()
+            .filter(|stanza| stanza.field_ignore_case("Auto-Installed") == Some("1"))
+            .filter_map(|stanza| stanza.field_ignore_case("Package"))
+            .map(str::to_string)
+            .collect()
+    });
+
+    for layer in layers {
+        // Packages found by detectors rather than the package database carry a purl
+        for package in layer.packages.iter_mut().filter(|package| package.purl.is_none()) {
+            let explicit = match (layer.pkg_format.as_str(), &world, &auto_installed) {
+                ("apk", Some(world), _) => world.contains(&package.name),
+                ("deb", _, Some(auto_installed)) => !auto_installed.contains(&package.name),
+                _ => continue,
+            };
+            package.install_reason = Some(if explicit { "explicit" } else { "dependency" }.to_string());
+        }
+    }
+}
+
+const OS_RELEASE_PATHS: &[&str] = &["etc/os-release", "usr/lib/os-release"];
+
+pub fn is_os_release_path(path: &str) -> bool {
+    OS_RELEASE_PATHS.contains(&normalize_path(path))
+}
+
+// The image config only says "linux", and images built `FROM scratch` with a
+// root filesystem copied in have no base image to go by. Read os-release from
+// the merged filesystem instead: the newest copy wins, /etc/os-release is
+// usually a link to /usr/lib/os-release, and the file may live in a different
+// layer than the package database.
+pub fn detect_os_release(layers: &mut [Layer]) {
+    let mut links: HashMap<&str, &str> = HashMap::new();
+    for layer in layers.iter() {
+        links.extend(layer.links.iter().map(|(path, target)| (path.as_str(), target.as_str())));
+    }
+    let os_release = OS_RELEASE_PATHS.iter().find_map(|path| {
+        let mut path = *path;
+        for _ in 0..MAX_LINK_DEPTH {
+            match links.get(path) {
+                Some(target) => path = target,
+                None => break,
+            }
+        }
+        layers.iter().rev().find_map(|layer| layer.contents.get(path)).map(|data| (path.to_string(), String::from_utf8_lossy(data).into_owned()))
+    });
+    let Some((path, os_release)) = os_release else {
+        return;
+    };
+
+    let fields: HashMap<&str, String> = os_release
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim(), value.trim().trim_matches(['"', '\'']).to_string()))
+        .collect();
+    let name = match (fields.get("PRETTY_NAME"), fields.get("NAME"), fields.get("VERSION_ID")) {
+        (Some(pretty_name), _, _) => pretty_name.clone(),
+        (None, Some(name), Some(version)) => format!("{} {}", name, version),
+        (None, Some(name), None) => name.clone(),
+        (None, None, _) => return,
+    };
+
+    for layer in layers.iter_mut() {
+        layer.os_guess = name.clone();
+    }
+    if let Some(layer) = layers.iter_mut().rev().find(|layer| layer.contents.contains_key(&path)) {
+        layer.notices.push(Notice {
+            message: format!("Operating system {} identified from /{}", name, path),
+            level: "info".to_string(),
+            location: None,
+            rule: None,
+        });
+    }
+}
+
+const PASSWD: &str = "etc/passwd";
+const SHELLS: &str = "etc/shells";
+
+// Init systems that reap zombies and forward signals when run as PID 1, by
+// the binaries that identify them
+pub const INIT_SYSTEMS: &[(&str, &[&str])] = &[
+    ("tini", &["sbin/tini", "usr/bin/tini", "usr/local/bin/tini", "bin/tini", "tini", "usr/bin/tini-static"]),
+    ("dumb-init", &["usr/bin/dumb-init", "usr/local/bin/dumb-init", "bin/dumb-init", "dumb-init"]),
+    ("s6", &["command/s6-svscan", "bin/s6-svscan", "usr/bin/s6-svscan", "package/admin/s6-overlay"]),
+    ("systemd", &["lib/systemd/systemd", "usr/lib/systemd/systemd"]),
+];
+
+pub fn is_shell_config_path(path: &str) -> bool {
+    matches!(normalize_path(path), PASSWD | SHELLS)
+}
+
+// The shell an interactive session in the image gets: root's login shell from
+// /etc/passwd, or else the first shell /etc/shells lists. Shells that do not
+// exist in the image (and nologin or false) do not count.
+pub fn detect_shell(layers: &[Layer]) -> Option<String> {
+    let present = merged_paths(layers);
+    let latest = |path: &str| layers.iter().rev().find_map(|layer| layer.contents.get(path)).map(|data| String::from_utf8_lossy(data).into_owned());
+
+    let root_shell = latest(PASSWD).and_then(|passwd| {
+        passwd.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            (fields.len() >= 7 && fields[2] == "0").then(|| fields[6].trim().to_string())
+        })
+    });
+    let listed_shells = latest(SHELLS)
+        .map(|shells| {
+            shells
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    root_shell.into_iter().chain(listed_shells).find(|shell| {
+        let name = shell.rsplit('/').next().unwrap_or(shell);
+        !matches!(name, "nologin" | "false" | "") && present.contains(normalize_path(shell))
+    })
+}
+
+// Names of the init systems installed anywhere in the image
+pub fn detect_init_systems(layers: &[Layer]) -> Vec<String> {
+    let present = merged_paths(layers);
+    INIT_SYSTEMS
+        .iter()
+        .filter(|(_, binaries)| binaries.iter().any(|binary| present.contains(binary)))
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+// Every path in the merged filesystem, files and links alike
+fn merged_paths(layers: &[Layer]) -> BTreeSet<&str> {
+    layers
+        .iter()
+        .flat_map(|layer| layer.files.iter().filter_map(|file| layer.rooted(&file.path)).chain(layer.links.keys().map(String::as_str)))
+        .collect()
+}
+
+fn is_busybox_package(package: &Package) -> bool {
+    matches!(package.name.as_str(), "busybox" | "busybox-static")
+}
+
+// Reports whether an ELF binary requests a program interpreter, i.e. is
+// dynamically linked. Returns None if the data is not a well-formed ELF file.
+fn elf_is_dynamic(data: &[u8]) -> Option<bool> {
+    const PT_INTERP: u32 = 3;
+    if data.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+    let is_64 = *data.get(4)? == 2;
+    let little_endian = *data.get(5)? == 1;
+    let read = |offset: usize, len: usize| -> Option<u64> {
+        let bytes = data.get(offset..offset + len)?;
+        let fold = |value: u64, byte: &u8| (value << 8) | u64::from(*byte);
+        Some(if little_endian { bytes.iter().rev().fold(0, fold) } else { bytes.iter().fold(0, fold) })
+    };
+
+    let (phoff, phentsize, phnum) = if is_64 {
+        (read(0x20, 8)?, read(0x36, 2)?, read(0x38, 2)?)
+    } else {
+        (read(0x1c, 4)?, read(0x2a, 2)?, read(0x2c, 2)?)
+    };
+    for header in 0..phnum {
+        let offset = usize::try_from(phoff + header * phentsize).ok()?;
+        if read(offset, 4)? == u64::from(PT_INTERP) {
+            return Some(true);
+        }
+    }
+    Some(false)
+}
@@ -0,0 +1,171 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use crate::checks::format_size;
+use crate::daemon;
+use crate::{key_fingerprint, SigningKey};
+use crate::osv;
+
+const DEFAULT_DOCKER_SOCKET: &str = "/var/run/docker.sock";
+const MIN_TEMP_SPACE: u64 = 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+struct CheckResult {
+    name: &'static str,
+    status: Status,
+    detail: String,
+}
+
+impl CheckResult {
+    fn new(name: &'static str, status: Status, detail: impl Into<String>) -> CheckResult {
+        CheckResult {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+// Runs the environment checks and prints a report. Returns false if any
+// critical check failed.
+pub async fn run_doctor(key_path: Option<&str>, check_osv: bool) -> bool {
+    let mut results = vec![check_socket(), check_daemon().await, check_temp_space()];
+    if let Some(key_path) = key_path {
+        results.push(check_key(key_path));
+    }
+    if check_osv {
+        results.push(check_osv_reachable().await);
+    }
+
+    for result in &results {
+        let label = match result.status {
+            Status::Pass => "PASS",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        };
+        println!("[{}] {}: {}", label, result.name, result.detail);
+    }
+
+    !results.iter().any(|result| result.status == Status::Fail)
+}
+
+// --host or DOCKER_HOST, or else the first of the usual sockets (Docker
+// Desktop's among them) that exists
+fn docker_socket_path() -> Option<PathBuf> {
+    match daemon::explicit_host() {
+        Some((host, _)) => host.strip_prefix("unix://").map(PathBuf::from),
+        None => {
+            let sockets: Vec<PathBuf> = daemon::default_endpoints().iter().filter_map(|endpoint| endpoint.strip_prefix("unix://")).map(PathBuf::from).collect();
+            Some(sockets.iter().find(|socket| socket.exists()).cloned().unwrap_or_else(|| PathBuf::from(DEFAULT_DOCKER_SOCKET)))
+        }
+    }
+}
+
+fn check_socket() -> CheckResult {
+    let name = "Docker socket";
+    let Some(path) = docker_socket_path() else {
+        return CheckResult::new(name, Status::Pass, "the daemon address is not a unix socket; skipped");
+    };
+    if !path.exists() {
+        return CheckResult::new(
+            name,
+            Status::Fail,
+            format!("{} does not exist; is Docker installed and running? Pass --host or set DOCKER_HOST if it listens elsewhere", path.display()),
+        );
+    }
+
+    #[cfg(unix)]
+    if let Err(e) = std::os::unix::net::UnixStream::connect(&path) {
+        let hint = if e.kind() == std::io::ErrorKind::PermissionDenied {
+            "; add your user to the docker group or run with sufficient privileges"
+        } else {
+            ""
+        };
+        return CheckResult::new(name, Status::Fail, format!("cannot open {}: {}{}", path.display(), e, hint));
+    }
+    CheckResult::new(name, Status::Pass, format!("{} is accessible", path.display()))
+}
+
+async fn check_daemon() -> CheckResult {
+    let name = "Docker daemon";
+    let docker = match daemon::connect().await {
+        Ok(docker) => docker,
+        Err(e) => return CheckResult::new(name, Status::Fail, e.to_string()),
+    };
+    match docker.version().await {
+        Ok(version) => CheckResult::new(
+            name,
+            Status::Pass,
+            format!(
+                "reachable (engine {}, API {})",
+                version.version.unwrap_or_default(),
+                version.api_version.unwrap_or_default()
+            ),
+        ),
+        Err(e) => CheckResult::new(name, Status::Fail, format!("not reachable: {}", e)),
+    }
+}
+
+fn check_temp_space() -> CheckResult {
+    let name = "Temporary space";
+    let temp_dir = env::temp_dir();
+    if let Err(e) = tempfile::tempfile_in(&temp_dir) {
+        return CheckResult::new(name, Status::Fail, format!("{} is not writable: {}", temp_dir.display(), e));
+    }
+    match fs4::available_space(&temp_dir) {
+        Ok(available) if available < MIN_TEMP_SPACE => CheckResult::new(
+            name,
+            Status::Warn,
+            format!(
+                "only {} free in {}; large images need several times their size (set TMPDIR to use another location)",
+                format_size(available),
+                temp_dir.display()
+            ),
+        ),
+        Ok(available) => CheckResult::new(name, Status::Pass, format!("{} free in {}", format_size(available), temp_dir.display())),
+        Err(e) => CheckResult::new(name, Status::Warn, format!("cannot determine free space in {}: {}", temp_dir.display(), e)),
+    }
+}
+
+fn check_key(key_path: &str) -> CheckResult {
+    let name = "Signing key";
+    match fs::read(key_path) {
+        Ok(key_data) => match SigningKey::from_pkcs8(&key_data) {
+            Some(key_pair) => CheckResult::new(
+                name,
+                Status::Pass,
+                format!("{} is a readable {} keypair ({})", key_path, key_pair.algorithm().name(), key_fingerprint(key_pair.public_key())),
+            ),
+            None => CheckResult::new(
+                name,
+                Status::Fail,
+                format!("{} is not a PKCS#8 Ed25519 or ECDSA P-256 keypair; create one with `cbom generate-key`", key_path),
+            ),
+        },
+        Err(e) => CheckResult::new(name, Status::Fail, format!("cannot read {}: {}", key_path, e)),
+    }
+}
+
+// Any HTTP response means the API is reachable; only transport errors fail.
+async fn check_osv_reachable() -> CheckResult {
+    let name = "OSV API";
+    let client = match reqwest::Client::builder().timeout(Duration::from_secs(10)).build() {
+        Ok(client) => client,
+        Err(e) => return CheckResult::new(name, Status::Warn, format!("cannot create HTTP client: {}", e)),
+    };
+    match client.post(format!("{}/query", osv::OSV_API)).body("{}").send().await {
+        Ok(_) => CheckResult::new(name, Status::Pass, format!("{} is reachable", osv::OSV_API)),
+        Err(e) => CheckResult::new(
+            name,
+            Status::Warn,
+            format!("{} is not reachable ({}); vulnerability scanning will not work, check proxy settings", osv::OSV_API, e),
+        ),
+    }
+}
@@ -0,0 +1,331 @@
+use std::collections::BTreeMap;
+use crate::{Confidence, Detection, Package};
+
+// Go binaries carry the module versions they were built from in a build info
+// blob (the .go.buildinfo section), which starts with this magic at a 16-byte
+// aligned offset. Since Go 1.18 the toolchain version and the module list
+// follow the 32-byte header inline as length-prefixed strings; older binaries
+// point at them instead and are not read.
+const BUILDINFO_MAGIC: &[u8] = b"\xff Go buildinf:";
+const BUILDINFO_HEADER_SIZE: usize = 32;
+const BUILDINFO_ALIGNMENT: u64 = 16;
+const FLAG_VERSION_INLINE: u8 = 0x2;
+// Larger module lists are not build info
+const MAX_BUILDINFO_SIZE: usize = 4 * 1024 * 1024;
+// The module list is framed by 16 bytes on each side
+const MODINFO_FRAME_SIZE: usize = 16;
+
+// What `go version -m` prints for a binary
+#[derive(Debug, Default, Clone)]
+pub struct BuildInfo {
+    pub go_version: String,
+    pub main: Option<Module>,
+    pub deps: Vec<Module>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub path: String,
+    pub version: String,
+    // A replace directive's target
+    pub replace: Option<Box<Module>>,
+}
+
+// Looks for build info in a file read a chunk at a time, so executables are
+// searched while they are hashed instead of being held in memory
+#[derive(Debug, Default)]
+pub struct BuildInfoScanner {
+    // The unsearched end of what was read, or everything from a candidate
+    // magic on
+    window: Vec<u8>,
+    // Offset in the file of the window's first byte
+    window_start: u64,
+    // Where in the window a magic starts whose build info is still incomplete
+    candidate: Option<usize>,
+    found: Option<BuildInfo>,
+}
+
+enum Parsed {
+    Complete(BuildInfo),
+    Incomplete,
+    Invalid,
+}
+
+impl BuildInfoScanner {
+    pub fn update(&mut self, data: &[u8]) {
+        if self.found.is_some() {
+            return;
+        }
+        self.window.extend_from_slice(data);
+        let mut from = 0;
+        loop {
+            if let Some(at) = self.candidate {
+                match parse_build_info(&self.window[at..]) {
+                    Parsed::Complete(info) => {
+                        self.found = Some(info);
+                        self.window = Vec::new();
+                        return;
+                    }
+                    Parsed::Incomplete if self.window.len() - at < MAX_BUILDINFO_SIZE => return,
+                    // The magic is also a string in binaries that read build
+                    // info; carry on past it
+                    _ => {
+                        self.candidate = None;
+                        from = at + 1;
+                    }
+                }
+            }
+            match self.find_magic(from) {
+                Some(at) => self.candidate = Some(at),
+                None => break,
+            }
+        }
+        // Keep what a magic split across chunks may start with
+        let searched = self.window.len().saturating_sub(BUILDINFO_MAGIC.len() - 1);
+        self.window.drain(..searched);
+        self.window_start += searched as u64;
+    }
+
+    pub fn finish(self) -> Option<BuildInfo> {
+        self.found
+    }
+
+    fn find_magic(&self, from: usize) -> Option<usize> {
+        let offset = (self.window_start + from as u64).next_multiple_of(BUILDINFO_ALIGNMENT);
+        let first = (offset - self.window_start) as usize;
+        (first..)
+            .step_by(BUILDINFO_ALIGNMENT as usize)
+            .take_while(|at| at + BUILDINFO_MAGIC.len() <= self.window.len())
+            .find(|&at| self.window[at..].starts_with(BUILDINFO_MAGIC))
+    }
+}
+
+fn parse_build_info(data: &[u8]) -> Parsed {
+    if data.len() < BUILDINFO_HEADER_SIZE {
+        return Parsed::Incomplete;
+    }
+    let (pointer_size, flags) = (data[BUILDINFO_MAGIC.len()], data[BUILDINFO_MAGIC.len() + 1]);
+    if !matches!(pointer_size, 4 | 8) || flags & FLAG_VERSION_INLINE == 0 {
+        return Parsed::Invalid;
+    }
+    let rest = &data[BUILDINFO_HEADER_SIZE..];
+    let (version, rest) = match read_string(rest) {
+        Some(Some(read)) => read,
+        Some(None) => return Parsed::Incomplete,
+        None => return Parsed::Invalid,
+    };
+    let (modinfo, _) = match read_string(rest) {
+        Some(Some(read)) => read,
+        Some(None) => return Parsed::Incomplete,
+        None => return Parsed::Invalid,
+    };
+    let Ok(version) = std::str::from_utf8(version) else {
+        return Parsed::Invalid;
+    };
+    if !version.starts_with("go") && !version.starts_with("devel") {
+        return Parsed::Invalid;
+    }
+    // Binaries built outside module mode have no module list
+    let modinfo = match modinfo.len() > 2 * MODINFO_FRAME_SIZE && modinfo[modinfo.len() - MODINFO_FRAME_SIZE - 1] == b'\n' {
+        true => &modinfo[MODINFO_FRAME_SIZE..modinfo.len() - MODINFO_FRAME_SIZE],
+        false => &[][..],
+    };
+    let mut info = parse_modinfo(&String::from_utf8_lossy(modinfo));
+    info.go_version = version.to_string();
+    Parsed::Complete(info)
+}
+
+// A uvarint length and that many bytes: None if malformed, Some(None) if the
+// data ends first
+fn read_string(data: &[u8]) -> Option<Option<(&[u8], &[u8])>> {
+    let mut length: u64 = 0;
+    for (index, byte) in data.iter().enumerate().take(10) {
+        length |= u64::from(byte & 0x7f) << (7 * index);
+        if byte & 0x80 == 0 {
+            let length = usize::try_from(length).ok().filter(|length| *length <= MAX_BUILDINFO_SIZE)?;
+            let rest = &data[index + 1..];
+            return Some((rest.len() >= length).then(|| rest.split_at(length)));
+        }
+    }
+    match data.len() < 10 {
+        true => Some(None),
+        false => None,
+    }
+}
+
+// Tab-separated lines: `mod` for the main module, `dep` for each dependency
+// and `=>` for the replacement of the module before it
+fn parse_modinfo(modinfo: &str) -> BuildInfo {
+    let mut info = BuildInfo::default();
+    for line in modinfo.lines() {
+        let mut fields = line.split('\t');
+        let kind = fields.next().unwrap_or_default();
+        let mut module = || {
+            let path = fields.next().filter(|path| !path.is_empty())?;
+            Some(Module { path: path.to_string(), version: fields.next().unwrap_or_default().to_string(), replace: None })
+        };
+        match kind {
+            "mod" => info.main = module(),
+            "dep" => info.deps.extend(module()),
+            "=>" => {
+                let replaced = info.deps.last_mut().or(info.main.as_mut());
+                if let (Some(replaced), Some(replacement)) = (replaced, module()) {
+                    replaced.replace = Some(Box::new(replacement));
+                }
+            }
+            _ => {}
+        }
+    }
+    info
+}
+
+// Lists the main module, the dependencies and the standard library of every
+// Go binary in a layer, keyed by the binary's path. Replaced modules are
+// listed as their replacement, unless it is a local directory, which has no
+// version.
+pub fn read_go_packages(binaries: &BTreeMap<String, BuildInfo>) -> Vec<Package> {
+    let mut packages = Vec::new();
+    for (path, info) in binaries {
+        let mut modules: Vec<(&str, &str)> = info
+            .main
+            .iter()
+            .chain(&info.deps)
+            .map(|module| match &module.replace {
+                Some(replacement) if !replacement.version.is_empty() => (replacement.path.as_str(), replacement.version.as_str()),
+                _ => (module.path.as_str(), module.version.as_str()),
+            })
+            .collect();
+        // Release toolchains are named like go1.22.3, with build experiments
+        // after a space
+        if let Some(release) = info.go_version.split_whitespace().next().and_then(|version| version.strip_prefix("go")) {
+            modules.push(("stdlib", release));
+        }
+        for (name, version) in modules {
+            let mut package = Package {
+                name: name.to_string(),
+                version: version.to_string(),
+                source: format!("/{}", path),
+                license: String::new(),
+                vendor: String::new(),
+                checksum: String::new(),
+                commands: Vec::new(),
+                purl: None,
+                install_reason: None,
+                depends: Vec::new(),
+                provides: Vec::new(),
+                built_at: None,
+                detection: Some(Detection::new(&format!("/{}", path), "go", Confidence::High)),
+                spdx_license: None,
+                vulnerabilities: Vec::new(),
+            };
+            let purl = package.purl("go");
+            // Modules built from a checkout are versioned (devel)
+            package.purl = Some(match version {
+                "" | "(devel)" => purl.rsplit_once('@').map_or(purl.clone(), |(unversioned, _)| unversioned.to_string()),
+                _ => purl,
+            });
+            packages.push(package);
+        }
+    }
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A build info blob as Go 1.18 and later write it
+    fn build_info(go_version: &str, modinfo: &str) -> Vec<u8> {
+        let mut blob = BUILDINFO_MAGIC.to_vec();
+        blob.extend_from_slice(&[8, FLAG_VERSION_INLINE]);
+        blob.resize(BUILDINFO_HEADER_SIZE, 0);
+        let framed = [&[0xaa; MODINFO_FRAME_SIZE][..], modinfo.as_bytes(), &[0xbb; MODINFO_FRAME_SIZE]].concat();
+        for string in [go_version.as_bytes(), &framed] {
+            let mut length = string.len();
+            while length >= 0x80 {
+                blob.push(length as u8 | 0x80);
+                length >>= 7;
+            }
+            blob.push(length as u8);
+            blob.extend_from_slice(string);
+        }
+        blob
+    }
+
+    const MODINFO: &str = "path\texample.com/app\nmod\texample.com/app\t(devel)\t\ndep\tgithub.com/spf13/cobra\tv1.8.0\th1:7aJaZx1B85qltLMc546zn58BxxfZdR/W22ej9CFoEf8=\ndep\tgolang.org/x/sys\tv0.15.0\n=>\tgolang.org/x/sys\tv0.16.0\th1:abc=\n";
+
+    // An ELF-looking prefix and the blob at a 16-byte aligned offset after
+    // it, with a misaligned decoy of the magic in front
+    fn binary(blob: &[u8]) -> Vec<u8> {
+        let mut data = b"\x7fELF".to_vec();
+        data.extend_from_slice(BUILDINFO_MAGIC);
+        data.resize(4096, 0);
+        data.extend_from_slice(blob);
+        data.extend_from_slice(&[0; 100]);
+        data
+    }
+
+    fn scan(data: &[u8], chunk_size: usize) -> Option<BuildInfo> {
+        let mut scanner = BuildInfoScanner::default();
+        for chunk in data.chunks(chunk_size) {
+            scanner.update(chunk);
+        }
+        scanner.finish()
+    }
+
+    #[test]
+    fn finds_build_info_with_the_magic_split_across_chunks() {
+        let data = binary(&build_info("go1.22.3", MODINFO));
+        // Chunks of 4100 bytes end 4 bytes into the magic at 4096
+        for chunk_size in [4100, 4096 + BUILDINFO_MAGIC.len() - 1, 7, 1] {
+            let info = scan(&data, chunk_size).unwrap_or_else(|| panic!("not found in chunks of {}", chunk_size));
+            assert_eq!(info.go_version, "go1.22.3");
+            assert_eq!(info.main.as_ref().unwrap().path, "example.com/app");
+            assert_eq!(info.main.as_ref().unwrap().version, "(devel)");
+            assert_eq!(info.deps.len(), 2);
+            assert_eq!(info.deps[0].path, "github.com/spf13/cobra");
+            assert_eq!(info.deps[0].version, "v1.8.0");
+            let replace = info.deps[1].replace.as_ref().unwrap();
+            assert_eq!((replace.path.as_str(), replace.version.as_str()), ("golang.org/x/sys", "v0.16.0"));
+        }
+    }
+
+    #[test]
+    fn finds_build_info_whose_module_list_spans_many_chunks() {
+        let deps: String = (0..2000).map(|index| format!("dep\tgithub.com/lib{}/pkg\tv1.0.{}\n", index, index)).collect();
+        let data = binary(&build_info("go1.21.0", &format!("mod\texample.com/app\tv1.0.0\t\n{}", deps)));
+        let info = scan(&data, 4099).unwrap();
+        assert_eq!(info.deps.len(), 2000);
+        assert_eq!(info.deps[1999].path, "github.com/lib1999/pkg");
+    }
+
+    #[test]
+    fn skips_magics_that_are_not_build_info() {
+        let mut blob = BUILDINFO_MAGIC.to_vec();
+        blob.extend_from_slice(&[8, 0]);
+        blob.resize(BUILDINFO_ALIGNMENT as usize * 4, 0);
+        blob.extend_from_slice(&build_info("go1.22.3", MODINFO));
+        assert_eq!(scan(&binary(&blob), 64).unwrap().go_version, "go1.22.3");
+        assert!(scan(&binary(&[]), 64).is_none());
+    }
+
+    #[test]
+    fn lists_replacements_and_the_standard_library() {
+        let info = scan(&binary(&build_info("go1.22.3 X:loopvar", MODINFO)), 4096).unwrap();
+        let packages = read_go_packages(&BTreeMap::from([("usr/bin/app".to_string(), info)]));
+        let listed: Vec<(&str, &str, &str)> = packages
+            .iter()
+            .map(|package| (package.name.as_str(), package.version.as_str(), package.purl.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            listed,
+            [
+                ("example.com/app", "(devel)", "pkg:golang/example.com/app"),
+                ("github.com/spf13/cobra", "v1.8.0", "pkg:golang/github.com/spf13/cobra@v1.8.0"),
+                ("golang.org/x/sys", "v0.16.0", "pkg:golang/golang.org/x/sys@v0.16.0"),
+                ("stdlib", "1.22.3", "pkg:golang/stdlib@1.22.3"),
+            ]
+        );
+        assert_eq!(packages[0].source, "/usr/bin/app");
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read, Seek};
+use zip::ZipArchive;
+use crate::checks::normalize_path;
+use crate::purl::maven_purl;
+use crate::{Confidence, Detection, Package};
+
+const ARCHIVE_EXTENSIONS: &[&str] = &[".jar", ".war", ".ear"];
+const MANIFEST: &str = "META-INF/MANIFEST.MF";
+// Archives nested deeper than this inside fat JARs are not opened
+const MAX_NESTING: usize = 3;
+// Larger nested archives and metadata files are left unread. Nested archives
+// are read into memory, one per level of nesting, so libraries fit but little
+// else does.
+const MAX_NESTED_ARCHIVE_SIZE: u64 = 32 * 1024 * 1024;
+const MAX_METADATA_SIZE: u64 = 1024 * 1024;
+
+// A library or application recorded in a Java archive
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    // The nested archive it was found in, as !/BOOT-INF/lib/name.jar after
+    // the outer archive's path; empty for the archive itself
+    pub nested_in: String,
+    // The file it was read from in that archive
+    pub metadata_file: String,
+    pub group_id: Option<String>,
+    pub artifact_id: String,
+    pub version: String,
+    pub vendor: String,
+    // Read from pom.properties rather than the manifest
+    pub from_pom: bool,
+}
+
+// JARs and the WARs and EARs application servers deploy
+pub fn is_java_archive_path(path: &str) -> bool {
+    let path = normalize_path(path);
+    ARCHIVE_EXTENSIONS.iter().any(|extension| path.ends_with(extension))
+}
+
+// The artifacts an archive records: every pom.properties Maven packed into
+// it, which a shaded JAR has one of for each library it absorbed, or else its
+// manifest's Implementation-Title and Implementation-Version. Archives nested
+// in fat JARs, WARs and EARs are read the same way. Files that are not zip
+// archives have none.
+pub fn read_archive<R: Read + Seek>(reader: R, file_name: &str) -> Vec<Artifact> {
+    let mut artifacts = Vec::new();
+    read_nested(reader, file_name, "", 0, &mut artifacts);
+    artifacts
+}
+
+fn read_nested<R: Read + Seek>(reader: R, file_name: &str, nested_in: &str, depth: usize, artifacts: &mut Vec<Artifact>) {
+    let Ok(mut archive) = ZipArchive::new(reader) else {
+        return;
+    };
+    let names: Vec<String> = archive.file_names().filter_map(|name| name.ok()).map(|name| name.into_owned()).collect();
+
+    let mut found = false;
+    for name in names.iter().filter(|name| is_pom_properties(name)) {
+        let Some(properties) = read_text(&mut archive, name) else {
+            continue;
+        };
+        let properties = parse_properties(&properties);
+        let (Some(artifact_id), Some(version)) = (properties.get("artifactId"), properties.get("version")) else {
+            continue;
+        };
+        artifacts.push(Artifact {
+            nested_in: nested_in.to_string(),
+            metadata_file: name.clone(),
+            group_id: properties.get("groupId").cloned(),
+            artifact_id: artifact_id.clone(),
+            version: version.clone(),
+            vendor: String::new(),
+            from_pom: true,
+        });
+        found = true;
+    }
+    if !found {
+        artifacts.extend(read_text(&mut archive, MANIFEST).and_then(|manifest| manifest_artifact(&manifest, file_name, nested_in)));
+    }
+
+    if depth >= MAX_NESTING {
+        return;
+    }
+    for name in names.iter().filter(|name| is_java_archive_path(name)) {
+        let Ok(entry) = archive.by_name(name) else {
+            continue;
+        };
+        if entry.size() > MAX_NESTED_ARCHIVE_SIZE {
+            continue;
+        }
+        let mut nested = Vec::new();
+        if entry.take(MAX_NESTED_ARCHIVE_SIZE).read_to_end(&mut nested).is_err() {
+            continue;
+        }
+        let nested_name = name.rsplit('/').next().unwrap_or(name);
+        read_nested(Cursor::new(nested), nested_name, &format!("{}!/{}", nested_in, name), depth + 1, artifacts);
+    }
+}
+
+// META-INF/maven/<groupId>/<artifactId>/pom.properties
+fn is_pom_properties(name: &str) -> bool {
+    name.strip_prefix("META-INF/maven/")
+        .and_then(|rest| rest.strip_suffix("/pom.properties"))
+        .is_some_and(|coordinates| coordinates.split('/').count() == 2)
+}
+
+fn read_text<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Option<String> {
+    let entry = archive.by_name(name).ok()?;
+    let mut data = Vec::new();
+    entry.take(MAX_METADATA_SIZE).read_to_end(&mut data).ok()?;
+    Some(String::from_utf8_lossy(&data).into_owned())
+}
+
+// key=value lines, with # and ! comments
+fn parse_properties(properties: &str) -> BTreeMap<String, String> {
+    properties
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with(['#', '!']))
+        .filter_map(|line| line.split_once(['=', ':']))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+}
+
+// The main section of a manifest: "Name: value" lines, continued on lines
+// starting with a space, up to the first blank line
+fn parse_manifest(manifest: &str) -> BTreeMap<String, String> {
+    let mut attributes: Vec<(String, String)> = Vec::new();
+    for line in manifest.lines().map(|line| line.trim_end_matches('\r')) {
+        if line.is_empty() {
+            break;
+        }
+        match (line.strip_prefix(' '), attributes.last_mut()) {
+            (Some(continued), Some((_, value))) => value.push_str(continued),
+            _ => {
+                if let Some((name, value)) = line.split_once(':') {
+                    attributes.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+        }
+    }
+    attributes.into_iter().filter(|(_, value)| !value.is_empty()).collect()
+}
+
+// Archives without a title are named after their file, less the version
+fn manifest_artifact(manifest: &str, file_name: &str, nested_in: &str) -> Option<Artifact> {
+    let attributes = parse_manifest(manifest);
+    let version = attributes.get("Implementation-Version")?;
+    let artifact_id = match attributes.get("Implementation-Title") {
+        Some(title) => title.clone(),
+        None => {
+            let stem = file_name.rsplit_once('.').map_or(file_name, |(stem, _)| stem);
+            stem.strip_suffix(version.as_str()).and_then(|stem| stem.strip_suffix('-')).unwrap_or(stem).to_string()
+        }
+    };
+    Some(Artifact {
+        nested_in: nested_in.to_string(),
+        metadata_file: MANIFEST.to_string(),
+        group_id: attributes.get("Implementation-Vendor-Id").cloned(),
+        artifact_id,
+        version: version.clone(),
+        vendor: attributes.get("Implementation-Vendor").cloned().unwrap_or_default(),
+        from_pom: false,
+    })
+}
+
+// Lists the artifacts of every Java archive in a layer, keyed by the
+// archive's path, which is recorded as the package source along with the
+// nested archive they were found in
+pub fn read_maven_packages(archives: &BTreeMap<String, Vec<Artifact>>) -> Vec<Package> {
+    let mut packages = Vec::new();
+    for (path, artifacts) in archives {
+        for artifact in artifacts {
+            let source = format!("/{}{}", path, artifact.nested_in);
+            let confidence = match artifact.from_pom {
+                true => Confidence::High,
+                false => Confidence::Medium,
+            };
+            packages.push(Package {
+                name: artifact.artifact_id.clone(),
+                version: artifact.version.clone(),
+                license: String::new(),
+                vendor: artifact.vendor.clone(),
+                checksum: String::new(),
+                commands: Vec::new(),
+                purl: Some(maven_purl(artifact.group_id.as_deref(), &artifact.artifact_id, &artifact.version)),
+                install_reason: None,
+                depends: Vec::new(),
+                provides: Vec::new(),
+                built_at: None,
+                detection: Some(Detection::new(&format!("{}!/{}", source, artifact.metadata_file), "maven", confidence)),
+                spdx_license: None,
+                vulnerabilities: Vec::new(),
+                source,
+            });
+        }
+    }
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognizes_pom_properties_by_coordinates() {
+        assert!(is_pom_properties("META-INF/maven/org.slf4j/slf4j-api/pom.properties"));
+        assert!(!is_pom_properties("META-INF/maven/slf4j-api/pom.properties"));
+        assert!(!is_pom_properties("META-INF/maven/org/slf4j/slf4j-api/pom.properties"));
+        assert!(!is_pom_properties("BOOT-INF/classes/META-INF/maven/org.slf4j/slf4j-api/pom.properties"));
+        assert!(!is_pom_properties("META-INF/maven/org.slf4j/slf4j-api/pom.xml"));
+    }
+
+    #[test]
+    fn parses_the_main_section_of_a_manifest() {
+        let manifest = "Manifest-Version: 1.0\r\nImplementation-Title: spring-boot\r\nImplementation-Vendor: VMware, Inc.\r\nClass-Path: lib/a.jar lib/b.ja\r\n r lib/c.jar\r\nEmpty:\r\n\r\nName: org/example/\r\nImplementation-Version: 9.9\r\n";
+        let attributes = parse_manifest(manifest);
+        assert_eq!(attributes.get("Implementation-Title").map(String::as_str), Some("spring-boot"));
+        assert_eq!(attributes.get("Implementation-Vendor").map(String::as_str), Some("VMware, Inc."));
+        assert_eq!(attributes.get("Class-Path").map(String::as_str), Some("lib/a.jar lib/b.jar lib/c.jar"));
+        assert!(!attributes.contains_key("Empty"));
+        // Per-entry sections after the first blank line are not read
+        assert!(!attributes.contains_key("Implementation-Version"));
+        assert!(!attributes.contains_key("Name"));
+    }
+
+    fn zip(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in entries {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            std::io::Write::write_all(&mut writer, data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn reads_archives_nested_in_a_seekable_reader() {
+        let inner = zip(&[("META-INF/maven/org.slf4j/slf4j-api/pom.properties", b"groupId=org.slf4j\nartifactId=slf4j-api\nversion=2.0.13\n")]);
+        let outer = zip(&[(MANIFEST, b"Implementation-Title: app\nImplementation-Version: 1.0\n"), ("BOOT-INF/lib/slf4j-api-2.0.13.jar", &inner)]);
+        let artifacts = read_archive(Cursor::new(outer), "app.jar");
+        let found: Vec<_> = artifacts.iter().map(|artifact| (artifact.nested_in.as_str(), artifact.artifact_id.as_str())).collect();
+        assert_eq!(found, [("", "app"), ("!/BOOT-INF/lib/slf4j-api-2.0.13.jar", "slf4j-api")]);
+        assert!(read_archive(Cursor::new(b"not a zip"), "broken.jar").is_empty());
+    }
+
+    #[test]
+    fn names_untitled_archives_after_their_file() {
+        let artifact = manifest_artifact("Implementation-Version: 2.0.13\n", "slf4j-api-2.0.13.jar", "").unwrap();
+        assert_eq!(artifact.artifact_id, "slf4j-api");
+        assert_eq!(artifact.version, "2.0.13");
+        assert!(manifest_artifact("Implementation-Title: app\n", "app.jar", "").is_none());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use prettytable::{row, Table};
+use serde::Serialize;
+use crate::checks::format_size;
+use crate::Layer;
+
+// What one layer did to the filesystem below it: the paths it added, changed
+// or deleted, and the packages it installed or upgraded
+#[derive(Debug, Serialize)]
+pub struct LayerDiff {
+    pub image: String,
+    pub platform: Option<String>,
+    // Position in the image, starting at 1 for the base layer
+    pub index: usize,
+    pub layer_id: String,
+    pub created_by: Option<String>,
+    pub changes: Vec<PathChange>,
+    pub packages: Vec<PackageChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PathChange {
+    // added, modified or deleted
+    pub change: &'static str,
+    pub path: String,
+    pub file_type: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageChange {
+    pub name: String,
+    pub version: String,
+    // The version below this layer, for upgrades and downgrades
+    pub previous_version: Option<String>,
+}
+
+// How a whiteout entry of an overlay layer deletes paths from the layers below
+pub enum Whiteout {
+    // .wh.<name>: the path and everything under it
+    Path(String),
+    // .wh..wh..opq: everything under the directory
+    Opaque(String),
+}
+
+pub fn whiteout(path: &str) -> Option<Whiteout> {
+    let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let join = |name: &str| if dir.is_empty() { name.to_string() } else { format!("{}/{}", dir, name) };
+    match name.strip_prefix(".wh.")? {
+        ".wh..opq" => Some(Whiteout::Opaque(dir.to_string())),
+        deleted => Some(Whiteout::Path(join(deleted))),
+    }
+}
+
+pub fn is_under(path: &str, dir: &str) -> bool {
+    dir.is_empty() || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[derive(Clone, PartialEq)]
+struct Entry {
+    file_type: String,
+    size: u64,
+    checksum: String,
+    link: Option<String>,
+}
+
+impl LayerDiff {
+    // `index` is 1-based; the caller checks it is in range
+    pub fn new(image: &str, platform: Option<&str>, layers: &[Layer], layer_commands: &[Option<String>], index: usize) -> LayerDiff {
+        let mut below: BTreeMap<String, Entry> = BTreeMap::new();
+        for layer in &layers[..index - 1] {
+            apply_layer(&mut below, layer);
+        }
+        let layer = &layers[index - 1];
+        let mut above = below.clone();
+        apply_layer(&mut above, layer);
+
+        let mut changes = Vec::new();
+        for (path, entry) in &above {
+            let change = match below.get(path) {
+                None => "added",
+                Some(previous) if previous != entry => "modified",
+                Some(_) => continue,
+            };
+            changes.push(PathChange { change, path: format!("/{}", path), file_type: entry.file_type.clone(), size: entry.size });
+        }
+        for (path, entry) in below.iter().filter(|(path, _)| !above.contains_key(*path)) {
+            changes.push(PathChange { change: "deleted", path: format!("/{}", path), file_type: entry.file_type.clone(), size: entry.size });
+        }
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+        // Each layer that touches a package database carries all of it, so the
+        // versions below are those of the newest lower layer listing a package
+        let mut previous: BTreeMap<&str, &str> = BTreeMap::new();
+        for package in layers[..index - 1].iter().flat_map(|layer| &layer.packages) {
+            previous.insert(&package.name, &package.version);
+        }
+        let packages = layer
+            .packages
+            .iter()
+            .filter(|package| previous.get(package.name.as_str()) != Some(&package.version.as_str()))
+            .map(|package| PackageChange {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                previous_version: previous.get(package.name.as_str()).map(|version| version.to_string()),
+            })
+            .collect();
+
+        LayerDiff {
+            image: image.to_string(),
+            platform: platform.map(str::to_string),
+            index,
+            layer_id: layer.layer_id.clone(),
+            created_by: layer_commands.get(index - 1).cloned().flatten(),
+            changes,
+            packages,
+        }
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = Table::new();
+        table.add_row(row!["Change", "Path", "Type", "Size"]);
+        for change in &self.changes {
+            table.add_row(row![change.change, change.path, change.file_type, format_size(change.size)]);
+        }
+        let count = |kind: &str| self.changes.iter().filter(|change| change.change == kind).count();
+        let platform = self.platform.as_deref().map(|platform| format!(" ({})", platform)).unwrap_or_default();
+        let mut report = format!(
+            "Layer {} ({}) of {}{}: {} added, {} modified, {} deleted\nCreated by: {}\n{}",
+            self.index,
+            self.layer_id,
+            self.image,
+            platform,
+            count("added"),
+            count("modified"),
+            count("deleted"),
+            self.created_by.as_deref().unwrap_or("unknown"),
+            table
+        );
+        if !self.packages.is_empty() {
+            let mut packages = Table::new();
+            packages.add_row(row!["Package", "Version", "Previous Version"]);
+            for package in &self.packages {
+                packages.add_row(row![package.name, package.version, package.previous_version.as_deref().unwrap_or("new")]);
+            }
+            report.push_str(&packages.to_string());
+        }
+        report
+    }
+}
+
+// Merges a layer onto the filesystem below it: whiteouts delete first, then
+// the layer's entries replace whatever was at their paths
+fn apply_layer(filesystem: &mut BTreeMap<String, Entry>, layer: &Layer) {
+    let paths: Vec<(&str, &crate::FileMetadata)> = layer.files.iter().filter_map(|file| Some((layer.rooted(&file.path)?, file))).collect();
+    for (path, _) in &paths {
+        match whiteout(path) {
+            Some(Whiteout::Path(deleted)) => {
+                filesystem.remove(&deleted);
+                remove_under(filesystem, &deleted);
+            }
+            Some(Whiteout::Opaque(dir)) => remove_under(filesystem, &dir),
+            None => {}
+        }
+    }
+    for (path, file) in paths {
+        if path.is_empty() || whiteout(path).is_some() {
+            continue;
+        }
+        let link = layer.links.get(path).cloned();
+        // Links are recorded as "dir" entries
+        let file_type = match &link {
+            Some(_) => "link".to_string(),
+            None => file.file_type.clone(),
+        };
+        // A file or link replacing a directory hides everything under it
+        if file_type != "dir" {
+            remove_under(filesystem, path);
+        }
+        filesystem.insert(
+            path.to_string(),
+            Entry {
+                file_type,
+                size: file.size,
+                checksum: file.checksum.clone(),
+                link,
+            },
+        );
+    }
+}
+
+fn remove_under(filesystem: &mut BTreeMap<String, Entry>, dir: &str) {
+    if dir.is_empty() {
+        filesystem.clear();
+        return;
+    }
+    // Paths under dir sort between "dir/" and "dir0", '0' following '/'
+    let under: Vec<String> = filesystem.range(format!("{}/", dir)..format!("{}0", dir)).map(|(path, _)| path.clone()).collect();
+    for path in under {
+        filesystem.remove(&path);
+    }
+}
@@ -0,0 +1,4157 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, read_dir};
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, Write, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use clap::{Arg, Command};
+use bollard::image::{CreateImageOptions, BuildImageOptions};
+use bollard::models::{BuildInfo, HealthConfig, ImageInspect};
+use futures_util::stream::{self, StreamExt};
+use tokio::runtime::Runtime;
+use serde::{Serialize, Deserialize};
+use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, UnparsedPublicKey, VerificationAlgorithm, ECDSA_P256_SHA256_ASN1, ECDSA_P256_SHA256_ASN1_SIGNING, ED25519};
+use ring::rand::SystemRandom;
+use data_encoding::{BASE64, BASE64_NOPAD, HEXLOWER};
+use dockerfile_parser::{Dockerfile, Instruction, ShellOrExecExpr, Stage, StageParent};
+use tar::Builder;
+use hyper::body::Bytes;
+use tar::Archive;
+use sha2::{Sha256, Digest};
+use tempfile::tempdir;
+use prettytable::{Table, row}; // Removed unused `cell` import
+use indicatif::{ProgressBar, ProgressStyle};
+use pathfilter::PathFilter;
+use progress::{Progress, ProgressReporter};
+use tracing::{debug, error, info, info_span, instrument, warn, Instrument};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
+
+mod archive;
+mod attestation;
+#[doc(hidden)]
+pub mod bench;
+mod cache;
+mod checkpoint;
+mod checks;
+mod compare;
+mod credentials;
+mod cyclonedx;
+mod daemon;
+mod detectors;
+mod doctor;
+mod golang;
+mod java;
+mod layerdiff;
+mod licenses;
+mod npm;
+mod oci;
+mod osv;
+mod pathfilter;
+mod pip;
+#[cfg(unix)]
+mod podman;
+mod progress;
+mod provenance;
+mod purl;
+mod registry;
+mod rpmdb;
+mod sarif;
+mod sbomdiff;
+mod size;
+mod source;
+mod spdx;
+mod sshsig;
+mod stanza;
+mod systemd;
+mod validate;
+
+// Version of the SBOM JSON layout. Bump it whenever a field is added, removed or
+// changes meaning, and list new fields in SCHEMA_FIELDS so that older shapes can
+// still be emitted with --schema-version.
+const SCHEMA_VERSION: u32 = 25;
+
+// Fields introduced after schema version 1, as (JSON path, version introduced).
+// Path segments address object keys; arrays along the path are traversed.
+const SCHEMA_FIELDS: &[(&str, u32)] = &[
+    ("schema_version", 2),
+    ("platform", 3),
+    ("layers.files.duplicate_paths", 4),
+    ("layers.packages.commands", 5),
+    ("metadata.healthcheck", 6),
+    ("layers.packages.purl", 7),
+    ("signature_key_id", 8),
+    ("layers.packages.install_reason", 9),
+    ("dockerfile_analysis.copies", 10),
+    ("layers.notices.location", 11),
+    ("dockerfile_analysis.copies.line", 11),
+    ("layers.packages.depends", 12),
+    ("layers.packages.provides", 12),
+    ("layers.packages.built_at", 13),
+    ("provenance", 14),
+    ("metadata.commit", 15),
+    ("metadata.built_at", 15),
+    ("layers.notices.rule", 16),
+    ("metadata.shell", 17),
+    ("metadata.init_systems", 17),
+    ("license_evidence", 18),
+    ("services", 19),
+    ("layers.packages.detection", 20),
+    ("dockerfile_analysis.packages.detection", 20),
+    ("layers.packages.spdx_license", 21),
+    ("dockerfile_analysis.packages.spdx_license", 21),
+    ("layers.packages.vulnerabilities", 22),
+    ("dockerfile_analysis.packages.vulnerabilities", 22),
+    ("dockerfile_analysis.stages", 23),
+    ("signature_algorithm", 24),
+    ("layers.created_by", 25),
+];
+
+// Version of the cbom binary, recorded in every SBOM it writes
+const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Commit the binary was built from, set by build.rs when built from a git checkout
+fn build_commit() -> Option<String> {
+    Some(env!("CBOM_GIT_COMMIT")).filter(|commit| !commit.is_empty()).map(str::to_string)
+}
+
+// Stored in the analysis caches: bump cache::CACHE_FORMAT when its fields change
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Layer {
+    layer_id: String,
+    created: String,
+    os_guess: String,
+    pkg_format: String,
+    packages: Vec<Package>,
+    files: Vec<FileMetadata>,
+    notices: Vec<Notice>,
+    analyzed_output: String,
+    // The build step that created the layer, from the image history
+    #[serde(default)]
+    created_by: Option<String>,
+    #[serde(skip)]
+    contents: LayerContents,
+    #[serde(skip)]
+    links: LayerLinks,
+    #[serde(skip)]
+    dir_modes: LayerModes,
+    #[serde(skip)]
+    root_prefix: Option<&'static str>,
+}
+
+impl Layer {
+    // Path of a layer entry relative to the analyzed root filesystem, or None
+    // if it lies outside a --root-prefix
+    fn rooted<'a>(&self, path: &'a str) -> Option<&'a str> {
+        checks::strip_root(path, self.root_prefix)
+    }
+}
+
+// The packages of the final image, each with the index of its layer. A layer
+// that touches a package database or manifest carries all of it, so only the
+// newest layer listing packages from a file counts: packages an earlier copy
+// listed and a later one dropped were removed.
+fn final_packages(layers: &[Layer]) -> Vec<(usize, &Package)> {
+    fn source_file<'a>(layer: &'a Layer, package: &'a Package) -> &'a str {
+        package.detection.as_ref().map_or(&layer.pkg_format, |detection| &detection.source_file)
+    }
+    let mut newest = HashMap::new();
+    for (index, layer) in layers.iter().enumerate() {
+        for package in &layer.packages {
+            newest.insert(source_file(layer, package), index);
+        }
+    }
+    layers
+        .iter()
+        .enumerate()
+        .flat_map(|(index, layer)| layer.packages.iter().map(move |package| (index, layer, package)))
+        .filter(|(index, layer, package)| newest.get(&source_file(layer, package)) == Some(index))
+        .map(|(index, _, package)| (index, package))
+        .collect()
+}
+
+// Contents of the files checks and detectors need to read, keyed by path
+type LayerContents = HashMap<String, Vec<u8>>;
+
+// Symlink and hard link targets, both resolved to paths from the image root
+type LayerLinks = HashMap<String, String>;
+
+// Permission bits of the directories in a layer, keyed by path
+type LayerModes = HashMap<String, u32>;
+
+// The error main returns. Rust prints it as "Error: " and its Debug form, so
+// Debug gives the plain message rather than the quoted string
+pub struct CliError(Box<dyn std::error::Error + Send + Sync>);
+
+impl std::fmt::Debug for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<E: Into<Box<dyn std::error::Error + Send + Sync>>> From<E> for CliError {
+    fn from(error: E) -> Self {
+        CliError(error.into())
+    }
+}
+
+// What an analyze run found that decides its exit status
+#[derive(Debug, Default)]
+struct RunOutcome {
+    gate_failed: bool,
+    malware_found: bool,
+}
+
+// Limits applied while reading layer files
+#[derive(Debug, Clone, Copy, Default)]
+struct ExtractOptions {
+    // Regular files larger than this are recorded without a checksum, unless
+    // detectors need their contents
+    max_file_size: Option<u64>,
+    // Directory in the image holding the root filesystem that package
+    // databases and detectors are read from, e.g. a chroot under /rootfs
+    root_prefix: Option<&'static str>,
+    // Keep license texts for --license-evidence
+    license_texts: bool,
+    // Keep frontend build manifests for --web-assets
+    web_assets: bool,
+    // Entries left out by --include-glob, --exclude-glob and the default
+    // exclusions
+    filter: Option<&'static PathFilter>,
+    // Where layer analyses are reused from and stored, unless --no-cache
+    layer_cache: Option<&'static cache::LayerCache>,
+}
+
+impl ExtractOptions {
+    // Whether a regular file is read. The size limit only spares hashing:
+    // files that are kept or inspected for packages are read at any size.
+    fn reads(&self, size: u64, retained: bool, inspection: Inspection) -> bool {
+        retained || inspection != Inspection::Nothing || self.max_file_size.is_none_or(|max| size <= max)
+    }
+
+    // Whether a regular file's contents are kept after hashing, given its
+    // path in the analyzed root
+    fn retains(&self, rooted: Option<&str>) -> bool {
+        rooted.is_some_and(|rooted| {
+            retain_content(rooted)
+                || (self.license_texts && licenses::is_license_text_path(rooted))
+                || (self.web_assets && detectors::is_asset_manifest_path(rooted))
+        })
+    }
+
+    // Whether an entry is neither hashed nor recorded. Files that detectors
+    // read are never left out, so filtering cannot hide packages.
+    fn skips(&self, path: &str, retained: bool) -> bool {
+        !retained && self.filter.is_some_and(|filter| filter.skips(path))
+    }
+}
+
+const SKIPPED_CHECKSUM: &str = "skipped: too large";
+// Read size for files that are hashed without being kept
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+// Everything extraction collects from a layer's entries
+#[derive(Default)]
+struct ExtractedFiles {
+    files: Vec<FileMetadata>,
+    contents: LayerContents,
+    links: LayerLinks,
+    dir_modes: LayerModes,
+    // Build info of the Go binaries in the layer, keyed by path
+    go_binaries: BTreeMap<String, golang::BuildInfo>,
+    // What the JARs, WARs and EARs in the layer record, keyed by path
+    java_archives: BTreeMap<String, Vec<java::Artifact>>,
+}
+
+// Stored in the analysis caches: bump cache::CACHE_FORMAT when its fields change
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Package {
+    name: String,
+    version: String,
+    source: String,
+    license: String,
+    vendor: String,
+    checksum: String,
+    // Commands the package provides through links, e.g. busybox applets
+    #[serde(default)]
+    commands: Vec<String>,
+    #[serde(default)]
+    purl: Option<String>,
+    // "explicit" if the package was requested, "dependency" if pulled in
+    #[serde(default)]
+    install_reason: Option<String>,
+    // Names of the installed packages that satisfy each dependency; dependencies
+    // no installed package provides are kept as written in the package database
+    #[serde(default)]
+    depends: Vec<String>,
+    // Names, shared libraries and commands the package provides to others
+    #[serde(default)]
+    provides: Vec<String>,
+    // RFC 3339 time the package was built, when the package database records it
+    #[serde(default)]
+    built_at: Option<String>,
+    // How the package was found, kept with --explain
+    #[serde(default)]
+    detection: Option<Detection>,
+    // The license as an SPDX license expression, when it could be read as one
+    #[serde(default)]
+    spdx_license: Option<String>,
+    // Known vulnerabilities from OSV.dev, with --scan
+    #[serde(default)]
+    vulnerabilities: Vec<osv::Vulnerability>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Detection {
+    // The file the package was read from: a package database or manifest in
+    // the image, or the Dockerfile
+    source_file: String,
+    detector: String,
+    confidence: Confidence,
+}
+
+// How far a detection can be trusted: high for records a package manager
+// wrote when it installed the package, medium for packages recognized from
+// the files they install, low for guesses from build inputs and manifests
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Confidence {
+    High,
+    Medium,
+    Low,
+}
+
+impl Detection {
+    fn new(source_file: &str, detector: &str, confidence: Confidence) -> Detection {
+        Detection {
+            source_file: source_file.to_string(),
+            detector: detector.to_string(),
+            confidence,
+        }
+    }
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Confidence::High => "high",
+            Confidence::Medium => "medium",
+            Confidence::Low => "low",
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FileMetadata {
+    path: String,
+    size: u64,
+    file_type: String,
+    checksum: String,
+    // Other paths in the layer with identical content, when files are deduplicated
+    #[serde(default)]
+    duplicate_paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Notice {
+    message: String,
+    level: String,
+    // Where the finding comes from in the build inputs, when it maps to a line
+    // of a file such as the Dockerfile
+    #[serde(default)]
+    location: Option<NoticeLocation>,
+    // Check that raised the finding, for findings raised by a check
+    #[serde(default)]
+    rule: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct NoticeLocation {
+    file: String,
+    line: usize,
+}
+
+// Fields serialize in declaration order, which is part of the documented
+// schema: append new fields instead of reordering existing ones.
+#[derive(Debug, Serialize, Deserialize)]
+struct Sbom {
+    #[serde(default = "legacy_schema_version")]
+    schema_version: u32,
+    sbom_version: String,
+    spdx_id: String,
+    name: String,
+    namespace: String,
+    creation_info: CreationInfo,
+    image_name: String,
+    image_digest: String,
+    layers: Vec<Layer>,
+    dockerfile_analysis: Option<DockerfileAnalysis>,
+    signature: Option<String>,
+    metadata: Metadata,
+    #[serde(default)]
+    platform: Option<String>,
+    // Fingerprint of the key that made `signature`; it is part of the signed payload
+    #[serde(default)]
+    signature_key_id: Option<String>,
+    // Build provenance read from image labels, with --include-provenance-from-labels
+    #[serde(default)]
+    provenance: Option<provenance::Provenance>,
+    // Licenses with the packages and license texts declaring them, with --license-evidence
+    #[serde(default)]
+    license_evidence: Option<Vec<licenses::LicenseEvidence>>,
+    // systemd service units, enabled or not
+    #[serde(default)]
+    services: Vec<systemd::Service>,
+    // Scheme of `signature`, ed25519 or ecdsa-p256; it is part of the signed
+    // payload. Signatures without it are Ed25519.
+    #[serde(default)]
+    signature_algorithm: Option<String>,
+}
+
+fn legacy_schema_version() -> u32 {
+    1
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CreationInfo {
+    created: String,
+    creators: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DockerfileAnalysis {
+    envs: BTreeMap<String, String>,
+    instructions: Vec<String>,
+    packages: Vec<Package>,
+    #[serde(default)]
+    copies: Vec<CopiedSource>,
+    #[serde(default)]
+    stages: Vec<StageInfo>,
+}
+
+// A build stage of the Dockerfile, from its FROM up to the next FROM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StageInfo {
+    index: usize,
+    // The alias given by `FROM ... AS <name>`
+    name: Option<String>,
+    // The image or earlier stage it starts from
+    base: String,
+    // Line of its FROM in the Dockerfile
+    line: usize,
+    // Positions of its instructions in `instructions`
+    instructions: Vec<usize>,
+}
+
+// One source of a COPY or ADD instruction and where its content came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CopiedSource {
+    instruction: String,
+    source: String,
+    destination: String,
+    // "context" (the build context), "stage" (--from a build stage or image)
+    // or "remote" (an ADD of a URL or git repository)
+    origin: String,
+    // The stage or image named by --from
+    from: Option<String>,
+    // The --checksum pinning a remote ADD
+    checksum: Option<String>,
+    // Line of the instruction in the Dockerfile
+    #[serde(default)]
+    line: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Metadata {
+    tool: String,
+    version: String,
+    authors: Vec<String>,
+    organization: String,
+    #[serde(default)]
+    healthcheck: Option<Healthcheck>,
+    // Commit and build time of the cbom binary that produced the SBOM
+    #[serde(default)]
+    commit: Option<String>,
+    #[serde(default)]
+    built_at: Option<String>,
+    // Default shell and installed init systems of the image
+    #[serde(default)]
+    shell: Option<String>,
+    #[serde(default)]
+    init_systems: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Healthcheck {
+    command: Vec<String>,
+    interval: Option<String>,
+    timeout: Option<String>,
+    retries: Option<i64>,
+}
+
+impl Healthcheck {
+    // Durations in image configs are nanoseconds, with 0 meaning the default
+    fn from_config(config: &HealthConfig) -> Option<Healthcheck> {
+        let command = config.test.clone().filter(|test| !test.is_empty() && test[0] != "NONE")?;
+        let duration = |nanos: Option<i64>| nanos.filter(|nanos| *nanos > 0).map(|nanos| format!("{}s", nanos as f64 / 1e9));
+        Some(Healthcheck {
+            command,
+            interval: duration(config.interval),
+            timeout: duration(config.timeout),
+            retries: config.retries.filter(|retries| *retries > 0),
+        })
+    }
+}
+
+// The command line: parses the arguments and runs the subcommand they name
+pub fn run() -> Result<(), CliError> {
+    let long_version = format!("{} ({})", TOOL_VERSION, build_commit().as_deref().unwrap_or("unknown commit"));
+    let matches = Command::new("CBOM")
+        .version(TOOL_VERSION)
+        .long_version(&*long_version.leak())
+        .about("Container Software Bill of Materials (SBOM) generator")
+        .arg(
+            Arg::new("log-format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .help("Diagnostic log format: text or json (level is set with -v, -q or RUST_LOG, default info)")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
+        .arg(
+            Arg::new("verbose")
+                .short('v')
+                .long("verbose")
+                .help("Log more: -v for debug messages, -vv to trace everything")
+                .action(clap::ArgAction::Count)
+                .global(true),
+        )
+        .arg(
+            Arg::new("quiet")
+                .short('q')
+                .long("quiet")
+                .help("Draw no progress bars and log only warnings and errors, for CI logs")
+                .action(clap::ArgAction::SetTrue)
+                .conflicts_with("verbose")
+                .global(true),
+        )
+        .arg(
+            Arg::new("host")
+                .short('H')
+                .long("host")
+                .value_name("HOST")
+                .help("Docker daemon to connect to, as tcp://HOST:PORT or unix:///PATH (defaults to DOCKER_HOST, then the local socket)")
+                .value_parser(clap::value_parser!(String))
+                .global(true),
+        )
+        .subcommand(
+            Command::new("generate-key")
+                .about("Generate a new Ed25519 or ECDSA P-256 keypair")
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Output file for the keypair; the public key alone is written to <FILE>.pub")
+                        .value_parser(clap::value_parser!(String))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("sig-algo")
+                        .long("sig-algo")
+                        .value_name("ALGORITHM")
+                        .help("Signature algorithm of the keypair")
+                        .value_parser(SIGNATURE_ALGORITHMS)
+                        .default_value("ed25519"),
+                ),
+        )
+        .subcommand(
+            Command::new("analyze")
+                .about("Analyze a Docker image and generate SBOM")
+                .arg(
+                    Arg::new("IMAGE")
+                        .help("Docker image to analyze; with --from-archive or --oci-layout, the tag of the image to read (defaults to the first image)")
+                        .required_unless_present_any(["from-archive", "oci-layout"])
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("output")
+                        .short('o')
+                        .long("output")
+                        .value_name("FILE")
+                        .help("Output file for the SBOM")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("output-dir")
+                        .long("output-dir")
+                        .value_name("DIR")
+                        .help("Write each SBOM into this directory, named by --filename-template")
+                        .value_parser(clap::value_parser!(String))
+                        .conflicts_with("output"),
+                )
+                .arg(
+                    Arg::new("filename-template")
+                        .long("filename-template")
+                        .value_name("TEMPLATE")
+                        .help("File name for SBOMs written to --output-dir; placeholders: {image}, {platform}, {date}, {ext}")
+                        .value_parser(clap::value_parser!(String))
+                        .default_value("{image}-{date}.{ext}")
+                        .requires("output-dir"),
+                )
+                .arg(
+                    Arg::new("resume")
+                        .long("resume")
+                        .help("Skip images and platforms an interrupted run already wrote to --output-dir")
+                        .action(clap::ArgAction::SetTrue)
+                        .requires("output-dir"),
+                )
+                .arg(
+                    Arg::new("dockerfile")
+                        .short('d')
+                        .long("dockerfile")
+                        .value_name("FILE")
+                        .help("Dockerfile to analyze and build")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("stage")
+                        .long("stage")
+                        .value_name("NAME")
+                        .help("Only report the packages and instructions of this named Dockerfile build stage")
+                        .value_parser(clap::value_parser!(String))
+                        .requires("dockerfile"),
+                )
+                .arg(
+                    Arg::new("all-stages")
+                        .long("all-stages")
+                        .help("Report the packages installed by every Dockerfile build stage, not only the final one")
+                        .requires("dockerfile")
+                        .conflicts_with("stage")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("build")
+                        .short('b')
+                        .long("build")
+                        .help("Build Docker image from Dockerfile")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("tag")
+                        .short('t')
+                        .long("tag")
+                        .value_name("NAME")
+                        .help("Tag for the Docker image")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("creator")
+                        .long("creator")
+                        .value_name("CREATOR")
+                        .help("Add a creator to the SBOM's creation info, as 'Person: NAME (EMAIL)', 'Organization: NAME' or 'Tool: NAME-VERSION'; repeatable")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(parse_creator),
+                )
+                .arg(
+                    Arg::new("name")
+                        .long("name")
+                        .value_name("NAME")
+                        .help("Name of the SBOM document (defaults to the image name)")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("namespace")
+                        .long("namespace")
+                        .value_name("URI")
+                        .help("Unique URI of the SBOM document (defaults to https://spdx.org/spdxdocs/NAME-UUID)")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("author")
+                        .long("author")
+                        .value_name("AUTHOR")
+                        .help("Author recorded in the SBOM metadata, e.g. 'Jane Doe <jane@example.com>'; repeatable")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("organization")
+                        .long("organization")
+                        .value_name("NAME")
+                        .help("Organization producing the SBOM, recorded in the metadata and as an 'Organization:' creator")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("sign")
+                        .short('s')
+                        .long("sign")
+                        .value_name("KEY")
+                        .help("Sign the SBOM with the given key")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("sig-algo")
+                        .long("sig-algo")
+                        .value_name("ALGORITHM")
+                        .help("Signature algorithm of the --sign key, recorded in the SBOM as signature_algorithm")
+                        .value_parser(SIGNATURE_ALGORITHMS)
+                        .default_value("ed25519")
+                        .requires("sign"),
+                )
+                .arg(
+                    Arg::new("ssh-key")
+                        .long("ssh-key")
+                        .value_name("FILE")
+                        .help("Sign the output file with an SSH Ed25519 private key, or the public key of one in ssh-agent, writing an SSH signature to <FILE>.sig")
+                        .value_parser(clap::value_parser!(String))
+                        .conflicts_with("sign"),
+                )
+                .arg(
+                    Arg::new("no-signature-mutation")
+                        .long("no-signature-mutation")
+                        .visible_alias("detached")
+                        .help("Sign the output file bytes as written and store the signature in <FILE>.sig instead of inside the SBOM")
+                        .requires("sign")
+                        .requires("output")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("attest")
+                        .long("attest")
+                        .value_name("KEY")
+                        .help("Also write a signed in-toto attestation of the SPDX SBOM for the image digest, as a DSSE envelope in <FILE>.intoto.jsonl next to --output")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("emit-signing-payload")
+                        .long("emit-signing-payload")
+                        .value_name("FILE")
+                        .help("Write the exact bytes that are (or would be) signed to FILE, for external signing tools")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Output format: list, json, yaml, toml, spdx (tag-value), spdx-json, cyclonedx, table")
+                        .value_parser(["list", "json", "yaml", "toml", "spdx", "spdx-json", "cyclonedx", "table"])
+                        .default_value("json"),
+                )
+                .arg(
+                    Arg::new("schema-version")
+                        .long("schema-version")
+                        .value_name("VERSION")
+                        .help("SBOM JSON schema version to emit (defaults to the latest)")
+                        .value_parser(clap::value_parser!(u32).range(1..=SCHEMA_VERSION as i64)),
+                )
+                .arg(
+                    Arg::new("toolchain-packages")
+                        .long("toolchain-packages")
+                        .value_name("NAMES")
+                        .help("Comma-separated build toolchain packages to flag in the final image")
+                        .value_delimiter(',')
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("registry")
+                        .long("registry")
+                        .help("Fetch the image directly from its registry instead of the Docker daemon")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("runtime")
+                        .long("runtime")
+                        .value_name("RUNTIME")
+                        .help("Container runtime whose local images are analyzed")
+                        .value_parser(["docker", "podman"])
+                        .default_value("docker")
+                        .conflicts_with("registry"),
+                )
+                .arg(
+                    Arg::new("from-archive")
+                        .long("from-archive")
+                        .value_name("FILE")
+                        .help("Read the image from a `docker save` tarball instead of the Docker daemon")
+                        .value_parser(clap::value_parser!(String))
+                        .conflicts_with_all(["registry", "runtime", "podman-storage", "all-platforms", "build"]),
+                )
+                .arg(
+                    Arg::new("oci-layout")
+                        .long("oci-layout")
+                        .value_name("DIR")
+                        .help("Read the image from an OCI image layout directory instead of the Docker daemon")
+                        .value_parser(clap::value_parser!(String))
+                        .conflicts_with_all(["from-archive", "registry", "runtime", "podman-storage", "all-platforms", "build"]),
+                )
+                .arg(
+                    Arg::new("podman-storage")
+                        .long("podman-storage")
+                        .value_name("DIR")
+                        .help("Podman storage root (defaults to the rootless then the rootful location)")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("platform")
+                        .long("platform")
+                        .value_name("OS/ARCH[/VARIANT]")
+                        .help("Platform to analyze of a multi-arch image, e.g. linux/arm64 (defaults to this machine's)")
+                        .value_parser(parse_platform)
+                        .conflicts_with("all-platforms"),
+                )
+                .arg(
+                    Arg::new("all-platforms")
+                        .long("all-platforms")
+                        .help("Analyze every platform of a multi-arch image from its registry, writing one SBOM per platform (requires --output or --output-dir)")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("username")
+                        .long("username")
+                        .value_name("USER")
+                        .help("Registry username to pull with, instead of the credentials in the Docker config")
+                        .requires("password")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("password")
+                        .long("password")
+                        .value_name("PASSWORD")
+                        .help("Registry password or access token for --username")
+                        .requires("username")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("registry-token")
+                        .long("registry-token")
+                        .value_name("TOKEN")
+                        .help("Bearer token to pull with, instead of the credentials in the Docker config")
+                        .conflicts_with("username")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("require-digest")
+                        .long("require-digest")
+                        .help("Refuse image references that are not pinned to a digest (name@sha256:...)")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("build"),
+                )
+                .arg(
+                    Arg::new("cache-dir")
+                        .long("cache-dir")
+                        .value_name("DIR")
+                        .help("Reuse analysis results for images whose repo digest was analyzed before, and keep the layer cache in DIR")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("no-cache")
+                        .long("no-cache")
+                        .help("Analyze every layer instead of reusing layer analyses cached under $XDG_CACHE_HOME/cbom (or ~/.cache/cbom)")
+                        .action(clap::ArgAction::SetTrue)
+                        .conflicts_with("cache-dir"),
+                )
+                .arg(
+                    Arg::new("jobs")
+                        .short('j')
+                        .long("jobs")
+                        .value_name("N")
+                        .help("Number of layers to fetch and analyze concurrently [default: number of CPUs]")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("exclude-package")
+                        .long("exclude-package")
+                        .value_name("NAME[@VERSION]")
+                        .help("Leave a package out of policy checks and gating (it stays in the SBOM); repeatable")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("allowlist-file")
+                        .long("allowlist-file")
+                        .value_name("FILE")
+                        .help("File of NAME[@VERSION] entries to leave out of policy checks and gating")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("no-file-dedup")
+                        .long("no-file-dedup")
+                        .help("List every file path separately instead of grouping files with identical content")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("max-file-size")
+                        .long("max-file-size")
+                        .value_name("BYTES")
+                        .help("Record files larger than BYTES without hashing them (default: no limit)")
+                        .value_parser(clap::value_parser!(u64)),
+                )
+                .arg(
+                    Arg::new("include-glob")
+                        .long("include-glob")
+                        .value_name("GLOB")
+                        .help("Only record files whose path in the image, or a parent directory's, matches GLOB; repeatable")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("exclude-glob")
+                        .long("exclude-glob")
+                        .value_name("GLOB")
+                        .help("Neither hash nor record files whose path in the image, or a parent directory's, matches GLOB; repeatable")
+                        .action(clap::ArgAction::Append)
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("no-default-excludes")
+                        .long("no-default-excludes")
+                        .help("Also record compiled Python files, documentation, man and info pages, which are left out by default")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("root-prefix")
+                        .long("root-prefix")
+                        .value_name("DIR")
+                        .help("Read package databases and run detectors on the root filesystem under DIR in the image, for relocated roots such as a chroot")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("install-reason")
+                        .long("install-reason")
+                        .value_name("REASON")
+                        .help("Only show packages installed for REASON in list and table output: explicit (requested) or dependency")
+                        .value_parser(["explicit", "dependency"]),
+                )
+                .arg(
+                    Arg::new("compare-base-images")
+                        .long("compare-base-images")
+                        .value_name("DIR")
+                        .help("Compare the image against base-image SBOMs in DIR and print advisory recommendations")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("size-report")
+                        .long("size-report")
+                        .value_name("FORMAT")
+                        .help("Also print each layer's size, ranked, with the command that created it and its largest files")
+                        .value_parser(["table", "json"]),
+                )
+                .arg(
+                    Arg::new("layer-diff")
+                        .long("layer-diff")
+                        .value_name("INDEX")
+                        .help("Also print the paths and packages layer INDEX (1 for the base layer) added, modified or deleted")
+                        .value_parser(clap::value_parser!(u64).range(1..)),
+                )
+                .arg(
+                    Arg::new("layer-diff-format")
+                        .long("layer-diff-format")
+                        .value_name("FORMAT")
+                        .help("Format of the --layer-diff change list")
+                        .value_parser(["table", "json"])
+                        .default_value("table")
+                        .requires("layer-diff"),
+                )
+                .arg(
+                    Arg::new("malware-db")
+                        .long("malware-db")
+                        .value_name("FILE")
+                        .help("JSON database of known-malicious packages and file hashes; any match exits with status 2")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("include-provenance-from-labels")
+                        .long("include-provenance-from-labels")
+                        .help("Fill in a provenance section from the image's OCI and builder labels")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("license-evidence")
+                        .long("license-evidence")
+                        .help("Add a license_evidence section listing each license with its packages and license texts (large)")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("web-assets")
+                        .long("web-assets")
+                        .help("Also list libraries bundled into frontend builds, from webpack and Vite asset manifests (best-effort)")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("explain")
+                        .long("explain")
+                        .help("Record how each package was detected: the file it was read from, the detector and a confidence (high, medium or low)")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("scan")
+                        .long("scan")
+                        .help("Look up known vulnerabilities of every package with a purl on OSV.dev")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("offline")
+                        .long("offline")
+                        .help("Make no requests to online services; --scan is skipped")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("ops-checks")
+                        .long("ops-checks")
+                        .help("Run image hygiene and operational checks")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("cert-expiry-days")
+                        .long("cert-expiry-days")
+                        .value_name("DAYS")
+                        .help("With --ops-checks, warn about certificates expiring within this many days")
+                        .value_parser(clap::value_parser!(i64))
+                        .default_value("30"),
+                )
+                .arg(
+                    Arg::new("min-severity")
+                        .long("min-severity")
+                        .value_name("LEVEL")
+                        .help("Only print, annotate and report in SARIF the findings at or above this severity; the SBOM keeps them all")
+                        .value_parser(checks::SEVERITY_LEVELS.to_vec())
+                        .default_value("info"),
+                )
+                .arg(
+                    Arg::new("fail-on-severity")
+                        .long("fail-on-severity")
+                        .value_name("LEVEL")
+                        .help("Exit with status 3 if any finding or known vulnerability is at or above this severity")
+                        .value_parser(checks::SEVERITY_LEVELS.to_vec()),
+                )
+                .arg(
+                    Arg::new("fail-if-unsigned")
+                        .long("fail-if-unsigned")
+                        .help("Refuse to produce an SBOM unless it is signed with --sign")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("annotations-format")
+                        .long("annotations-format")
+                        .value_name("FORMAT")
+                        .help("Also print findings and policy failures as CI annotations on stderr")
+                        .value_parser(["github"]),
+                )
+                .arg(
+                    Arg::new("sarif-output")
+                        .long("sarif-output")
+                        .value_name("FILE")
+                        .help("Also write findings, policy failures and known vulnerabilities as a SARIF 2.1.0 log, e.g. for GitHub code scanning")
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("verify")
+                .about("Verify the SBOM with the given key")
+                .arg(
+                    Arg::new("sbom")
+                        .short('i')
+                        .long("sbom")
+                        .value_name("FILE")
+                        .help("Input SBOM file to verify")
+                        .value_parser(clap::value_parser!(String))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("key")
+                        .short('k')
+                        .long("key")
+                        .value_name("KEY")
+                        .help("Keypair to verify the SBOM with (deprecated: verifying needs only the public key, use --pubkey)")
+                        .value_parser(clap::value_parser!(String))
+                        .required_unless_present_any(["pubkey", "keyring"]),
+                )
+                .arg(
+                    Arg::new("pubkey")
+                        .long("pubkey")
+                        .value_name("FILE")
+                        .help("Ed25519 public key to verify the SBOM with: the .pub file generate-key writes, or a PEM public key")
+                        .value_parser(clap::value_parser!(String))
+                        .conflicts_with("key"),
+                )
+                .arg(
+                    Arg::new("keyring")
+                        .long("keyring")
+                        .value_name("DIR")
+                        .help("Accept a signature from any of the keys in DIR, reporting which one matched")
+                        .value_parser(clap::value_parser!(String))
+                        .conflicts_with_all(["key", "pubkey"]),
+                )
+                .arg(
+                    Arg::new("no-signature-mutation")
+                        .long("no-signature-mutation")
+                        .help("Verify the sidecar <FILE>.sig signature over the SBOM file bytes (implied for non-native formats such as SPDX)")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("detached")
+                        .long("detached")
+                        .value_name("SIG_FILE")
+                        .help("Verify the SBOM file bytes against the detached signature in SIG_FILE instead of <FILE>.sig")
+                        .value_parser(clap::value_parser!(String))
+                        .conflicts_with("no-signature-mutation"),
+                )
+                .arg(
+                    Arg::new("fail-if-unsigned")
+                        .long("fail-if-unsigned")
+                        .help("Exit with a non-zero status if the SBOM has no signature")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("print-signed-fields")
+                        .long("print-signed-fields")
+                        .help("After a successful verification, summarize what the signature covers, read from the signed bytes")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("against-image")
+                        .long("against-image")
+                        .value_name("IMAGE")
+                        .help("Re-hash the files of IMAGE and report any whose checksum differs from the SBOM")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("registry")
+                        .long("registry")
+                        .help("Read --against-image from its registry instead of the Docker daemon")
+                        .requires("against-image")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("runtime")
+                        .long("runtime")
+                        .value_name("RUNTIME")
+                        .help("Container runtime holding --against-image")
+                        .value_parser(["docker", "podman"])
+                        .default_value("docker")
+                        .conflicts_with("registry"),
+                )
+                .arg(
+                    Arg::new("podman-storage")
+                        .long("podman-storage")
+                        .value_name("DIR")
+                        .help("Podman storage root (defaults to the rootless then the rootful location)")
+                        .value_parser(clap::value_parser!(String)),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("Report the packages added, removed and changed between two SBOMs")
+                .arg(
+                    Arg::new("old")
+                        .long("old")
+                        .value_name("FILE")
+                        .help("SBOM of the earlier image")
+                        .value_parser(clap::value_parser!(String))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("new")
+                        .long("new")
+                        .value_name("FILE")
+                        .help("SBOM of the later image")
+                        .value_parser(clap::value_parser!(String))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Output format; sarif reports the changes and the vulnerabilities they bring in as a SARIF 2.1.0 log")
+                        .value_parser(["table", "json", "sarif"])
+                        .default_value("table"),
+                ),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check that an SBOM file is complete and well-formed, exiting non-zero if it is not")
+                .arg(
+                    Arg::new("sbom")
+                        .short('i')
+                        .long("sbom")
+                        .value_name("FILE")
+                        .help("SBOM file to validate")
+                        .value_parser(clap::value_parser!(String))
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Format of the problem list")
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            Command::new("cache")
+                .about("Manage cached image and layer analyses")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("clear")
+                        .about("Remove every cached analysis")
+                        .arg(
+                            Arg::new("cache-dir")
+                                .long("cache-dir")
+                                .value_name("DIR")
+                                .help("Cache directory to clear (defaults to $XDG_CACHE_HOME/cbom, or ~/.cache/cbom)")
+                                .value_parser(clap::value_parser!(String)),
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check that the environment is set up to analyze images")
+                .arg(
+                    Arg::new("key")
+                        .short('k')
+                        .long("key")
+                        .value_name("KEY")
+                        .help("Also check that this signing key is readable")
+                        .value_parser(clap::value_parser!(String)),
+                )
+                .arg(
+                    Arg::new("check-osv")
+                        .long("check-osv")
+                        .help("Also check network access to the OSV vulnerability API")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .get_matches();
+
+    init_logging(matches.get_one::<String>("log-format").unwrap(), matches.get_count("verbose"), matches.get_flag("quiet"));
+    progress::set_quiet(matches.get_flag("quiet"));
+    if let Some(host) = matches.get_one::<String>("host") {
+        daemon::set_host(host);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("generate-key") {
+        let output_file = matches.get_one::<String>("output").unwrap();
+        let algorithm = SignatureAlgorithm::parse(matches.get_one::<String>("sig-algo").unwrap()).unwrap();
+        let (key_pair, pkcs8_bytes) = generate_keypair(algorithm);
+        save_keypair_to_file(&pkcs8_bytes, output_file)?;
+        let public_key_file = format!("{}.pub", output_file);
+        write_file(&public_key_file, key_pair.public_key())?;
+        println!("Keypair saved to {}", output_file);
+        println!("Public key saved to {}", public_key_file);
+        println!("Key fingerprint: {}", key_fingerprint(key_pair.public_key()));
+    }
+
+    if let Some(matches) = matches.subcommand_matches("doctor") {
+        let key_path = matches.get_one::<String>("key");
+        let check_osv = matches.get_flag("check-osv");
+        let rt = Runtime::new()?;
+        if !rt.block_on(doctor::run_doctor(key_path.map(String::as_str), check_osv)) {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("diff") {
+        let load = |key: &str| {
+            let path = matches.get_one::<String>(key).unwrap();
+            load_sbom(path).map_err(|e| format!("cannot load {}: {}", path, e))
+        };
+        let diff = sbomdiff::SbomDiff::new(&load("old")?, &load("new")?);
+        match matches.get_one::<String>("format").unwrap().as_str() {
+            "json" => println!("{}", serde_json::to_string_pretty(&diff).expect("Failed to serialize SBOM diff")),
+            "sarif" => {
+                let mut report = sarif::SarifReport::default();
+                report.add_diff(&diff, matches.get_one::<String>("new").unwrap());
+                println!("{}", report.to_json());
+            }
+            _ => print!("{}", diff.to_table()),
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("validate") {
+        let sbom_file = matches.get_one::<String>("sbom").unwrap();
+        let problems = match load_sbom(sbom_file) {
+            Ok(sbom) => validate::validate_sbom(&sbom),
+            Err(e) => vec![validate::Problem { path: String::new(), message: format!("cannot be read as an SBOM: {}", e) }],
+        };
+        match matches.get_one::<String>("format").unwrap().as_str() {
+            "json" => println!("{}", serde_json::to_string_pretty(&problems).expect("Failed to serialize problems")),
+            _ if problems.is_empty() => println!("{}: no problems found", sbom_file),
+            _ => {
+                println!("{}: {} problem(s)", sbom_file, problems.len());
+                for problem in &problems {
+                    match problem.path.as_str() {
+                        "" => println!("  {}", problem.message),
+                        path => println!("  {}: {}", path, problem.message),
+                    }
+                }
+            }
+        }
+        if !problems.is_empty() {
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("cache").and_then(|matches| matches.subcommand_matches("clear")) {
+        let dir = match matches.get_one::<String>("cache-dir") {
+            Some(dir) => PathBuf::from(dir),
+            None => cache::default_dir().ok_or("cannot locate the cache directory: neither XDG_CACHE_HOME nor HOME is set")?,
+        };
+        let removed = cache::clear(&dir)?;
+        println!("Removed {} cached analyses from {}", removed, dir.display());
+    }
+
+    if let Some(matches) = matches.subcommand_matches("analyze") {
+        let from_archive = matches.get_one::<String>("from-archive");
+        let oci_layout = matches.get_one::<String>("oci-layout");
+        let image_name: &String = &match (matches.get_one::<String>("IMAGE"), from_archive, oci_layout) {
+            (Some(image_name), _, _) => image_name.clone(),
+            // Untagged images are named after the archive or layout directory
+            (None, Some(path), _) => match archive::image_name(Path::new(path))? {
+                Some(tag) => tag,
+                None => Path::new(path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_else(|| path.clone()),
+            },
+            (None, None, Some(dir)) => match oci::image_name(Path::new(dir))? {
+                Some(name) => name,
+                None => Path::new(dir).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_else(|| dir.clone()),
+            },
+            (None, None, None) => unreachable!(),
+        };
+        let output_file = matches.get_one::<String>("output");
+        let output_dir = matches.get_one::<String>("output-dir");
+        let filename_template = matches.get_one::<String>("filename-template").unwrap();
+        let resume = matches.get_flag("resume");
+        let dockerfile_path = matches.get_one::<String>("dockerfile");
+        let stage = matches.get_one::<String>("stage");
+        let all_stages = matches.get_flag("all-stages");
+        let build_image = matches.get_flag("build");
+        let tag_name = matches.get_one::<String>("tag").unwrap_or(image_name);
+        let sign_key = matches.get_one::<String>("sign");
+        let sig_algo = SignatureAlgorithm::parse(matches.get_one::<String>("sig-algo").unwrap()).unwrap();
+        let ssh_key = matches.get_one::<String>("ssh-key");
+        let no_signature_mutation = matches.get_flag("no-signature-mutation");
+        let signing_payload_file = matches.get_one::<String>("emit-signing-payload");
+        let attest_key = matches.get_one::<String>("attest");
+        let output_format = matches.get_one::<String>("format").unwrap();
+        let schema_version = matches.get_one::<u32>("schema-version").copied().unwrap_or(SCHEMA_VERSION);
+        let toolchain_packages: Vec<String> = match matches.get_many::<String>("toolchain-packages") {
+            Some(names) => names.cloned().collect(),
+            None => checks::DEFAULT_TOOLCHAIN_PACKAGES.iter().map(|name| name.to_string()).collect(),
+        };
+        let from_registry = matches.get_flag("registry");
+        let all_platforms = matches.get_flag("all-platforms");
+        let platform = matches.get_one::<String>("platform");
+        let runtime = matches.get_one::<String>("runtime").unwrap();
+        let podman_storage = matches.get_one::<String>("podman-storage");
+
+        if matches.contains_id("username") || matches.contains_id("registry-token") {
+            credentials::set_overrides(credentials::Credentials {
+                username: matches.get_one::<String>("username").cloned(),
+                password: matches.get_one::<String>("password").cloned(),
+                registry_token: matches.get_one::<String>("registry-token").cloned(),
+                ..Default::default()
+            });
+        }
+        // One document per platform on stdout would run together unparseably
+        if all_platforms && writes_output_file(output_format) && output_file.is_none() && output_dir.is_none() {
+            return Err("--all-platforms writes one SBOM per platform; pass --output or --output-dir to name the files".into());
+        }
+        if matches.get_flag("require-digest") && !registry::ImageReference::parse(image_name).is_digest_pinned() {
+            return Err(format!(
+                "--require-digest is set but {} is not pinned to a digest; tags such as :latest can point at different images over time.\n\
+                 Pin the reference as NAME@sha256:DIGEST, e.g. with the digest from `docker buildx imagetools inspect {}` or `docker inspect --format '{{{{index .RepoDigests 0}}}}' {}`.",
+                image_name, image_name, image_name
+            )
+            .into());
+        }
+        let jobs = matches
+            .get_one::<usize>("jobs")
+            .copied()
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, usize::from));
+        let mut allowlist = checks::Allowlist::default();
+        for entry in matches.get_many::<String>("exclude-package").unwrap_or_default() {
+            allowlist.add(entry);
+        }
+        if let Some(path) = matches.get_one::<String>("allowlist-file") {
+            allowlist.add_file(path).map_err(|e| format!("cannot read allowlist file {}: {}", path, e))?;
+        }
+        // Schemas before version 4 cannot express grouped paths
+        let file_dedup = !matches.get_flag("no-file-dedup") && schema_version >= 4;
+        let include_globs: Vec<String> = matches.get_many::<String>("include-glob").unwrap_or_default().cloned().collect();
+        let mut exclude_globs: Vec<String> = matches.get_many::<String>("exclude-glob").unwrap_or_default().cloned().collect();
+        if !matches.get_flag("no-default-excludes") {
+            exclude_globs.extend(pathfilter::DEFAULT_EXCLUDES.iter().map(|pattern| pattern.to_string()));
+        }
+        let path_filter = PathFilter::new(&include_globs, &exclude_globs)?;
+        let extract_options = ExtractOptions {
+            max_file_size: matches.get_one::<u64>("max-file-size").copied(),
+            // Extraction options are copied into every layer task, so the prefix lives for the whole run
+            root_prefix: matches
+                .get_one::<String>("root-prefix")
+                .map(|prefix| &*checks::normalize_path(prefix).trim_end_matches('/').to_string().leak()),
+            license_texts: matches.get_flag("license-evidence"),
+            web_assets: matches.get_flag("web-assets"),
+            filter: Some(&*Box::leak(Box::new(path_filter))),
+            layer_cache: match (matches.get_flag("no-cache"), matches.get_one::<String>("cache-dir")) {
+                (true, _) => None,
+                (false, Some(dir)) => Some(&*Box::leak(Box::new(cache::LayerCache::new(Path::new(dir))))),
+                (false, None) => cache::default_dir().map(|dir| &*Box::leak(Box::new(cache::LayerCache::new(&dir)))),
+            },
+        };
+        let malware_db = matches
+            .get_one::<String>("malware-db")
+            .map(|path| checks::MalwareDb::load(path).map_err(|e| format!("cannot load malware database {}: {}", path, e)))
+            .transpose()?;
+        let install_reason = matches.get_one::<String>("install-reason");
+        let compare_base_images = matches.get_one::<String>("compare-base-images");
+        let size_report = matches.get_one::<String>("size-report");
+        let layer_diff = matches.get_one::<u64>("layer-diff").map(|index| *index as usize);
+        let layer_diff_format = matches.get_one::<String>("layer-diff-format").unwrap();
+        let ops_checks = matches.get_flag("ops-checks");
+        let include_provenance = matches.get_flag("include-provenance-from-labels");
+        let result_cache = matches.get_one::<String>("cache-dir").map(|dir| cache::ResultCache::new(dir));
+        let explain = matches.get_flag("explain");
+        let scan = matches.get_flag("scan");
+        let offline = matches.get_flag("offline");
+        let document_name = matches.get_one::<String>("name");
+        let document_namespace = matches.get_one::<String>("namespace");
+        let organization = matches.get_one::<String>("organization");
+        let authors: Vec<String> = matches.get_many::<String>("author").unwrap_or_default().cloned().collect();
+        let mut creators = vec![format!("Tool: Container SBOM Generator v{}", TOOL_VERSION)];
+        creators.extend(organization.map(|organization| format!("Organization: {}", organization)));
+        creators.extend(matches.get_many::<String>("creator").unwrap_or_default().cloned());
+        // One timestamp for the run, shared by every platform's SBOM
+        let created = creation_time();
+        let cert_expiry_days = *matches.get_one::<i64>("cert-expiry-days").unwrap();
+        let min_severity = checks::Severity::from_level(matches.get_one::<String>("min-severity").unwrap());
+        let fail_on_severity = matches.get_one::<String>("fail-on-severity").map(|level| checks::Severity::from_level(level));
+        let github_annotations = matches.get_one::<String>("annotations-format").is_some_and(|format| format == "github");
+        let sarif_output = matches.get_one::<String>("sarif-output");
+
+        if matches.get_flag("fail-if-unsigned") && sign_key.is_none() && ssh_key.is_none() {
+            return Err("--fail-if-unsigned is set but no signing key was given with --sign or --ssh-key; refusing to write an unsigned SBOM".into());
+        }
+        let ssh_signer = ssh_key
+            .map(|path| sshsig::SshSigner::load(path).map_err(|e| format!("cannot load SSH key {}: {}", path, e)))
+            .transpose()?;
+        let signing_key = sign_key.map(|path| load_keypair_from_file(path, sig_algo)).transpose()?;
+        let attestation_key = attest_key
+            .map(|path| {
+                let key_data = fs::read(path).map_err(|e| format!("cannot read key {}: {}", path, e))?;
+                SigningKey::from_pkcs8(&key_data).ok_or_else(|| format!("{} is not a keypair as written by generate-key", path))
+            })
+            .transpose()?;
+        if attest_key.is_some() && (!writes_output_file(output_format) || (output_file.is_none() && output_dir.is_none())) {
+            return Err("--attest requires --output so the attestation can be written next to the SBOM".into());
+        }
+
+        // Only the native JSON format can carry an embedded signature; every
+        // other format, and every SSH signature, is made over the exact output
+        // bytes instead
+        let detached_signature = no_signature_mutation || ssh_key.is_some() || !is_native_format(output_format);
+        // Verification of an embedded signature learns the algorithm from the
+        // signed SBOM; older schema versions drop the field and mean Ed25519
+        if sig_algo != SignatureAlgorithm::Ed25519 && schema_version < 24 && !detached_signature {
+            return Err(format!("--sig-algo {} needs --schema-version 24 or later, which records signature_algorithm", sig_algo.name()).into());
+        }
+        if (sign_key.is_some() || ssh_key.is_some() || signing_payload_file.is_some()) && detached_signature {
+            if !writes_output_file(output_format) {
+                return Err(format!("the {} output format cannot be signed", output_format).into());
+            }
+            if output_file.is_none() && output_dir.is_none() {
+                return Err(format!("signing {} output requires --output so the signature can be written next to it", output_format).into());
+            }
+        }
+        if let Some(dir) = output_dir {
+            if !writes_output_file(output_format) {
+                return Err(format!("the {} output format cannot be written to --output-dir", output_format).into());
+            }
+            fs::create_dir_all(dir).map_err(|e| format!("cannot create output directory {}: {}", dir, e))?;
+        }
+        let mut checkpoint = output_dir.map(|dir| checkpoint::Checkpoint::open(dir, resume));
+        let signed_output = (sign_key.is_some() || ssh_key.is_some()) && detached_signature;
+        let already_completed = |checkpoint: &Option<checkpoint::Checkpoint>, platform: Option<&str>| {
+            checkpoint.as_ref()?.completed_output(image_name, platform, |output| output_is_valid(output, output_format, signed_output)).map(str::to_string)
+        };
+
+        let dockerfile_analysis = dockerfile_path
+            .map(|dockerfile| analyze_dockerfile(dockerfile, stage.map(String::as_str), all_stages).map_err(|e| format!("cannot analyze Dockerfile {}: {}", dockerfile, e)))
+            .transpose()?;
+
+        let rt = Runtime::new()?;
+        let analysis = async {
+            if build_image {
+                let Some(dockerfile) = dockerfile_path else {
+                    return Err("--build requires --dockerfile".into());
+                };
+                build_dockerfile_image(dockerfile, tag_name, stage.map(String::as_str)).await.map_err(|e| format!("cannot build image {}: {}", tag_name, e))?;
+            }
+
+            let targets = if all_platforms {
+                let platforms = match registry::list_platforms(image_name).await {
+                    Ok(platforms) if platforms.is_empty() => vec![None],
+                    Ok(platforms) => platforms.into_iter().map(Some).collect(),
+                    Err(e) => return Err(format!("cannot read the image index of {} from the registry: {}", image_name, e).into()),
+                };
+
+                // A platform that fails to pull is reported and skipped so the
+                // remaining platforms still get their SBOMs
+                let mut targets = Vec::new();
+                let mut skipped = 0;
+                for platform in platforms {
+                    if let Some(output) = already_completed(&checkpoint, platform.as_deref()) {
+                        info!(platform = platform.as_deref().unwrap_or("default"), output = %output, "already completed, skipping");
+                        skipped += 1;
+                        continue;
+                    }
+                    let source = source::ImageSource::Registry { platform: platform.clone(), jobs };
+                    match cache::analyze_cached(&source, image_name, extract_options, result_cache.as_ref(), &layer_progress_bar()).await {
+                        Ok(image) => targets.push(image),
+                        Err(e) => warn!(platform = platform.as_deref().unwrap_or("default"), error = %e, "skipping platform"),
+                    }
+                }
+                if targets.is_empty() && skipped == 0 {
+                    return Err(format!("no platform of {} could be analyzed", image_name).into());
+                }
+                targets
+            } else {
+                if let Some(output) = already_completed(&checkpoint, None) {
+                    eprintln!("{} was already written to {}; skipping.", image_name, output);
+                    return Ok(RunOutcome::default());
+                }
+                let source = source::ImageSource::from_options(from_archive, oci_layout, from_registry, runtime, podman_storage, platform, jobs)
+                    .map_err(|e| format!("cannot locate podman storage: {}", e))?;
+                let image = cache::analyze_cached(&source, image_name, extract_options, result_cache.as_ref(), &layer_progress_bar())
+                    .await
+                    .map_err(|e| format!("cannot analyze image {}: {}", image_name, e))?;
+                // Archives and podman storage hold a single platform of an image
+                if let (Some(requested), Some(analyzed)) = (platform, &image.platform) {
+                    if !registry::platform_matches(analyzed, requested) {
+                        return Err(format!("{} is a {} image, not the requested {}", image_name, analyzed, requested).into());
+                    }
+                }
+                vec![image]
+            };
+
+            let mut outcome = RunOutcome::default();
+            let mut sarif_report = sarif::SarifReport::default();
+            for source::AnalyzedImage { platform, mut layers, healthcheck, labels, entrypoint, cmd, layer_commands, layer_created, digest, image_id } in targets {
+                // Layers are dated by the image until the history says otherwise
+                for ((layer, created), command) in layers.iter_mut().zip(layer_created).zip(&layer_commands) {
+                    if let Some(created) = created {
+                        layer.created = created;
+                    }
+                    layer.created_by = command.clone();
+                }
+                let image_digest = digest.clone().or_else(|| image_id.clone()).unwrap_or_else(|| "unknown".to_string());
+                let name = document_name.unwrap_or(image_name).clone();
+                let namespace = match document_namespace {
+                    Some(namespace) => namespace.clone(),
+                    None => default_namespace(&name, &image_digest, platform.as_deref(), &created),
+                };
+                let mut sbom = Sbom {
+                    schema_version,
+                    sbom_version: "1.0".to_string(),
+                    spdx_id: "SPDXRef-DOCUMENT".to_string(),
+                    name,
+                    namespace,
+                    creation_info: CreationInfo {
+                        created: created.clone(),
+                        creators: creators.clone(),
+                    },
+                    image_name: image_name.clone(),
+                    image_digest,
+                    layers: Vec::new(),
+                    dockerfile_analysis: dockerfile_analysis.clone(),
+                    signature: None,
+                    metadata: Metadata {
+                        tool: "Container SBOM Generator".to_string(),
+                        version: TOOL_VERSION.to_string(),
+                        authors: authors.clone(),
+                        organization: organization.cloned().unwrap_or_default(),
+                        healthcheck,
+                        commit: build_commit(),
+                        built_at: epoch_to_rfc3339(env!("CBOM_BUILD_EPOCH")),
+                        shell: detectors::detect_shell(&layers),
+                        init_systems: detectors::detect_init_systems(&layers),
+                    },
+                    platform: platform.clone(),
+                    signature_key_id: None,
+                    provenance: None,
+                    license_evidence: None,
+                    services: systemd::detect_services(&layers),
+                    signature_algorithm: None,
+                };
+
+                // A locally built image that was never pushed or pulled has no
+                // manifest digest; its image ID identifies the config instead
+                if let (None, Some(image_id), Some(layer)) = (&digest, &image_id, layers.last_mut()) {
+                    layer.notices.push(Notice {
+                        message: format!(
+                            "Image has no registry manifest digest (it was built locally and never pushed or pulled); image_digest is its image ID {}, the digest of the image config",
+                            image_id
+                        ),
+                        level: "info".to_string(),
+                        location: None,
+                        rule: None,
+                    });
+                }
+                if include_provenance {
+                    let (provenance, missing) = provenance::provenance_from_labels(&labels);
+                    if let (Some(layer), false) = (layers.last_mut(), missing.is_empty()) {
+                        layer.notices.push(Notice {
+                            message: format!("Provenance fields not found in image labels: {}", missing.join(", ")),
+                            level: "info".to_string(),
+                            location: None,
+                            rule: None,
+                        });
+                    }
+                    sbom.provenance = Some(provenance);
+                }
+
+                detectors::detect_os_release(&mut layers);
+                detectors::detect_busybox_applets(&mut layers);
+                detectors::detect_conda_packages(&mut layers);
+                detectors::detect_composer_packages(&mut layers);
+                detectors::detect_install_reasons(&mut layers);
+                if extract_options.web_assets {
+                    detectors::detect_bundled_assets(&mut layers);
+                }
+                for layer in &mut layers {
+                    for package in layer.packages.iter_mut().filter(|package| package.purl.is_none()) {
+                        package.purl = Some(package.purl(&layer.pkg_format));
+                    }
+                }
+                licenses::normalize_package_licenses(&mut layers);
+                if scan && !offline {
+                    osv::scan_vulnerabilities(&mut layers).await;
+                } else if let (true, Some(layer)) = (scan, layers.last_mut()) {
+                    layer.notices.push(Notice {
+                        message: "Vulnerability scan skipped: --offline is set".to_string(),
+                        level: "info".to_string(),
+                        location: None,
+                        rule: None,
+                    });
+                }
+                checks::check_toolchain_packages(&mut layers, &toolchain_packages, &allowlist);
+                checks::check_world_writable_dirs(&mut layers);
+                if let Some(healthcheck) = &sbom.metadata.healthcheck {
+                    checks::check_healthcheck(&mut layers, healthcheck);
+                }
+                checks::check_pid1_signal_handling(&mut layers, entrypoint.as_deref(), cmd.as_deref());
+                checks::check_systemd_services(&mut layers, &sbom.services);
+                if let Some(malware_db) = &malware_db {
+                    outcome.malware_found |= checks::check_malware(&mut layers, malware_db);
+                }
+                if let (Some(analysis), Some(dockerfile)) = (&sbom.dockerfile_analysis, dockerfile_path) {
+                    checks::check_remote_sources(&mut layers, dockerfile, &analysis.copies);
+                }
+                if ops_checks {
+                    checks::check_package_caches(&mut layers);
+                    checks::check_certificates(&mut layers, cert_expiry_days);
+                }
+
+                // Gate on every finding; --min-severity only filters what is
+                // printed and annotated
+                if let (Some(threshold), Some(highest)) = (fail_on_severity, checks::highest_severity(&layers, &allowlist)) {
+                    outcome.gate_failed |= highest >= threshold;
+                }
+                if github_annotations {
+                    let title = match &platform {
+                        Some(platform) if all_platforms => format!("cbom: {} ({})", image_name, platform),
+                        _ => format!("cbom: {}", image_name),
+                    };
+                    checks::print_github_annotations(&layers, &title, min_severity);
+                }
+                if sarif_output.is_some() {
+                    let fallback_file = dockerfile_path.map(String::as_str);
+                    sarif_report.add_findings(&layers, image_name, platform.as_deref(), fallback_file, min_severity);
+                    sarif_report.add_vulnerabilities(&layers, image_name, platform.as_deref(), fallback_file, min_severity);
+                }
+                sbom.layers = layers;
+                if !explain {
+                    let dockerfile_packages = sbom.dockerfile_analysis.iter_mut().flat_map(|analysis| &mut analysis.packages);
+                    for package in sbom.layers.iter_mut().flat_map(|layer| &mut layer.packages).chain(dockerfile_packages) {
+                        package.detection = None;
+                    }
+                }
+
+                canonicalize_sbom(&mut sbom);
+                if extract_options.license_texts {
+                    sbom.license_evidence = Some(licenses::license_evidence(&sbom.layers));
+                }
+                if let Some(dir) = compare_base_images {
+                    match compare::compare_base_images(&sbom, Path::new(dir)) {
+                        Ok(report) => eprint!("{}", report),
+                        Err(e) => warn!(error = %e, "base image comparison failed"),
+                    }
+                }
+                // Reports go to stdout when the SBOM itself goes to a file
+                let print_report = |report: String| {
+                    if writes_output_file(output_format) && (output_file.is_some() || output_dir.is_some()) {
+                        print!("{}", report);
+                    } else {
+                        eprint!("{}", report);
+                    }
+                };
+                if let Some(format) = size_report {
+                    let report = size::SizeReport::new(image_name, platform.as_deref(), &sbom.layers, &layer_commands);
+                    print_report(match format.as_str() {
+                        "json" => serde_json::to_string_pretty(&report).expect("Failed to serialize size report") + "\n",
+                        _ => report.to_table(),
+                    });
+                }
+                if let Some(index) = layer_diff {
+                    if index > sbom.layers.len() {
+                        return Err(format!("{} has no layer {}; it has {} layers", image_name, index, sbom.layers.len()).into());
+                    }
+                    let diff = layerdiff::LayerDiff::new(image_name, platform.as_deref(), &sbom.layers, &layer_commands, index);
+                    print_report(match layer_diff_format.as_str() {
+                        "json" => serde_json::to_string_pretty(&diff).expect("Failed to serialize layer diff") + "\n",
+                        _ => diff.to_table(),
+                    });
+                }
+                if file_dedup {
+                    for layer in &mut sbom.layers {
+                        layer.files = dedup_files(std::mem::take(&mut layer.files));
+                    }
+                }
+
+                let (output_file, signing_payload_file) = match &platform {
+                    Some(platform) if all_platforms => (
+                        output_file.map(|output| platform_output_path(output, platform)),
+                        signing_payload_file.map(|payload| platform_output_path(payload, platform)),
+                    ),
+                    _ => (output_file.cloned(), signing_payload_file.cloned()),
+                };
+                let output_file = match output_dir {
+                    Some(dir) => {
+                        let platform = platform.as_deref().filter(|_| all_platforms);
+                        Some(templated_output_path(dir, filename_template, image_name, platform, output_format))
+                    }
+                    None => output_file,
+                };
+
+                if !detached_signature {
+                    if let Some(key_pair) = &signing_key {
+                        sbom.signature_key_id = Some(key_fingerprint(key_pair.public_key()));
+                        sbom.signature_algorithm = Some(key_pair.algorithm().name().to_string());
+                    }
+                    let payload = signing_payload(&sbom, schema_version);
+                    if let Some(payload_file) = &signing_payload_file {
+                        write_file(payload_file, &payload)?;
+                    }
+                    if let Some(key_pair) = &signing_key {
+                        debug!(key = ?sbom.signature_key_id, "signing SBOM");
+                        sbom.signature = Some(sign_data(key_pair, &payload));
+                        debug!(signature = ?sbom.signature, "SBOM signed");
+                    }
+                }
+
+                let _serialization = info_span!("serialization", format = %output_format).entered();
+
+                // --install-reason narrows the list and table views; the SBOM
+                // formats always carry every package
+                let listed = |package: &Package| install_reason.is_none_or(|reason| package.install_reason.as_ref() == Some(reason));
+
+                match output_format.as_str() {
+                    "json" | "yaml" | "toml" => {
+                        if let Some(output) = &output_file {
+                            save_sbom_to_file(&sbom, schema_version, output_format, output)?;
+                        } else {
+                            println!("{}", serialize_sbom(&sbom, schema_version, output_format));
+                        }
+                    },
+                    "list" => {
+                        let packages: Vec<&Package> = sbom.layers.iter().flat_map(|layer| &layer.packages).filter(|package| listed(package)).collect();
+                        for package in packages {
+                            println!("{} {} {} {} {} {}", package.name, package.version, package.source, package.license, package.vendor, package.checksum);
+                        }
+                    },
+                    "spdx" => {
+                        let spdx_output = spdx::generate_spdx(&sbom);
+                        if let Some(output) = &output_file {
+                            write_file(output, &spdx_output)?;
+                        } else {
+                            println!("{}", spdx_output);
+                        }
+                    },
+                    "spdx-json" => {
+                        let spdx_output = spdx::generate_spdx_json(&sbom);
+                        if let Some(output) = &output_file {
+                            write_file(output, &spdx_output)?;
+                        } else {
+                            println!("{}", spdx_output);
+                        }
+                    },
+                    "cyclonedx" => {
+                        let cyclonedx_output = cyclonedx::generate_cyclonedx(&sbom);
+                        if let Some(output) = &output_file {
+                            write_file(output, &cyclonedx_output)?;
+                        } else {
+                            println!("{}", cyclonedx_output);
+                        }
+                    },
+                    "table" => {
+                        display_sbom_table(&sbom, listed, min_severity);
+                    },
+                    _ => unreachable!(),
+                }
+
+                // Sign the bytes exactly as they were written so verification
+                // never has to re-serialize the document
+                if let (Some(output), true) = (&output_file, detached_signature) {
+                    if let Some(payload_file) = &signing_payload_file {
+                        fs::copy(output, payload_file).map_err(|e| format!("cannot write {}: {}", payload_file, e))?;
+                    }
+                    if let Some(key_pair) = &signing_key {
+                        write_detached_signature(key_pair, output)?;
+                        eprintln!("Signature written to {}", signature_path(output));
+                    }
+                    if let Some(ssh_signer) = &ssh_signer {
+                        let data = fs::read(output).map_err(|e| format!("cannot read {}: {}", output, e))?;
+                        let signature = ssh_signer.sign(&data).map_err(|e| format!("cannot sign {} with the SSH key: {}", output, e))?;
+                        write_file(&signature_path(output), signature)?;
+                        eprintln!("SSH signature written to {}", signature_path(output));
+                    }
+                }
+                if let (Some(key_pair), Some(output)) = (&attestation_key, &output_file) {
+                    let statement = attestation::Statement::spdx(&sbom.image_name, &sbom.image_digest, &spdx::generate_spdx_json(&sbom))?;
+                    let envelope = attestation::Envelope::sign(&statement, key_fingerprint(key_pair.public_key()), |payload| sign_data(key_pair, payload));
+                    write_file(&attestation_path(output), envelope.to_jsonl())?;
+                    eprintln!("Attestation written to {}", attestation_path(output));
+                }
+                if let (Some(checkpoint), Some(output)) = (&mut checkpoint, &output_file) {
+                    if let Err(e) = checkpoint.mark_completed(image_name, platform.as_deref().filter(|_| all_platforms), output) {
+                        warn!(error = %e, "unable to update checkpoint");
+                    }
+                }
+            }
+
+            if let Some(path) = sarif_output {
+                sarif_report.write(path).map_err(|e| format!("cannot write SARIF log {}: {}", path, e))?;
+            }
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(outcome)
+        };
+
+        // Temporary files live in TempDirs owned by the analysis, so dropping
+        // it on an interrupt removes exported layers and downloaded blobs
+        let outcome = rt.block_on(async {
+            tokio::select! {
+                outcome = analysis => Some(outcome),
+                _ = shutdown_signal() => None,
+            }
+        });
+        let Some(outcome) = outcome else {
+            eprintln!("Interrupted; temporary files were removed.");
+            std::process::exit(130);
+        };
+        let outcome = outcome?;
+
+        let fail = |message: &str, code: i32| -> ! {
+            if github_annotations {
+                eprintln!("{}", checks::github_annotation("error", &format!("cbom: {}", image_name), message, None));
+            }
+            eprintln!("{}", message);
+            std::process::exit(code);
+        };
+        if outcome.malware_found {
+            fail("Packages or files listed in the malware database were found.", 2);
+        }
+        if outcome.gate_failed {
+            fail("Findings at or above the --fail-on-severity threshold were reported.", 3);
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        let sbom_file = matches.get_one::<String>("sbom").unwrap();
+        let keyring = matches.get_one::<String>("keyring");
+        let keys = match keyring {
+            Some(dir) => load_keyring(dir)?,
+            None if matches.contains_id("pubkey") => {
+                let path = matches.get_one::<String>("pubkey").unwrap();
+                let public_key = fs::read(path).map_err(|e| format!("cannot read public key {}: {}", path, e))?;
+                let (algorithm, public_key) =
+                    parse_public_key(&public_key).ok_or_else(|| format!("{} is not an Ed25519 or ECDSA P-256 public key (raw bytes or PEM)", path))?;
+                vec![TrustedKey::new(algorithm, &public_key, path)]
+            }
+            None => {
+                let key_path = matches.get_one::<String>("key").unwrap();
+                let key_data = fs::read(key_path).map_err(|e| format!("cannot read key {}: {}", key_path, e))?;
+                let key_pair = SigningKey::from_pkcs8(&key_data).ok_or_else(|| format!("{} is not a keypair as written by generate-key", key_path))?;
+                vec![TrustedKey::new(key_pair.algorithm(), key_pair.public_key(), key_path)]
+            }
+        };
+        for key in &keys {
+            debug!(path = %key.path, fingerprint = %key.fingerprint, algorithm = key.algorithm.name(), public_key = %HEXLOWER.encode(&key.public_key), "trusted key");
+        }
+        let fail_if_unsigned = matches.get_flag("fail-if-unsigned");
+        let detached = matches.get_one::<String>("detached");
+        let print_fields = matches.get_flag("print-signed-fields");
+        let unsigned = || {
+            println!("No signature found to verify.");
+            if fail_if_unsigned {
+                std::process::exit(1);
+            }
+        };
+        let succeeded = |key: &TrustedKey| {
+            println!("Signature verification succeeded.");
+            if keyring.is_some() {
+                println!("Signed with key {} ({}).", key.fingerprint, key.path);
+            }
+        };
+
+        // Anything that is not a native SBOM (SPDX, CycloneDX, ...) can only
+        // have been signed over its bytes
+        match load_sbom(sbom_file) {
+            Ok(sbom) if !matches.get_flag("no-signature-mutation") && detached.is_none() => {
+                if let Some(signature) = &sbom.signature {
+                    debug!(keys = keys.len(), "verifying SBOM");
+
+                    let signed_with = sbom.signature_key_id.clone();
+                    let algorithm_name = sbom.signature_algorithm.clone().unwrap_or_else(|| SignatureAlgorithm::Ed25519.name().to_string());
+                    let algorithm = SignatureAlgorithm::parse(&algorithm_name)
+                        .ok_or_else(|| format!("{} was signed with {}, which this version cannot verify", sbom_file, algorithm_name))?;
+                    let unsigned_sbom = Sbom {
+                        signature: None,
+                        ..sbom
+                    };
+                    let payloads = verification_payloads(&unsigned_sbom);
+                    debug!(signature = %signature, sbom = %sbom_file, "verification inputs");
+
+                    // The signed key id picks the matching key out of a keyring
+                    // instead of trying every key
+                    let candidates: Vec<&TrustedKey> = match &signed_with {
+                        Some(signed_with) if keyring.is_some() => keys.iter().filter(|key| key.fingerprint == *signed_with).collect(),
+                        _ => keys.iter().collect(),
+                    };
+                    let verified = candidates
+                        .into_iter()
+                        .filter(|key| key.algorithm == algorithm)
+                        .find_map(|key| payloads.iter().find(|payload| verify_signature(key, payload, signature)).map(|payload| (key, payload)));
+                    match verified {
+                        Some((key, payload)) => {
+                            succeeded(key);
+                            if print_fields {
+                                print_signed_fields(payload, "json");
+                            }
+                        }
+                        None => {
+                            let mut message = String::from("Signature verification failed.");
+                            if !keys.iter().any(|key| key.algorithm == algorithm) {
+                                message.push_str(&format!("\nThe SBOM was signed with {}, and none of the keys given are {} keys.", algorithm.name(), algorithm.name()));
+                            }
+                            let reason = match (signed_with, keyring) {
+                                (Some(signed_with), Some(dir)) if !keys.iter().any(|key| key.fingerprint == signed_with) => {
+                                    Some(format!("The signature was made with key {}, which is not in the keyring {}.", signed_with, dir))
+                                }
+                                (Some(_), Some(_)) => Some("The signer's key is in the keyring; the SBOM was modified after signing.".to_string()),
+                                (Some(signed_with), None) if signed_with != keys[0].fingerprint => {
+                                    Some(format!("The signature was made with key {} but you provided key {}.", signed_with, keys[0].fingerprint))
+                                }
+                                (Some(_), None) => Some("The key matches the signer's; the SBOM was modified after signing.".to_string()),
+                                (None, _) => None,
+                            };
+                            if let Some(reason) = reason {
+                                message.push('\n');
+                                message.push_str(&reason);
+                            }
+                            return Err(message.into());
+                        }
+                    }
+                } else {
+                    unsigned();
+                }
+            }
+            _ if detached.is_none() && !Path::new(&signature_path(sbom_file)).exists() => unsigned(),
+            _ => match verify_detached_signature(&keys, sbom_file, &detached.cloned().unwrap_or_else(|| signature_path(sbom_file)))? {
+                Some(key) => {
+                    succeeded(key);
+                    if print_fields {
+                        let extension = Path::new(sbom_file).extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+                        print_signed_fields(&fs::read(sbom_file)?, extension);
+                    }
+                }
+                None => return Err("Signature verification failed.".into()),
+            },
+        }
+
+        if let Some(image_name) = matches.get_one::<String>("against-image") {
+            let sbom = load_sbom(sbom_file)
+                .map_err(|e| format!("file checksums can only be checked for native SBOMs; cannot load {}: {}", sbom_file, e))?;
+            let source = source::ImageSource::from_options(
+                None,
+                None,
+                matches.get_flag("registry"),
+                matches.get_one::<String>("runtime").unwrap(),
+                matches.get_one::<String>("podman-storage"),
+                sbom.platform.as_ref(),
+                1,
+            )
+            .map_err(|e| format!("cannot locate podman storage: {}", e))?;
+            let rt = Runtime::new()?;
+            let image = rt
+                .block_on(source.analyze(image_name, ExtractOptions::default(), &layer_progress_bar()))
+                .map_err(|e| format!("cannot analyze image {}: {}", image_name, e))?;
+
+            let (checked, mismatches) = compare_file_checksums(&sbom, &image.layers);
+            if mismatches.is_empty() {
+                println!("All {} file checksums match {}.", checked, image_name);
+            } else {
+                println!("{} of {} files differ from {}:", mismatches.len(), checked, image_name);
+                for mismatch in &mismatches {
+                    println!("  {}", mismatch);
+                }
+                std::process::exit(1);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Summarizes what a verified signature vouches for. The fields are read back
+// from the exact bytes the signature covers, not from the document as loaded,
+// so anything shown here was signed. Each output format names them
+// differently; packages are counted once per layer that has them, as in the
+// native SBOM, except in CycloneDX, which lists each package once.
+fn print_signed_fields(payload: &[u8], extension: &str) {
+    let text = String::from_utf8_lossy(payload);
+    let (document_id, image_digest, packages, created) = if text.starts_with("SPDXVersion:") {
+        let tag = |name: &str| text.lines().find_map(|line| line.strip_prefix(name)).map(|value| value.trim().to_string());
+        // The image is the package whose SPDXID line precedes its version
+        let image_digest = text
+            .lines()
+            .skip_while(|line| line.trim() != "SPDXID: SPDXRef-Image")
+            .find_map(|line| line.strip_prefix("PackageVersion:"))
+            .map(|value| value.trim().to_string());
+        let packages = text
+            .lines()
+            .filter_map(|line| line.strip_prefix("Relationship:"))
+            .filter(|relationship| is_layer_package_relationship(relationship.split_whitespace()))
+            .count();
+        (tag("SPDXID:"), image_digest, packages, tag("Created:"))
+    } else {
+        let value: Option<serde_json::Value> = match extension.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => serde_yaml::from_str(&text).ok(),
+            "toml" => toml::from_str(&text).ok(),
+            _ => serde_json::from_slice(payload).ok(),
+        };
+        let Some(value) = value else {
+            println!("The signed content is not an SBOM this tool can read; no fields to show.");
+            return;
+        };
+        let field = |pointer: &str| value.pointer(pointer).and_then(|value| value.as_str()).map(str::to_string);
+        let array = |pointer: &str| value.pointer(pointer).and_then(|value| value.as_array()).map(Vec::as_slice).unwrap_or_default();
+        if value.get("spdxVersion").is_some() {
+            let image_digest = array("/packages")
+                .iter()
+                .find(|package| package.get("SPDXID").and_then(|id| id.as_str()) == Some("SPDXRef-Image"))
+                .and_then(|image| image.get("versionInfo")?.as_str())
+                .map(str::to_string);
+            // Layers contain files as well as packages
+            let package_ids: HashSet<&str> = array("/packages").iter().filter_map(|package| package.get("SPDXID")?.as_str()).collect();
+            let packages = array("/relationships")
+                .iter()
+                .filter(|relationship| {
+                    let field = |name: &str| relationship.get(name).and_then(|value| value.as_str()).unwrap_or_default();
+                    package_ids.contains(field("relatedSpdxElement"))
+                        && is_layer_package_relationship([field("spdxElementId"), field("relationshipType"), field("relatedSpdxElement")].into_iter())
+                })
+                .count();
+            (field("/SPDXID"), image_digest, packages, field("/creationInfo/created"))
+        } else if value.get("bomFormat").and_then(|format| format.as_str()) == Some("CycloneDX") {
+            (field("/serialNumber"), field("/metadata/component/version"), array("/components").len(), field("/metadata/timestamp"))
+        } else if value.get("layers").is_some() {
+            let packages = array("/layers").iter().filter_map(|layer| layer.get("packages")?.as_array()).map(Vec::len).sum();
+            (field("/spdx_id"), field("/image_digest"), packages, field("/creation_info/created"))
+        } else {
+            println!("The signed content is JSON but not an SBOM, SPDX or CycloneDX document; no fields to show.");
+            return;
+        }
+    };
+
+    let unknown = |value: Option<String>| value.filter(|value| !value.is_empty()).unwrap_or_else(|| "not signed".to_string());
+    println!("Signed fields:");
+    println!("  Document ID:  {}", unknown(document_id));
+    println!("  Image digest: {}", unknown(image_digest));
+    println!("  Packages:     {}", packages);
+    println!("  Created:      {}", unknown(created));
+}
+
+// SPDX output relates the image to each layer, and each layer to each of its
+// packages, by CONTAINS
+fn is_layer_package_relationship<'a>(mut relationship: impl Iterator<Item = &'a str>) -> bool {
+    let (Some(element), Some(kind)) = (relationship.next(), relationship.next()) else {
+        return false;
+    };
+    kind == "CONTAINS" && element != "SPDXRef-Image" && relationship.next().is_some()
+}
+
+// Re-checks every file the SBOM recorded a checksum for against freshly
+// analyzed layers. Files are looked up in the layer with the same ID, or in
+// the merged filesystem if the image was read through a source that names
+// layers differently. Files the SBOM skipped (or never listed, because of
+// filters used at generation time) are not checked. Returns the number of
+// files checked and a description of each mismatch.
+fn compare_file_checksums(sbom: &Sbom, live_layers: &[Layer]) -> (usize, Vec<String>) {
+    let mut by_layer: HashMap<(&str, &str), &str> = HashMap::new();
+    let mut merged: HashMap<&str, &str> = HashMap::new();
+    for layer in live_layers {
+        for file in layer.files.iter().filter(|file| file.file_type == "file") {
+            let path = checks::normalize_path(&file.path);
+            by_layer.insert((layer.layer_id.as_str(), path), file.checksum.as_str());
+            merged.insert(path, file.checksum.as_str());
+        }
+    }
+
+    let mut checked = 0;
+    let mut mismatches = Vec::new();
+    for layer in &sbom.layers {
+        for file in layer.files.iter().filter(|file| file.file_type == "file" && file.checksum != SKIPPED_CHECKSUM) {
+            for path in std::iter::once(&file.path).chain(&file.duplicate_paths) {
+                let path = checks::normalize_path(path);
+                checked += 1;
+                let live = by_layer.get(&(layer.layer_id.as_str(), path)).or_else(|| merged.get(path));
+                match live {
+                    None => mismatches.push(format!("/{}: missing from the image", path)),
+                    Some(&checksum) if checksum != file.checksum => {
+                        mismatches.push(format!("/{}: expected {}, found {}", path, file.checksum, checksum))
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+    }
+    (checked, mismatches)
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).unwrap();
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+fn inspect_platform(image_inspect: &ImageInspect) -> Option<String> {
+    let platform = format!("{}/{}", image_inspect.os.as_ref()?, image_inspect.architecture.as_ref()?);
+    match &image_inspect.variant {
+        Some(variant) if !variant.is_empty() => Some(format!("{}/{}", platform, variant)),
+        _ => Some(platform),
+    }
+}
+
+// The repo digest matching the image's repository, if it was pulled from or
+// pushed to a registry
+fn inspect_repo_digest(image_name: &str, image_inspect: &ImageInspect) -> Option<String> {
+    let repo_digests = image_inspect.repo_digests.as_ref()?;
+    let image = registry::ImageReference::parse(image_name);
+    let same_repository = |repo_digest: &&String| {
+        let other = registry::ImageReference::parse(repo_digest);
+        other.registry == image.registry && other.repository == image.repository
+    };
+    let repo_digest = repo_digests.iter().find(same_repository).or_else(|| repo_digests.first())?;
+    repo_digest.rsplit_once('@').map(|(_, digest)| digest.to_string())
+}
+
+// The image's build steps, oldest first, as recorded in an image config's history
+async fn image_history(image_name: &str) -> Vec<registry::HistoryEntry> {
+    let history = match daemon::connect().await {
+        Ok(docker) => docker.image_history(image_name).await.unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    history
+        .into_iter()
+        .rev()
+        .map(|entry| {
+            // The daemon's history has no empty_layer flag: steps that only
+            // change metadata are told apart by their instruction (#(nop) for
+            // the classic builder) and by adding no bytes
+            let command = entry.created_by.trim_start_matches("/bin/sh -c ").trim_start();
+            let metadata_only = match command.strip_prefix("#(nop)") {
+                Some(instruction) => !matches!(instruction.split_whitespace().next(), Some("ADD" | "COPY")),
+                None => matches!(
+                    command.split_whitespace().next(),
+                    Some("ENV" | "LABEL" | "CMD" | "ENTRYPOINT" | "EXPOSE" | "USER" | "VOLUME" | "ARG" | "HEALTHCHECK" | "SHELL" | "STOPSIGNAL" | "ONBUILD" | "MAINTAINER")
+                ),
+            };
+            registry::HistoryEntry {
+                created: chrono::DateTime::from_timestamp(entry.created, 0).map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+                created_by: Some(entry.created_by),
+                empty_layer: metadata_only && entry.size == 0,
+            }
+        })
+        .collect()
+}
+
+fn inspect_healthcheck(image_inspect: &ImageInspect) -> Option<Healthcheck> {
+    Healthcheck::from_config(image_inspect.config.as_ref()?.healthcheck.as_ref()?)
+}
+
+fn inspect_labels(image_inspect: &ImageInspect) -> BTreeMap<String, String> {
+    let labels = image_inspect.config.as_ref().and_then(|config| config.labels.clone());
+    labels.map(|labels| labels.into_iter().collect()).unwrap_or_default()
+}
+
+// The image's ENTRYPOINT and CMD
+fn inspect_process(image_inspect: &ImageInspect) -> (Option<Vec<String>>, Option<Vec<String>>) {
+    match &image_inspect.config {
+        Some(config) => (config.entrypoint.clone(), config.cmd.clone()),
+        None => (None, None),
+    }
+}
+
+fn parse_platform(value: &str) -> Result<String, String> {
+    let parts: Vec<&str> = value.split('/').collect();
+    match (2..=3).contains(&parts.len()) && parts.iter().all(|part| !part.is_empty()) {
+        true => Ok(value.to_string()),
+        false => Err("expected OS/ARCH or OS/ARCH/VARIANT, e.g. linux/arm64".to_string()),
+    }
+}
+
+// Derives a per-platform file name, e.g. sbom.json -> sbom-linux-arm64.json
+fn platform_output_path(output: &str, platform: &str) -> String {
+    let path = Path::new(output);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("sbom");
+    let file_name = match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!("{}-{}.{}", stem, platform.replace('/', "-"), ext),
+        None => format!("{}-{}", stem, platform.replace('/', "-")),
+    };
+    path.with_file_name(file_name).display().to_string()
+}
+
+// Names an SBOM written to --output-dir. {image} is the image reference made
+// safe for file names, {platform} the analyzed platform (empty unless
+// --all-platforms), {date} the UTC date and {ext} the extension of the format.
+fn templated_output_path(dir: &str, template: &str, image_name: &str, platform: Option<&str>, output_format: &str) -> String {
+    let file_name = template
+        .replace("{image}", &sanitize_file_name(image_name))
+        .replace("{platform}", &platform.map(sanitize_file_name).unwrap_or_default())
+        .replace("{date}", &chrono::Utc::now().format("%Y-%m-%d").to_string())
+        .replace("{ext}", file_extension(output_format));
+    let path = Path::new(dir).join(file_name).display().to_string();
+    // Keep per-platform SBOMs apart even if the template leaves out {platform}
+    match platform {
+        Some(platform) if !template.contains("{platform}") => platform_output_path(&path, platform),
+        _ => path,
+    }
+}
+
+fn file_extension(output_format: &str) -> &str {
+    match output_format {
+        "spdx-json" => "spdx.json",
+        "cyclonedx" => "cdx.json",
+        _ => output_format,
+    }
+}
+
+// Registry, repository, tag and digest separators become '_', e.g.
+// ghcr.io/org/app:1.0 -> ghcr.io_org_app_1.0
+fn sanitize_file_name(value: &str) -> String {
+    let name: String = value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect();
+    name.trim_start_matches('.').to_string()
+}
+
+// A progress bar on stderr, drawn with the given template unless --quiet was
+// given; indicatif also hides it when stderr is not a terminal
+fn progress_bar(length: u64, template: &str) -> ProgressBar {
+    if progress::quiet() {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new(length);
+    pb.set_style(ProgressStyle::default_bar()
+        .template(template)
+        .expect("Error setting progress bar template")
+        .progress_chars("#>-"));
+    pb
+}
+
+// Draws layer progress on the terminal
+fn layer_progress_bar() -> ProgressReporter {
+    let pb = progress_bar(0, "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} layers {msg}");
+    ProgressReporter::new(std::sync::Arc::new(move |event| {
+        let percent = event.percent();
+        match event {
+            Progress::LayerStarted { index, total, layer_id } => {
+                pb.set_length(total as u64);
+                pb.set_message(format!("layer {}: {}", index + 1, layer_id.chars().take(19).collect::<String>()));
+            }
+            Progress::LayerFinished { index, total, completed, files } => {
+                pb.set_length(total as u64);
+                pb.set_position(completed as u64);
+                debug!(layer = index, files, percent, "layer analyzed");
+            }
+            Progress::Finished { layers, cached } => {
+                pb.finish_and_clear();
+                debug!(layers, cached, "image analysis finished");
+            }
+        }
+    }))
+}
+
+// Pulls the image unless the daemon already has it for `platform`, and
+// returns what the daemon knows of it
+#[instrument(name = "pull", skip_all, fields(image = %image_name))]
+async fn ensure_image_exists(image_name: &str, platform: Option<&str>) -> registry::Result<ImageInspect> {
+    let docker = daemon::connect().await?;
+
+    match docker.inspect_image(image_name).await {
+        Ok(image_inspect) if platform.is_none_or(|requested| inspect_platform(&image_inspect).is_some_and(|local| registry::platform_matches(&local, requested))) => Ok(image_inspect),
+        _ => {
+            let options = Some(CreateImageOptions {
+                from_image: image_name,
+                platform: platform.unwrap_or_default(),
+                ..Default::default()
+            });
+            let registry = registry::ImageReference::parse(image_name).registry;
+            let credentials = credentials::lookup(&registry).map(|credentials| credentials.docker_credentials(&registry));
+            let mut stream = docker.create_image(options, None, credentials);
+
+            let pb = progress_bar(0, "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} ({eta}) {msg}");
+            let mut pull = progress::PullProgress::default();
+            while let Some(result) = stream.next().await {
+                let info = result?;
+                pull.update(&info);
+                pb.set_length(pull.size());
+                pb.set_position(pull.downloaded());
+                if let Some(status) = &info.status {
+                    pb.set_message(status.clone());
+                }
+            }
+            pb.finish_with_message("Image download complete.");
+            info!("image pulled");
+
+            // Daemons without multi-platform support pull their own platform
+            let image_inspect = docker.inspect_image(image_name).await?;
+            if let Some(requested) = platform {
+                let pulled = inspect_platform(&image_inspect).unwrap_or_else(|| "an unknown platform".to_string());
+                if !registry::platform_matches(&pulled, requested) {
+                    return Err(format!("the daemon pulled {} for {}, not the requested {}", pulled, image_name, requested).into());
+                }
+            }
+            Ok(image_inspect)
+        }
+    }
+}
+
+// Builds the Dockerfile, up to `stage` if given, as --target does
+#[instrument(name = "build", skip_all, fields(image = %image_name))]
+async fn build_dockerfile_image(dockerfile_path: &str, image_name: &str, stage: Option<&str>) -> registry::Result<()> {
+    let docker = daemon::connect().await?;
+
+    let options = BuildImageOptions {
+        t: image_name.to_string(),
+        target: stage.unwrap_or_default().to_string(),
+        rm: true,
+        ..Default::default()
+    };
+
+    let build_dir = tempdir()?;
+    let tar_path = create_tarball(dockerfile_path, build_dir.path())?;
+    let tar_file = fs::read(tar_path)?;
+    let body = Bytes::from(tar_file);
+
+    let mut stream = docker.build_image(options, None, Some(body));
+
+    // The classic builder numbers its steps as "Step 2/7 : RUN ..."
+    let pb = progress_bar(0, "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} steps");
+    while let Some(result) = stream.next().await {
+        match result {
+            Ok(BuildInfo { stream: Some(stream), error: None, .. }) => {
+                let step = stream.strip_prefix("Step ").and_then(|step| step.split_once(' ')).and_then(|(step, _)| step.split_once('/'));
+                if let Some((Ok(current), Ok(total))) = step.map(|(current, total)| (current.parse::<u64>(), total.parse::<u64>())) {
+                    pb.set_length(total);
+                    pb.set_position(current.saturating_sub(1));
+                }
+                // Build output is a diagnostic; stdout is kept for the SBOM
+                eprint!("{}", stream);
+            }
+            Ok(BuildInfo { error: Some(error), .. }) => {
+                error!(error = %error, "error building image");
+                return Err(error.into());
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!(error = %e, "error building image");
+                return Err(e.into());
+            }
+        }
+    }
+    pb.set_position(pb.length().unwrap_or(0));
+    pb.finish_with_message("Image build complete.");
+    Ok(())
+}
+
+fn create_tarball(dockerfile_path: &str, dir: &Path) -> Result<PathBuf, std::io::Error> {
+    let tar_path = dir.join("dockerfile.tar");
+    let file = File::create(&tar_path)?;
+    let mut builder = Builder::new(file);
+
+    let dockerfile_name = Path::new(dockerfile_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("Dockerfile");
+
+    builder.append_path_with_name(dockerfile_path, dockerfile_name)?;
+    
+    // Add all files in the same directory as the Dockerfile to the tarball
+    let parent_dir = Path::new(dockerfile_path).parent().unwrap_or(Path::new("."));
+    for entry in read_dir(parent_dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_file() {
+            builder.append_path_with_name(&path, path.file_name().unwrap())?;
+        }
+    }
+
+    builder.finish()?;
+    Ok(tar_path)
+}
+
+// Layers are read from one exported archive, each at its own offset, so up to
+// `jobs` of them are analyzed at once without writing anything per layer; they
+// are returned in image order whichever finishes first.
+#[instrument(name = "analyze", skip_all, fields(image = %image_name))]
+async fn analyze_image(
+    image_name: &str,
+    image_inspect: &ImageInspect,
+    jobs: usize,
+    options: ExtractOptions,
+    progress: &ProgressReporter,
+) -> registry::Result<Vec<Layer>> {
+    let docker = daemon::connect().await?;
+    let layers = image_inspect.root_fs.as_ref().and_then(|root_fs| root_fs.layers.clone()).unwrap_or_default();
+
+    let temp_dir = spool_image_export(image_name, docker.export_image(image_name), &std::env::temp_dir())
+        .instrument(tracing::debug_span!("export"))
+        .await?;
+    let archive_path = temp_dir.path().join(EXPORTED_IMAGE);
+    let layer_entries = archive::image_archive_layers(&archive_path).map_err(|e| format!("cannot read the exported image: {}", e))?;
+    if layer_entries.len() != layers.len() {
+        return Err(format!("the exported image has {} layers where {} were expected", layer_entries.len(), layers.len()).into());
+    }
+
+    let total = layers.len();
+    let created = image_inspect.created.clone().unwrap_or_else(|| "Unknown".to_string());
+    let os_guess = image_inspect.os.clone().unwrap_or_else(|| "Unknown".to_string());
+    let completed = Arc::new(AtomicUsize::new(0));
+    let analyzed_layers: Vec<Layer> = stream::iter(layers.into_iter().enumerate().zip(layer_entries))
+        .map(|((index, layer_id), entry)| {
+            let archive_path = archive_path.clone();
+            let created = created.clone();
+            let os_guess = os_guess.clone();
+            let completed = completed.clone();
+            let progress = progress.clone();
+            tokio::task::spawn_blocking(move || {
+                progress.report(Progress::LayerStarted { index, total, layer_id: layer_id.clone() });
+                let layer = cache::analyze_layer(options, &layer_id, &created, &os_guess, || {
+                    let reader = archive::archive_layer_reader(&archive_path, entry)
+                        .map_err(|e| format!("cannot read layer {} from the exported image: {}", layer_id, e))?;
+                    let extracted = analyze_layer_files(reader, options)?;
+
+                    // Identify packages
+                    let detected = analyze_layer_for_packages(&extracted, options.root_prefix);
+
+                    // Perform analysis on each layer
+                    Ok(Layer {
+                        layer_id: layer_id.clone(),
+                        created: created.clone(),
+                        os_guess: os_guess.clone(),
+                        pkg_format: detected.format.to_string(),
+                        packages: detected.packages,
+                        files: extracted.files,
+                        notices: detected.notices,
+                        analyzed_output: String::new(),
+                        created_by: None,
+                        contents: extracted.contents,
+                        links: extracted.links,
+                        dir_modes: extracted.dir_modes,
+                        root_prefix: options.root_prefix,
+                    })
+                });
+                progress.report(Progress::LayerFinished {
+                    index,
+                    total,
+                    completed: completed.fetch_add(1, Ordering::SeqCst) + 1,
+                    files: layer.as_ref().map_or(0, |layer| layer.files.len()),
+                });
+                layer
+            })
+        })
+        .buffered(jobs.max(1))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .map(|joined| joined?)
+        .collect::<registry::Result<_>>()?;
+
+    info!(layers = analyzed_layers.len(), "image analyzed");
+    progress.report(Progress::Finished { layers: analyzed_layers.len(), cached: false });
+    Ok(analyzed_layers)
+}
+
+const EXPORTED_IMAGE: &str = "image.tar";
+
+// Writes an exported image to a new directory under `temp_root`, which is
+// removed with everything in it when the returned TempDir is dropped. If the
+// export fails partway, the partly written archive is removed before this
+// returns.
+async fn spool_image_export<E: std::fmt::Display>(
+    image_name: &str,
+    export: impl futures_util::Stream<Item = Result<Bytes, E>>,
+    temp_root: &Path,
+) -> registry::Result<tempfile::TempDir> {
+    let temp_dir = tempfile::tempdir_in(temp_root)?;
+    let mut archive_file = File::create(temp_dir.path().join(EXPORTED_IMAGE))?;
+    let mut export = std::pin::pin!(export);
+    while let Some(chunk) = export.next().await {
+        match chunk {
+            Ok(bytes) => archive_file.write_all(&bytes).map_err(|e| format!("cannot write the exported image: {}", e))?,
+            Err(e) => return Err(format!("cannot export {}: {}", image_name, e).into()),
+        }
+    }
+    Ok(temp_dir)
+}
+
+// Files whose contents are kept around after hashing for later inspection
+fn retain_content(path: &str) -> bool {
+    checks::is_certificate_path(path)
+        || detectors::is_busybox_path(path)
+        || detectors::is_conda_meta_path(path)
+        || detectors::is_composer_installed_path(path)
+        || detectors::is_install_reason_path(path)
+        || detectors::is_os_release_path(path)
+        || detectors::is_shell_config_path(path)
+        || pip::is_python_metadata_path(path)
+        || npm::is_package_manifest_path(path)
+        || systemd::is_unit_path(path)
+        || matches!(checks::normalize_path(path), APK_INSTALLED | DPKG_STATUS | rpmdb::RPMDB_SQLITE)
+}
+
+#[instrument(name = "extraction", level = "debug", skip_all)]
+fn analyze_layer_files<R: Read>(reader: R, options: ExtractOptions) -> registry::Result<ExtractedFiles> {
+    let mut archive = Archive::new(reader);
+
+    let mut extracted = ExtractedFiles::default();
+    for file in archive.entries().map_err(|e| format!("cannot read layer: {}", e))? {
+        let mut file = file.map_err(|e| format!("cannot read layer entry: {}", e))?;
+        let path = file.path().map_err(|e| format!("cannot read layer entry path: {}", e))?.display().to_string();
+        let size = file.size();
+        let entry_type = file.header().entry_type();
+        let is_file = entry_type.is_file();
+        let link = match file.link_name() {
+            Ok(Some(target)) if entry_type.is_symlink() || entry_type.is_hard_link() => Some(
+                detectors::resolve_link_target(&path, &target.display().to_string(), entry_type.is_hard_link()),
+            ),
+            _ => None,
+        };
+        if let (true, Ok(mode), Some(rooted)) = (entry_type.is_dir(), file.header().mode(), checks::strip_root(&path, options.root_prefix)) {
+            extracted.dir_modes.insert(rooted.to_string(), mode & 0o7777);
+        }
+
+        // Filtered and oversized entries are skipped over by the archive
+        // reader unread
+        let retain = is_file && options.retains(checks::strip_root(&path, options.root_prefix));
+        if options.skips(&path, retain) {
+            continue;
+        }
+        let mode = file.header().mode().unwrap_or(0);
+        let inspection = Inspection::of(is_file.then(|| checks::strip_root(&path, options.root_prefix)).flatten(), mode);
+        let contents = match options.reads(size, retain, inspection) {
+            true => Some(read_contents(&mut file, retain, inspection).map_err(|e| format!("cannot read {}: {}", path, e))?),
+            false => None,
+        };
+        record_file(&mut extracted, path, size, is_file, link, contents, options);
+    }
+
+    debug!(files = extracted.files.len(), "layer extracted");
+    Ok(extracted)
+}
+
+// What a regular file is read for besides its checksum, given its path in
+// the analyzed root
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Inspection<'a> {
+    Nothing,
+    // Searched for Go build info
+    Executable,
+    // Spooled to a temporary file for the artifacts it records
+    JavaArchive { file_name: &'a str },
+}
+
+impl<'a> Inspection<'a> {
+    fn of(rooted: Option<&'a str>, mode: u32) -> Inspection<'a> {
+        match rooted {
+            Some(rooted) if java::is_java_archive_path(rooted) => Inspection::JavaArchive {
+                file_name: rooted.rsplit('/').next().unwrap_or(rooted),
+            },
+            Some(_) if mode & 0o111 != 0 => Inspection::Executable,
+            _ => Inspection::Nothing,
+        }
+    }
+}
+
+// What was read of a file: its checksum, its contents when they are kept for
+// inspection, the build info of a Go binary and the artifacts of a Java
+// archive
+struct FileContents {
+    checksum: String,
+    retained: Option<Vec<u8>>,
+    go_build_info: Option<golang::BuildInfo>,
+    java_artifacts: Option<Vec<java::Artifact>>,
+}
+
+// Hashes a file's contents, holding them in memory only when they are kept.
+// A Java archive, whose index is at its end, is spooled to a temporary file
+// to be read from there; everything else streams through the hasher a buffer
+// at a time. Executables are searched for Go build info along the way.
+fn read_contents<R: Read>(reader: R, retain: bool, inspection: Inspection) -> std::io::Result<FileContents> {
+    let mut hasher = Sha256::new();
+    let mut scanner = (inspection == Inspection::Executable).then(golang::BuildInfoScanner::default);
+    let mut java_artifacts = None;
+    let retained = if retain {
+        let mut buffer = Vec::new();
+        BufReader::new(reader).read_to_end(&mut buffer)?;
+        hasher.update(&buffer);
+        if let Some(scanner) = &mut scanner {
+            scanner.update(&buffer);
+        }
+        if let Inspection::JavaArchive { file_name } = inspection {
+            java_artifacts = Some(java::read_archive(Cursor::new(&buffer), file_name));
+        }
+        Some(buffer)
+    } else if let Inspection::JavaArchive { file_name } = inspection {
+        let mut spool = tempfile::tempfile()?;
+        for_each_chunk(reader, |chunk| {
+            hasher.update(chunk);
+            spool.write_all(chunk)
+        })?;
+        spool.rewind()?;
+        java_artifacts = Some(java::read_archive(BufReader::new(spool), file_name));
+        None
+    } else {
+        for_each_chunk(reader, |chunk| {
+            hasher.update(chunk);
+            if let Some(scanner) = &mut scanner {
+                scanner.update(chunk);
+            }
+            Ok(())
+        })?;
+        None
+    };
+    Ok(FileContents {
+        checksum: format!("{:x}", hasher.finalize()),
+        retained,
+        go_build_info: scanner.and_then(golang::BuildInfoScanner::finish),
+        java_artifacts,
+    })
+}
+
+// Hands the reader's contents to `process` a buffer at a time
+fn for_each_chunk<R: Read>(reader: R, mut process: impl FnMut(&[u8]) -> std::io::Result<()>) -> std::io::Result<()> {
+    let mut reader = BufReader::with_capacity(HASH_BUFFER_SIZE, reader);
+    loop {
+        let chunk = match reader.fill_buf() {
+            Ok([]) => return Ok(()),
+            Ok(chunk) => chunk,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        process(chunk)?;
+        let read = chunk.len();
+        reader.consume(read);
+    }
+}
+
+// Missing contents mean the file was too large to read and is recorded
+// without a checksum. Contents and links are keyed by their path in the
+// analyzed root and left out for entries outside it.
+fn record_file(
+    extracted: &mut ExtractedFiles,
+    path: String,
+    size: u64,
+    is_file: bool,
+    link: Option<String>,
+    contents: Option<FileContents>,
+    options: ExtractOptions,
+) {
+    let rooted = checks::strip_root(&path, options.root_prefix);
+    let file_type = match is_file {
+        true => "file".to_string(),
+        false => "dir".to_string(),
+    };
+
+    let checksum = match contents {
+        Some(FileContents { checksum, retained, go_build_info, java_artifacts }) => {
+            if let (Some(buffer), Some(rooted)) = (retained, rooted) {
+                extracted.contents.insert(rooted.to_string(), buffer);
+            }
+            if let (Some(info), Some(rooted)) = (go_build_info, rooted) {
+                extracted.go_binaries.insert(rooted.to_string(), info);
+            }
+            if let (Some(artifacts), Some(rooted)) = (java_artifacts.filter(|artifacts| !artifacts.is_empty()), rooted) {
+                extracted.java_archives.insert(rooted.to_string(), artifacts);
+            }
+            checksum
+        }
+        None => SKIPPED_CHECKSUM.to_string(),
+    };
+    if let (Some(target), Some(rooted)) = (link, rooted) {
+        // Absolute symlink targets already name a path in the analyzed root
+        let target = checks::strip_root(&target, options.root_prefix).map_or(target.clone(), str::to_string);
+        extracted.links.insert(rooted.to_string(), target);
+    }
+
+    extracted.files.push(FileMetadata {
+        path,
+        size,
+        file_type,
+        checksum,
+        duplicate_paths: Vec::new(),
+    });
+}
+
+// Collapses regular files with identical content into a single entry that
+// lists every path, keeping the first path as the entry's own.
+fn dedup_files(files: Vec<FileMetadata>) -> Vec<FileMetadata> {
+    let mut deduped: Vec<FileMetadata> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for file in files {
+        if file.file_type != "file" || file.checksum == SKIPPED_CHECKSUM {
+            deduped.push(file);
+            continue;
+        }
+        match seen.get(&file.checksum) {
+            Some(&index) => deduped[index].duplicate_paths.push(file.path),
+            None => {
+                seen.insert(file.checksum.clone(), deduped.len());
+                deduped.push(file);
+            }
+        }
+    }
+    deduped
+}
+
+const APK_INSTALLED: &str = "lib/apk/db/installed";
+
+// Packages read from a layer's package databases and the format of the
+// database the layer carries, with notices about databases that were found
+// but could not be read
+struct LayerPackages {
+    packages: Vec<Package>,
+    format: &'static str,
+    notices: Vec<Notice>,
+}
+
+// Reads the package database from the layer's retained contents, so it is
+// found however the layer was produced: a package manager run, or a whole
+// root filesystem copied into a `FROM scratch` image
+#[instrument(name = "detection", level = "debug", skip_all)]
+fn analyze_layer_for_packages(extracted: &ExtractedFiles, root_prefix: Option<&str>) -> LayerPackages {
+    let contents = &extracted.contents;
+    let mut packages = Vec::new();
+    let mut notices = Vec::new();
+
+    if let Some(apk_db) = contents.get(APK_INSTALLED) {
+        let apk_db = String::from_utf8_lossy(apk_db);
+        packages.extend(stanza::read_stanzas(&apk_db).iter().filter_map(apk_package));
+        resolve_apk_depends(&mut packages);
+    }
+    if let Some(status) = contents.get(DPKG_STATUS) {
+        packages.extend(parse_dpkg_status(&String::from_utf8_lossy(status)));
+    }
+    if let Some(database) = contents.get(rpmdb::RPMDB_SQLITE) {
+        match rpmdb::read_sqlite_packages(database) {
+            Ok(rpm_packages) => packages.extend(rpm_packages),
+            Err(e) => notices.push(Notice {
+                message: format!("Unreadable RPM database /{}: {}", rpmdb::RPMDB_SQLITE, e),
+                level: "warning".to_string(),
+                location: None,
+                rule: None,
+            }),
+        }
+    } else if let Some(path) = extracted
+        .files
+        .iter()
+        .filter_map(|file| checks::strip_root(&file.path, root_prefix))
+        .find(|path| rpmdb::RPMDB_UNSUPPORTED.contains(path))
+    {
+        notices.push(Notice {
+            message: format!("RPM database /{} is in a format that cannot be read (only rpmdb.sqlite is supported); its packages are not listed", path),
+            level: "warning".to_string(),
+            location: None,
+            rule: None,
+        });
+    }
+
+    let unhashed = extracted.files.iter().filter(|file| file.checksum == SKIPPED_CHECKSUM).count();
+    if unhashed > 0 {
+        notices.push(Notice {
+            message: format!("{} files larger than --max-file-size were recorded without a checksum", unhashed),
+            level: "info".to_string(),
+            location: None,
+            rule: None,
+        });
+    }
+
+    packages.extend(pip::read_python_packages(contents));
+    packages.extend(npm::read_npm_packages(contents, &extracted.links));
+    packages.extend(golang::read_go_packages(&extracted.go_binaries));
+    packages.extend(java::read_maven_packages(&extracted.java_archives));
+
+    LayerPackages {
+        packages,
+        format: detect_pkg_format(extracted, root_prefix),
+        notices,
+    }
+}
+
+// The package database a layer writes, told apart by where it lives; "pip"
+// or "npm" for layers that only install Python or Node packages, "go" or
+// "maven" for layers that only add Go binaries or Java archives and "unknown"
+// for layers that leave package databases alone
+fn detect_pkg_format(extracted: &ExtractedFiles, root_prefix: Option<&str>) -> &'static str {
+    let paths: Vec<&str> = extracted.files.iter().filter_map(|file| checks::strip_root(&file.path, root_prefix)).collect();
+    if paths.contains(&APK_INSTALLED) {
+        "apk"
+    } else if paths.contains(&DPKG_STATUS) {
+        "deb"
+    } else if paths.iter().any(|path| path.starts_with("var/lib/rpm/") || path.starts_with("usr/lib/sysimage/rpm/")) {
+        "rpm"
+    } else if paths.iter().any(|path| pip::is_python_metadata_path(path)) {
+        "pip"
+    } else if paths.iter().any(|path| npm::is_package_manifest_path(path)) {
+        "npm"
+    } else if !extracted.go_binaries.is_empty() {
+        "go"
+    } else if !extracted.java_archives.is_empty() {
+        "maven"
+    } else {
+        "unknown"
+    }
+}
+
+const DPKG_STATUS: &str = "var/lib/dpkg/status";
+
+// apk's installed database has one stanza per package, with single-letter
+// fields: P: name, V: version, L: license, o: origin, t: build time, C:
+// checksum, D: and p: dependencies and provides
+fn apk_package(stanza: &stanza::Stanza) -> Option<Package> {
+    let list = |name: &str| -> Vec<String> { stanza.field(name).unwrap_or_default().split_whitespace().map(str::to_string).collect() };
+    Some(Package {
+        name: stanza.field("P").filter(|name| !name.is_empty())?.to_string(),
+        version: stanza.field("V").unwrap_or_default().to_string(),
+        source: String::new(),
+        license: stanza.field("L").unwrap_or_default().to_string(),
+        vendor: stanza.field("o").unwrap_or_default().to_string(),
+        checksum: stanza.field("C").map(apk_checksum).unwrap_or_default(),
+        commands: Vec::new(),
+        purl: None,
+        install_reason: None,
+        depends: list("D"),
+        provides: list("p"),
+        // apk records build times as Unix epoch seconds
+        built_at: stanza.field("t").and_then(epoch_to_rfc3339),
+        detection: Some(Detection::new(&format!("/{}", APK_INSTALLED), "apk", Confidence::High)),
+        spdx_license: None,
+        vulnerabilities: Vec::new(),
+    })
+}
+
+// The C: field is Q1 followed by the base64 SHA-1 digest of the package's
+// control segment; it is kept as hex like other checksums. Other forms are
+// kept as written.
+fn apk_checksum(value: &str) -> String {
+    value
+        .strip_prefix("Q1")
+        .and_then(|digest| BASE64.decode(digest.as_bytes()).ok())
+        .filter(|digest| digest.len() == 20)
+        .map(|digest| digest.iter().map(|b| format!("{:02x}", b)).collect())
+        .unwrap_or_else(|| value.to_string())
+}
+
+// dpkg's status file has one stanza per package. Packages that were removed
+// but left their configuration behind are listed too, and are skipped.
+fn parse_dpkg_status(status: &str) -> Vec<Package> {
+    stanza::read_stanzas(status).iter().filter_map(dpkg_package).collect()
+}
+
+fn dpkg_package(stanza: &stanza::Stanza) -> Option<Package> {
+    let field = |name: &str| stanza.field_ignore_case(name);
+    if !field("Status").is_some_and(|status| status.ends_with(" installed")) {
+        return None;
+    }
+    // Relationship fields list `name (>= version)` entries; of alternatives
+    // (`a | b`) the first is taken
+    let names = |value: Option<&str>| -> Vec<String> {
+        value
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|entry| entry.split('|').next()?.split_whitespace().next())
+            .map(|name| name.split(':').next().unwrap_or(name).to_string())
+            .collect()
+    };
+    Some(Package {
+        name: field("Package")?.to_string(),
+        version: field("Version").unwrap_or("unknown").to_string(),
+        source: field("Homepage").unwrap_or_default().to_string(),
+        license: String::new(),
+        vendor: field("Maintainer").unwrap_or_default().to_string(),
+        checksum: String::new(),
+        commands: Vec::new(),
+        purl: None,
+        install_reason: None,
+        depends: {
+            let mut depends = names(field("Pre-Depends"));
+            depends.extend(names(field("Depends")));
+            depends.sort();
+            depends.dedup();
+            depends
+        },
+        provides: names(field("Provides")),
+        built_at: None,
+        detection: Some(Detection::new(&format!("/{}", DPKG_STATUS), "dpkg", Confidence::High)),
+        spdx_license: None,
+        vulnerabilities: Vec::new(),
+    })
+}
+
+// When the SBOM was created: now, or SOURCE_DATE_EPOCH when set so that
+// reproducible pipelines get byte-identical output
+fn creation_time() -> String {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch_to_rfc3339(epoch.trim()))
+        .unwrap_or_else(|| chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+// A unique document namespace as SPDX recommends, with a random UUID. With
+// SOURCE_DATE_EPOCH set the UUID is derived from the image and the creation
+// time instead, so that reproducible pipelines still get identical output.
+fn default_namespace(name: &str, image_digest: &str, platform: Option<&str>, created: &str) -> String {
+    let uuid = match std::env::var_os("SOURCE_DATE_EPOCH") {
+        Some(_) => uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, format!("{} {} {} {}", name, image_digest, platform.unwrap_or_default(), created).as_bytes()),
+        None => uuid::Uuid::new_v4(),
+    };
+    let name: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || ".-_".contains(c) { c } else { '-' }).collect();
+    format!("https://spdx.org/spdxdocs/{}-{}", name, uuid)
+}
+
+// SPDX creators name their kind first
+fn parse_creator(value: &str) -> Result<String, String> {
+    match value.split_once(':') {
+        Some(("Person" | "Organization" | "Tool", name)) if !name.trim().is_empty() => Ok(value.to_string()),
+        _ => Err("expected 'Person: NAME', 'Organization: NAME' or 'Tool: NAME'".to_string()),
+    }
+}
+
+fn epoch_to_rfc3339(value: &str) -> Option<String> {
+    let seconds = value.trim().parse::<i64>().ok()?;
+    let time = chrono::DateTime::from_timestamp(seconds, 0)?;
+    Some(time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+// Replaces each apk dependency (a package name, `so:` library or `cmd:`
+// command, optionally with a version constraint) with the name of the
+// installed package providing it. Conflicts (`!name`) are not dependencies.
+fn resolve_apk_depends(packages: &mut [Package]) {
+    let mut providers = HashMap::new();
+    for package in packages.iter() {
+        providers.entry(package.name.clone()).or_insert_with(|| package.name.clone());
+        for provided in &package.provides {
+            let name = provided.split('=').next().unwrap_or(provided);
+            providers.entry(name.to_string()).or_insert_with(|| package.name.clone());
+        }
+    }
+
+    for package in packages.iter_mut() {
+        let mut depends: Vec<String> = package
+            .depends
+            .iter()
+            .filter(|dependency| !dependency.starts_with('!'))
+            .map(|dependency| {
+                let name = dependency.split(['<', '>', '=', '~']).next().unwrap_or(dependency);
+                providers.get(name).cloned().unwrap_or_else(|| dependency.clone())
+            })
+            .filter(|name| *name != package.name)
+            .collect();
+        depends.sort();
+        depends.dedup();
+        package.depends = depends;
+    }
+}
+
+// With a stage, only the instructions from that stage's FROM up to the next
+// FROM are analyzed; instructions before the first FROM belong to no stage.
+// Packages are those installed by the last analyzed stage, the one the image
+// is built from, and by the stages it starts FROM, whose filesystem it
+// inherits, unless all stages are asked for: other build stages install
+// compilers and tooling that do not end up in the image.
+fn analyze_dockerfile(dockerfile_path: &str, stage: Option<&str>, all_stages: bool) -> registry::Result<DockerfileAnalysis> {
+    let mut envs = BTreeMap::new();
+    let mut instructions = Vec::new();
+    let mut packages = Vec::new();
+    let mut copies = Vec::new();
+
+    let dockerfile_content = fs::read_to_string(dockerfile_path)?;
+    let line_of = |inst: &Instruction| dockerfile_content[..inst.span().start].matches('\n').count() + 1;
+
+    let parser = Dockerfile::parse(dockerfile_content.as_str())?;
+    let stages = parser.stages();
+    let analyzed: Vec<&Stage> = match stage {
+        Some(name) => match stages.get_by_name(name) {
+            Some(stage) => vec![stage],
+            None => {
+                let names: Vec<String> = stages.iter().filter_map(|stage| stage.name.clone()).collect();
+                return Err(match names.is_empty() {
+                    true => format!("stage {} not found; the Dockerfile has no named stages", name).into(),
+                    false => format!("stage {} not found; named stages are: {}", name, names.join(", ")).into(),
+                });
+            }
+        },
+        None => stages.iter().collect(),
+    };
+    let mut selected: Vec<(Option<usize>, &Instruction)> = match stage {
+        Some(_) => Vec::new(),
+        None => parser.instructions.iter().take_while(|inst| !matches!(inst, Instruction::From(_))).map(|inst| (None, inst)).collect(),
+    };
+    let mut stage_infos = Vec::new();
+    for stage in &analyzed {
+        selected.extend(stage.instructions.iter().map(|inst| (Some(stage_infos.len()), *inst)));
+        stage_infos.push(StageInfo {
+            index: stage.index,
+            name: stage.name.clone(),
+            base: match stage.instructions[0] {
+                Instruction::From(from) => from.image.content.clone(),
+                _ => stage.parent.to_string(),
+            },
+            line: line_of(stage.instructions[0]),
+            instructions: Vec::new(),
+        });
+    }
+
+    for (stage_position, inst) in selected {
+        let line = line_of(inst);
+        if let Some(position) = stage_position {
+            stage_infos[position].instructions.push(instructions.len());
+        }
+        match inst {
+            Instruction::Env(env_line) => {
+                for env_var in &env_line.vars {
+                    envs.insert(env_var.key.to_string(), env_var.value.to_string());
+                }
+            }
+            Instruction::Copy(copy) => {
+                let flag = |name: &str| {
+                    copy.flags.iter().find(|flag| flag.name.content == name).map(|flag| flag.value.content.clone())
+                };
+                let sources = copy.sources.iter().map(|source| source.content.clone()).collect();
+                copies.extend(copied_sources("COPY", sources, &copy.destination.content, flag("from"), None, line));
+            }
+            Instruction::Misc(misc) if misc.instruction.content.eq_ignore_ascii_case("ADD") => {
+                let mut flags = Vec::new();
+                let mut paths = Vec::new();
+                let arguments = misc.arguments.to_string();
+                match serde_json::from_str::<Vec<String>>(arguments.trim()) {
+                    Ok(exec_form) => paths = exec_form,
+                    Err(_) => {
+                        for word in arguments.split_whitespace() {
+                            match word.strip_prefix("--") {
+                                Some(flag) if paths.is_empty() => flags.push(flag.to_string()),
+                                _ => paths.push(word.to_string()),
+                            }
+                        }
+                    }
+                }
+                let flag = |name: &str| {
+                    flags.iter().find_map(|flag| flag.strip_prefix(name)?.strip_prefix('=').map(str::to_string))
+                };
+                if let Some(destination) = paths.pop() {
+                    copies.extend(copied_sources("ADD", paths, &destination, flag("from"), flag("checksum"), line));
+                }
+            }
+            _ => {}
+        }
+        instructions.push(format!("{:?}", inst));
+    }
+
+    let package_stages: Vec<&Stage> = match (all_stages, analyzed.last()) {
+        (true, _) | (false, None) => analyzed.clone(),
+        (false, Some(target)) => {
+            let mut lineage = vec![*target];
+            while let StageParent::Stage(parent) = lineage[lineage.len() - 1].parent {
+                lineage.push(&stages[parent]);
+            }
+            lineage.reverse();
+            lineage
+        }
+    };
+    for inst in package_stages.iter().flat_map(|stage| &stage.instructions) {
+        if let Instruction::Run(run_line) = inst {
+            let command = match &run_line.expr {
+                ShellOrExecExpr::Shell(command) => command.to_string(),
+                ShellOrExecExpr::Exec(commands) => commands.elements.iter().map(|c| c.to_string()).collect::<Vec<_>>().join(" "),
+            };
+            packages.extend(run_packages(&command, dockerfile_path));
+        }
+    }
+
+    Ok(DockerfileAnalysis {
+        envs,
+        instructions,
+        packages,
+        copies,
+        stages: stage_infos,
+    })
+}
+
+// Package managers whose install commands a RUN line may hold, as (program,
+// install subcommand, package format)
+const INSTALLERS: &[(&str, &str, &str)] = &[
+    ("apk", "add", "apk"),
+    ("apt-get", "install", "deb"),
+    ("apt", "install", "deb"),
+    ("yum", "install", "rpm"),
+    ("dnf", "install", "rpm"),
+    ("microdnf", "install", "rpm"),
+    ("pip", "install", "pip"),
+    ("pip3", "install", "pip"),
+];
+
+// Packages named by the install commands of a RUN line, with the version each
+// pins if any and a purl of the format its package manager installs. Other
+// commands name no packages.
+fn run_packages(command: &str, dockerfile_path: &str) -> Vec<Package> {
+    command
+        .split(['\n', ';', '|', '&'])
+        .filter_map(install_command)
+        .flat_map(|(format, arguments)| {
+            arguments.into_iter().map(move |word| {
+                let (name, version) = split_version_pin(word, format == "pip");
+                let mut package = Package {
+                    name,
+                    version: version.clone().unwrap_or_else(|| "unknown".to_string()),
+                    source: "unknown".to_string(),
+                    license: "unknown".to_string(),
+                    vendor: "unknown".to_string(),
+                    checksum: "unknown".to_string(),
+                    commands: Vec::new(),
+                    purl: None,
+                    install_reason: None,
+                    depends: Vec::new(),
+                    provides: Vec::new(),
+                    built_at: None,
+                    detection: Some(Detection::new(dockerfile_path, "dockerfile", Confidence::Low)),
+                    spdx_license: None,
+                    vulnerabilities: Vec::new(),
+                };
+                // An unpinned package is whichever version the install picks;
+                // names are percent-encoded, so the last `@` starts the version
+                let purl = package.purl(format);
+                package.purl = Some(match version {
+                    Some(_) => purl,
+                    None => purl.rsplit_once('@').map_or(purl.clone(), |(unversioned, _)| unversioned.to_string()),
+                });
+                package
+            })
+        })
+        .collect()
+}
+
+// The package format and package arguments of an install command such as
+// `DEBIAN_FRONTEND=noninteractive apt-get -y install --no-install-recommends
+// curl=7.88.1-10` or `python3 -m pip install flask==3.0.0`
+fn install_command(command: &str) -> Option<(&'static str, Vec<&str>)> {
+    let mut words = command
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c| c == '\'' || c == '"'))
+        .filter(|word| !word.is_empty() && *word != "\\")
+        .skip_while(|word| *word == "sudo" || (word.contains('=') && !word.starts_with('-')))
+        .peekable();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program.starts_with("python") && words.next_if_eq(&"-m").is_some() {
+        program = words.next()?;
+    }
+    let &(_, subcommand, format) = INSTALLERS.iter().find(|(name, _, _)| *name == program)?;
+
+    let mut arguments = Vec::new();
+    let mut installing = false;
+    let mut option_value = false;
+    for word in words {
+        if std::mem::take(&mut option_value) {
+            continue;
+        }
+        if word.starts_with('-') {
+            option_value = INSTALL_OPTIONS_WITH_VALUES.contains(&word);
+            continue;
+        }
+        if !installing {
+            // The first word that is not an option is the subcommand
+            if word != subcommand {
+                return None;
+            }
+            installing = true;
+            continue;
+        }
+        // Local paths, URLs and variables are not package names
+        if word.starts_with(['.', '/', '$']) || word.contains("://") {
+            continue;
+        }
+        arguments.push(word);
+    }
+    installing.then_some((format, arguments))
+}
+
+// Install options whose value is the next word rather than a package
+const INSTALL_OPTIONS_WITH_VALUES: &[&str] = &[
+    "-t", "--virtual", "-X", "--repository", "-o", "-r", "--requirement", "-c", "--constraint", "-e", "--editable",
+    "-i", "--index-url", "--extra-index-url", "--target", "--prefix", "--root",
+];
+
+// apk and apt pin with `name=version` (apt allows globs such as `1.24.*`), pip
+// with `name==version`. Range constraints are not pins, so they only yield the
+// name.
+fn split_version_pin(word: &str, pip: bool) -> (String, Option<String>) {
+    let (name, version) = match pip {
+        true => match word.split_once("==") {
+            Some((name, version)) => (name, Some(version.to_string())),
+            None => (word, None),
+        },
+        false => match word.split_once('=') {
+            Some((name, version)) if !name.ends_with(['<', '>', '~']) => (name, Some(version.to_string())),
+            _ => (word, None),
+        },
+    };
+    // Drop range operators, pip extras and apt target releases (nginx/bookworm)
+    let name = name.split(['<', '>', '~', '=', '!', '[', ';', '/']).next().unwrap_or(name);
+    (name.to_string(), version.filter(|version| !version.is_empty()))
+}
+
+fn copied_sources(
+    instruction: &str,
+    sources: Vec<String>,
+    destination: &str,
+    from: Option<String>,
+    checksum: Option<String>,
+    line: usize,
+) -> Vec<CopiedSource> {
+    sources
+        .into_iter()
+        .map(|source| {
+            let origin = if from.is_some() {
+                "stage"
+            } else if instruction == "ADD" && is_remote_source(&source) {
+                "remote"
+            } else {
+                "context"
+            };
+            CopiedSource {
+                instruction: instruction.to_string(),
+                origin: origin.to_string(),
+                source,
+                destination: destination.to_string(),
+                from: from.clone(),
+                checksum: checksum.clone(),
+                line,
+            }
+        })
+        .collect()
+}
+
+fn is_remote_source(source: &str) -> bool {
+    source.contains("://") || source.starts_with("git@")
+}
+
+// Diagnostics go to stderr so they never mix with SBOM output. JSON logs also
+// record when each phase span closes, with its timing, for aggregation.
+// -v and -q override RUST_LOG, which overrides the default info level
+fn init_logging(log_format: &str, verbosity: u8, quiet: bool) {
+    let level = match (verbosity, quiet) {
+        (0, true) => Some("warn"),
+        (0, false) => None,
+        (1, _) => Some("debug"),
+        _ => Some("trace"),
+    };
+    let filter = match level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal());
+    if log_format == "json" {
+        builder.json().with_span_events(FmtSpan::CLOSE).init();
+    } else {
+        builder.init();
+    }
+}
+
+// Names --sig-algo accepts, as SignatureAlgorithm::parse reads them
+const SIGNATURE_ALGORITHMS: [&str; 2] = ["ed25519", "ecdsa-p256"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SignatureAlgorithm {
+    Ed25519,
+    // ECDSA over P-256 with SHA-256, signatures DER-encoded
+    EcdsaP256,
+}
+
+impl SignatureAlgorithm {
+    fn parse(name: &str) -> Option<SignatureAlgorithm> {
+        match name {
+            "ed25519" => Some(SignatureAlgorithm::Ed25519),
+            "ecdsa-p256" => Some(SignatureAlgorithm::EcdsaP256),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SignatureAlgorithm::Ed25519 => "ed25519",
+            SignatureAlgorithm::EcdsaP256 => "ecdsa-p256",
+        }
+    }
+
+    fn verification(self) -> &'static dyn VerificationAlgorithm {
+        match self {
+            SignatureAlgorithm::Ed25519 => &ED25519,
+            SignatureAlgorithm::EcdsaP256 => &ECDSA_P256_SHA256_ASN1,
+        }
+    }
+}
+
+// A PKCS#8 keypair as written by generate-key
+enum SigningKey {
+    Ed25519(Ed25519KeyPair),
+    EcdsaP256(EcdsaKeyPair),
+}
+
+impl SigningKey {
+    fn from_pkcs8(data: &[u8]) -> Option<SigningKey> {
+        if let Ok(key_pair) = Ed25519KeyPair::from_pkcs8(data) {
+            return Some(SigningKey::Ed25519(key_pair));
+        }
+        EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, data, &SystemRandom::new()).ok().map(SigningKey::EcdsaP256)
+    }
+
+    fn algorithm(&self) -> SignatureAlgorithm {
+        match self {
+            SigningKey::Ed25519(_) => SignatureAlgorithm::Ed25519,
+            SigningKey::EcdsaP256(_) => SignatureAlgorithm::EcdsaP256,
+        }
+    }
+
+    // The raw public key: 32 bytes for Ed25519, the 65-byte uncompressed
+    // point for P-256
+    fn public_key(&self) -> &[u8] {
+        match self {
+            SigningKey::Ed25519(key_pair) => key_pair.public_key().as_ref(),
+            SigningKey::EcdsaP256(key_pair) => key_pair.public_key().as_ref(),
+        }
+    }
+}
+
+fn generate_keypair(algorithm: SignatureAlgorithm) -> (SigningKey, Vec<u8>) {
+    let rng = SystemRandom::new();
+    let pkcs8_bytes = match algorithm {
+        SignatureAlgorithm::Ed25519 => Ed25519KeyPair::generate_pkcs8(&rng).unwrap(),
+        SignatureAlgorithm::EcdsaP256 => EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap(),
+    };
+    let key_pair = SigningKey::from_pkcs8(pkcs8_bytes.as_ref()).unwrap();
+    (key_pair, pkcs8_bytes.as_ref().to_vec())
+}
+
+fn save_keypair_to_file(pkcs8_bytes: &[u8], file_path: &str) -> registry::Result<()> {
+    write_file(file_path, pkcs8_bytes)
+}
+
+fn load_keypair_from_file(file_path: &str, algorithm: SignatureAlgorithm) -> registry::Result<SigningKey> {
+    let key_data = fs::read(file_path).map_err(|e| format!("cannot read key {}: {}", file_path, e))?;
+    match SigningKey::from_pkcs8(&key_data) {
+        Some(key_pair) if key_pair.algorithm() == algorithm => Ok(key_pair),
+        Some(key_pair) => Err(format!("{} is an {} keypair; pass --sig-algo {} to sign with it", file_path, key_pair.algorithm().name(), key_pair.algorithm().name()).into()),
+        None => Err(format!("{} is not an {} keypair as written by generate-key", file_path, algorithm.name()).into()),
+    }
+}
+
+// fs::write, with the path in the error
+fn write_file(file_path: &str, data: impl AsRef<[u8]>) -> registry::Result<()> {
+    fs::write(file_path, data).map_err(|e| format!("cannot write {}: {}", file_path, e).into())
+}
+
+// A public key a signature may be checked against, with where it came from
+struct TrustedKey {
+    fingerprint: String,
+    algorithm: SignatureAlgorithm,
+    public_key: Vec<u8>,
+    path: String,
+}
+
+impl TrustedKey {
+    fn new(algorithm: SignatureAlgorithm, public_key: &[u8], path: &str) -> TrustedKey {
+        TrustedKey {
+            fingerprint: key_fingerprint(public_key),
+            algorithm,
+            public_key: public_key.to_vec(),
+            path: path.to_string(),
+        }
+    }
+}
+
+// Every key in a keyring directory: keypairs as written by generate-key, or
+// public keys as parse_public_key reads them. Other files are skipped.
+fn load_keyring(dir: &str) -> registry::Result<Vec<TrustedKey>> {
+    let mut paths: Vec<PathBuf> = read_dir(dir)
+        .map_err(|e| format!("cannot read keyring {}: {}", dir, e))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+
+    let mut keys: Vec<TrustedKey> = Vec::new();
+    for path in paths {
+        let Ok(key_data) = fs::read(&path) else { continue };
+        let (algorithm, public_key) = match SigningKey::from_pkcs8(&key_data) {
+            Some(key_pair) => (key_pair.algorithm(), key_pair.public_key().to_vec()),
+            None => match parse_public_key(&key_data) {
+                Some(public_key) => public_key,
+                None => {
+                    warn!(path = %path.display(), "skipping keyring file that is not an Ed25519 or ECDSA P-256 key");
+                    continue;
+                }
+            },
+        };
+        let key = TrustedKey::new(algorithm, &public_key, &path.display().to_string());
+        if !keys.iter().any(|known| known.fingerprint == key.fingerprint) {
+            keys.push(key);
+        }
+    }
+    if keys.is_empty() {
+        return Err(format!("the keyring {} holds no Ed25519 or ECDSA P-256 keys", dir).into());
+    }
+    Ok(keys)
+}
+
+// DER SubjectPublicKeyInfo header of an Ed25519 public key (RFC 8410), which
+// the 32 key bytes follow
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+// The same for a P-256 public key (RFC 5480), followed by the 65-byte
+// uncompressed point
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01,
+    0x07, 0x03, 0x42, 0x00,
+];
+
+// A public key as generate-key writes it (the raw 32 Ed25519 bytes or the
+// 65-byte P-256 point) or in PEM form, as `openssl pkey -pubout` writes it
+fn parse_public_key(data: &[u8]) -> Option<(SignatureAlgorithm, Vec<u8>)> {
+    let raw = |key: &[u8]| match key.len() {
+        32 => Some((SignatureAlgorithm::Ed25519, key.to_vec())),
+        65 if key[0] == 0x04 => Some((SignatureAlgorithm::EcdsaP256, key.to_vec())),
+        _ => None,
+    };
+    if let Some(key) = raw(data) {
+        return Some(key);
+    }
+    let pem = std::str::from_utf8(data).ok()?;
+    let body = pem.trim().strip_prefix("-----BEGIN PUBLIC KEY-----")?.strip_suffix("-----END PUBLIC KEY-----")?;
+    let der = BASE64.decode(body.split_whitespace().collect::<String>().as_bytes()).ok()?;
+    match der.strip_prefix(&ED25519_SPKI_PREFIX[..]) {
+        Some(key) if key.len() == 32 => raw(key),
+        Some(_) => None,
+        None => der.strip_prefix(&P256_SPKI_PREFIX[..]).filter(|key| key.len() == 65).and_then(raw),
+    }
+}
+
+// Identifies a public key as SHA256:<unpadded base64 of its digest>, the way
+// ssh-keygen prints fingerprints
+fn key_fingerprint(public_key: &[u8]) -> String {
+    format!("SHA256:{}", BASE64_NOPAD.encode(&Sha256::digest(public_key)))
+}
+
+fn sign_data(key_pair: &SigningKey, data: &[u8]) -> String {
+    let sig = match key_pair {
+        SigningKey::Ed25519(key_pair) => key_pair.sign(data),
+        SigningKey::EcdsaP256(key_pair) => key_pair.sign(&SystemRandom::new(), data).unwrap(),
+    };
+    BASE64.encode(sig.as_ref())
+}
+
+fn save_sbom_to_file(sbom: &Sbom, schema_version: u32, output_format: &str, file_path: &str) -> registry::Result<()> {
+    write_file(file_path, serialize_sbom(sbom, schema_version, output_format))
+}
+
+// Formats that serialize the Sbom itself and can be loaded back
+fn is_native_format(output_format: &str) -> bool {
+    matches!(output_format, "json" | "yaml" | "toml")
+}
+
+fn serialize_sbom(sbom: &Sbom, schema_version: u32, output_format: &str) -> String {
+    let mut value = sbom_to_value(sbom, schema_version);
+    match output_format {
+        "yaml" => serde_yaml::to_string(&value).unwrap(),
+        "toml" => {
+            // TOML has no null; absent keys read back as None
+            remove_nulls(&mut value);
+            toml::to_string_pretty(&value).unwrap()
+        }
+        _ => serde_json::to_string_pretty(&value).unwrap(),
+    }
+}
+
+fn remove_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Array(items) => items.iter_mut().for_each(remove_nulls),
+        serde_json::Value::Object(map) => {
+            map.retain(|_, child| !child.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        _ => {}
+    }
+}
+
+// Reads a native SBOM, choosing the parser from the file extension
+fn load_sbom(file_path: &str) -> Result<Sbom, Box<dyn std::error::Error>> {
+    let data = fs::read_to_string(file_path)?;
+    let extension = Path::new(file_path).extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    Ok(match extension.to_ascii_lowercase().as_str() {
+        "yaml" | "yml" => serde_yaml::from_str(&data)?,
+        "toml" => toml::from_str(&data)?,
+        _ => serde_json::from_str(&data)?,
+    })
+}
+
+// Puts every collection whose order carries no meaning into a fixed order, so
+// the same image always produces byte-identical output in every format and
+// diffs between SBOMs show only content changes. Layers and Dockerfile
+// instructions keep their order; map keys are sorted by their BTreeMap type.
+fn canonicalize_sbom(sbom: &mut Sbom) {
+    for layer in &mut sbom.layers {
+        layer.packages.sort_by(package_order);
+        layer.files.sort_by(|a, b| a.path.cmp(&b.path));
+        for file in &mut layer.files {
+            file.duplicate_paths.sort();
+        }
+    }
+    if let Some(analysis) = &mut sbom.dockerfile_analysis {
+        analysis.packages.sort_by(package_order);
+    }
+}
+
+// By purl, then by name and version for packages without one, then by where
+// they were found, so packages of the same name and version from different
+// ecosystems or locations never keep their discovery order
+fn package_order(a: &Package, b: &Package) -> std::cmp::Ordering {
+    (&a.purl, &a.name, &a.version, &a.source).cmp(&(&b.purl, &b.name, &b.version, &b.source))
+}
+
+// Serializes the SBOM in the shape of the given schema version, dropping any
+// fields that version does not know about.
+fn sbom_to_value(sbom: &Sbom, schema_version: u32) -> serde_json::Value {
+    let mut value = serde_json::to_value(sbom).unwrap();
+    value["schema_version"] = schema_version.into();
+    for (path, introduced) in SCHEMA_FIELDS {
+        if *introduced > schema_version {
+            remove_field(&mut value, &path.split('.').collect::<Vec<_>>());
+        }
+    }
+    value
+}
+
+fn remove_field(value: &mut serde_json::Value, path: &[&str]) {
+    match value {
+        serde_json::Value::Array(items) => {
+            for item in items {
+                remove_field(item, path);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            if path.len() == 1 {
+                map.shift_remove(path[0]);
+            } else if let Some(child) = map.get_mut(path[0]) {
+                remove_field(child, &path[1..]);
+            }
+        }
+        _ => {}
+    }
+}
+
+// The exact bytes an embedded signature covers: the canonical JSON of the
+// SBOM, without its signature, in the shape of the emitted schema version.
+// Object keys are sorted and there is no whitespace, so the bytes depend only
+// on the content and never on field or map order.
+fn signing_payload(sbom: &Sbom, schema_version: u32) -> Vec<u8> {
+    let mut value = sbom_to_value(sbom, schema_version);
+    sort_keys(&mut value);
+    serde_json::to_vec(&value).unwrap()
+}
+
+// What an embedded signature may cover, given the SBOM without it: the
+// canonical payload, or the legacy one of earlier releases, in the schema
+// version the document was written with
+fn verification_payloads(unsigned_sbom: &Sbom) -> [Vec<u8>; 2] {
+    let schema_version = unsigned_sbom.schema_version;
+    [signing_payload(unsigned_sbom, schema_version), legacy_signing_payload(unsigned_sbom, schema_version)]
+}
+
+// What releases before canonical signing signed: the compact JSON in struct
+// field order. Verification still accepts it for SBOMs signed back then.
+fn legacy_signing_payload(sbom: &Sbom, schema_version: u32) -> Vec<u8> {
+    serde_json::to_vec(&sbom_to_value(sbom, schema_version)).unwrap()
+}
+
+fn sort_keys(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Array(items) => items.iter_mut().for_each(sort_keys),
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<(String, serde_json::Value)> = std::mem::take(map).into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, mut child) in entries {
+                sort_keys(&mut child);
+                map.insert(key, child);
+            }
+        }
+        _ => {}
+    }
+}
+
+// A resumed run only trusts an earlier output that still parses and, when
+// signing, has its detached signature next to it
+fn output_is_valid(path: &str, output_format: &str, signed: bool) -> bool {
+    let parses = match output_format {
+        "spdx" => fs::read_to_string(path).is_ok_and(|spdx| spdx.starts_with("SPDXVersion:")),
+        "spdx-json" => fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<serde_json::Value>(&data).ok())
+            .is_some_and(|document| document["spdxVersion"] == "SPDX-2.3"),
+        "cyclonedx" => fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<serde_json::Value>(&data).ok())
+            .is_some_and(|bom| bom["bomFormat"] == "CycloneDX"),
+        _ => load_sbom(path).is_ok(),
+    };
+    parses && (!signed || Path::new(&signature_path(path)).exists())
+}
+
+fn signature_path(file_path: &str) -> String {
+    format!("{}.sig", file_path)
+}
+
+// sbom.json's attestation is sbom.intoto.jsonl
+fn attestation_path(file_path: &str) -> String {
+    Path::new(file_path).with_extension("intoto.jsonl").display().to_string()
+}
+
+fn writes_output_file(output_format: &str) -> bool {
+    !matches!(output_format, "list" | "table")
+}
+
+fn write_detached_signature(key_pair: &SigningKey, file_path: &str) -> registry::Result<()> {
+    let data = fs::read(file_path).map_err(|e| format!("cannot read {}: {}", file_path, e))?;
+    write_file(&signature_path(file_path), sign_data(key_pair, &data))
+}
+
+// The first key the detached signature of the file verifies with, if any
+fn verify_detached_signature<'a>(keys: &'a [TrustedKey], file_path: &str, signature_file: &str) -> registry::Result<Option<&'a TrustedKey>> {
+    let data = fs::read(file_path).map_err(|e| format!("cannot read {}: {}", file_path, e))?;
+    let signature = fs::read_to_string(signature_file).map_err(|e| format!("cannot read {}: {}", signature_file, e))?;
+    Ok(keys.iter().find(|key| verify_signature(key, &data, signature.trim())))
+}
+
+// Checks a signature made with the key's algorithm. A signature that is not
+// even base64 does not verify.
+fn verify_signature(key: &TrustedKey, data: &[u8], signature: &str) -> bool {
+    let Ok(sig_bytes) = BASE64.decode(signature.as_bytes()) else { return false };
+    let peer_public_key = UnparsedPublicKey::new(key.algorithm.verification(), &key.public_key);
+    peer_public_key.verify(data, &sig_bytes).is_ok()
+}
+
+// Shows every package `listed` accepts, and the findings and vulnerabilities
+// at or above `min_severity`
+fn display_sbom_table(sbom: &Sbom, listed: impl Fn(&Package) -> bool, min_severity: checks::Severity) {
+    let mut table = Table::new();
+    table.add_row(row!["Field", "Value"]);
+    table.add_row(row!["SBOM Version", &sbom.sbom_version]);
+    table.add_row(row!["SPDX ID", &sbom.spdx_id]);
+    table.add_row(row!["Name", &sbom.name]);
+    table.add_row(row!["Namespace", &sbom.namespace]);
+    table.add_row(row!["Created", &sbom.creation_info.created]);
+    table.add_row(row!["Creators", &sbom.creation_info.creators.join(", ")]);
+    table.add_row(row!["Image Name", &sbom.image_name]);
+    table.add_row(row!["Image Digest", &sbom.image_digest]);
+    table.add_row(row!["Platform", sbom.platform.as_deref().unwrap_or("Unknown")]);
+    table.add_row(row!["Generated By", format!("{} {}", sbom.metadata.tool, sbom.metadata.version)]);
+    if let Some(commit) = &sbom.metadata.commit {
+        table.add_row(row!["  Commit", commit]);
+    }
+    if let Some(provenance) = &sbom.provenance {
+        let unknown = |value: &Option<String>| value.clone().unwrap_or_else(|| "Unknown".to_string());
+        table.add_row(row!["Provenance", ""]);
+        table.add_row(row!["  Builder", unknown(&provenance.builder_id)]);
+        table.add_row(row!["  Source", unknown(&provenance.source_uri)]);
+        table.add_row(row!["  Revision", unknown(&provenance.source_revision)]);
+        table.add_row(row!["  Finished On", unknown(&provenance.finished_on)]);
+        table.add_row(row!["  Base Image", unknown(&provenance.base_image)]);
+    }
+    table.add_row(row!["Shell", sbom.metadata.shell.as_deref().unwrap_or("none")]);
+    table.add_row(row!["Init Systems", if sbom.metadata.init_systems.is_empty() { "none".to_string() } else { sbom.metadata.init_systems.join(", ") }]);
+    if let Some(healthcheck) = &sbom.metadata.healthcheck {
+        table.add_row(row!["Healthcheck", healthcheck.command.join(" ")]);
+        table.add_row(row!["  Interval", healthcheck.interval.as_deref().unwrap_or("default")]);
+    }
+
+    if !sbom.services.is_empty() {
+        table.add_row(row!["Services", ""]);
+        for service in &sbom.services {
+            let mut details = vec![service.state.clone()];
+            if service.runs_as_root {
+                details.push("root".to_string());
+            }
+            if service.network_facing {
+                details.push("network-facing".to_string());
+            }
+            table.add_row(row![format!("  {}", service.unit), format!("{}: {}", details.join(", "), service.exec_start.join("; "))]);
+        }
+    }
+    if let Some(evidence) = &sbom.license_evidence {
+        table.add_row(row!["License Evidence", ""]);
+        for entry in evidence {
+            table.add_row(row![format!("  {}", entry.license), format!("{} packages, {} license texts", entry.packages.len(), entry.files.len())]);
+        }
+    }
+
+    for (i, layer) in sbom.layers.iter().enumerate() {
+        table.add_row(row![format!("Layer {}", i + 1), ""]);
+        table.add_row(row!["  Layer ID", &layer.layer_id]);
+        table.add_row(row!["  Created", &layer.created]);
+        if let Some(created_by) = &layer.created_by {
+            table.add_row(row!["  Created By", created_by]);
+        }
+        table.add_row(row!["  OS Guess", &layer.os_guess]);
+        table.add_row(row!["  Package Format", &layer.pkg_format]);
+
+        table.add_row(row!["  Packages", ""]);
+        for package in layer.packages.iter().filter(|package| listed(package)) {
+            table.add_row(row!["    Name", &package.name]);
+            table.add_row(row!["    Version", &package.version]);
+            table.add_row(row!["    Source", &package.source]);
+            table.add_row(row!["    License", &package.license]);
+            table.add_row(row!["    Vendor", &package.vendor]);
+            table.add_row(row!["    Checksum", &package.checksum]);
+            if let Some(install_reason) = &package.install_reason {
+                table.add_row(row!["    Install Reason", install_reason]);
+            }
+            if let Some(detection) = &package.detection {
+                table.add_row(row!["    Detected By", format!("{} from {}, {} confidence", detection.detector, detection.source_file, detection.confidence)]);
+            }
+            if !package.commands.is_empty() {
+                table.add_row(row!["    Commands", package.commands.join(" ")]);
+            }
+            if let Some(built_at) = &package.built_at {
+                table.add_row(row!["    Built At", built_at]);
+            }
+            if !package.depends.is_empty() {
+                table.add_row(row!["    Depends", package.depends.join(" ")]);
+            }
+            let ids: Vec<&str> = package
+                .vulnerabilities
+                .iter()
+                .filter(|vulnerability| osv::vulnerability_severity(vulnerability) >= min_severity)
+                .map(|vulnerability| vulnerability.id.as_str())
+                .collect();
+            if !ids.is_empty() {
+                table.add_row(row!["    Vulnerabilities", format!("{} ({})", ids.len(), ids.join(", "))]);
+            }
+        }
+
+        table.add_row(row!["  Files", ""]);
+        for file in &layer.files {
+            table.add_row(row!["    Path", &file.path]);
+            table.add_row(row!["    Size", file.size.to_string()]);
+            table.add_row(row!["    File Type", &file.file_type]);
+            table.add_row(row!["    Checksum", &file.checksum]);
+            for duplicate in &file.duplicate_paths {
+                table.add_row(row!["    Same Content", duplicate]);
+            }
+        }
+
+        table.add_row(row!["  Notices", ""]);
+        for notice in layer.notices.iter().filter(|notice| checks::is_shown(notice, min_severity)) {
+            table.add_row(row!["    Message", &notice.message]);
+            table.add_row(row!["    Level", &notice.level]);
+        }
+
+        table.add_row(row!["  Analyzed Output", &layer.analyzed_output]);
+    }
+
+    table.add_row(row!["Dockerfile Analysis", &sbom.dockerfile_analysis.is_some().to_string()]);
+    table.add_row(row!["Signature", &sbom.signature.clone().unwrap_or_else(|| "None".to_string())]);
+
+    table.printstd();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sbom() -> Sbom {
+        serde_json::from_value(serde_json::json!({
+            "schema_version": SCHEMA_VERSION,
+            "sbom_version": "1.0",
+            "spdx_id": "SPDXRef-DOCUMENT",
+            "name": "alpine:3.20",
+            "namespace": "https://example.com/alpine",
+            "creation_info": { "created": "2024-06-01T00:00:00Z", "creators": ["Tool: cbom"] },
+            "image_name": "alpine:3.20",
+            "image_digest": "sha256:abc",
+            "layers": [],
+            "dockerfile_analysis": null,
+            "signature": null,
+            "metadata": { "tool": "cbom", "version": "0.1.0", "authors": [], "organization": "" },
+            "platform": "linux/amd64",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn counts_spdx_relationships_from_layers() {
+        assert!(is_layer_package_relationship("SPDXRef-Layer-1 CONTAINS SPDXRef-musl".split_whitespace()));
+        assert!(!is_layer_package_relationship("SPDXRef-Image CONTAINS SPDXRef-Layer-1".split_whitespace()));
+        assert!(!is_layer_package_relationship("SPDXRef-DOCUMENT DESCRIBES SPDXRef-Image".split_whitespace()));
+        assert!(!is_layer_package_relationship("SPDXRef-Layer-1 CONTAINS".split_whitespace()));
+    }
+
+    fn package(name: &str, version: &str, purl: &str, source: &str) -> Package {
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            license: String::new(),
+            vendor: String::new(),
+            checksum: String::new(),
+            commands: Vec::new(),
+            purl: Some(purl.to_string()),
+            install_reason: None,
+            depends: Vec::new(),
+            provides: Vec::new(),
+            built_at: None,
+            detection: None,
+            spdx_license: None,
+            vulnerabilities: Vec::new(),
+            source: source.to_string(),
+        }
+    }
+
+    fn layer(packages: Vec<Package>, paths: &[&str]) -> Layer {
+        Layer {
+            layer_id: "sha256:layer".to_string(),
+            created: String::new(),
+            os_guess: "linux".to_string(),
+            pkg_format: "apk".to_string(),
+            packages,
+            files: paths
+                .iter()
+                .map(|path| FileMetadata {
+                    path: path.to_string(),
+                    size: 1,
+                    file_type: "file".to_string(),
+                    checksum: String::new(),
+                    duplicate_paths: Vec::new(),
+                })
+                .collect(),
+            notices: Vec::new(),
+            analyzed_output: String::new(),
+            created_by: None,
+            contents: LayerContents::default(),
+            links: LayerLinks::default(),
+            dir_modes: LayerModes::default(),
+            root_prefix: None,
+        }
+    }
+
+    // Every output format, serialized after canonicalization
+    fn outputs(mut sbom: Sbom) -> Vec<String> {
+        canonicalize_sbom(&mut sbom);
+        let mut outputs: Vec<String> = ["json", "yaml", "toml"].iter().map(|format| serialize_sbom(&sbom, SCHEMA_VERSION, format)).collect();
+        outputs.push(spdx::generate_spdx(&sbom));
+        outputs.push(spdx::generate_spdx_json(&sbom));
+        outputs.push(cyclonedx::generate_cyclonedx(&sbom));
+        outputs.push(String::from_utf8(signing_payload(&sbom, SCHEMA_VERSION)).unwrap());
+        outputs
+    }
+
+    #[test]
+    fn output_is_identical_whatever_order_packages_and_files_were_found_in() {
+        let packages = vec![
+            package("musl", "1.2.5-r0", "pkg:apk/alpine/musl@1.2.5-r0", "/lib/apk/db/installed"),
+            package("busybox", "1.36.1-r29", "pkg:apk/alpine/busybox@1.36.1-r29", "/lib/apk/db/installed"),
+            package("requests", "2.32.3", "pkg:pypi/requests@2.32.3", "/usr/lib/python3.12/site-packages"),
+            // The same name and version from two ecosystems, and twice from one
+            package("six", "1.16.0", "pkg:pypi/six@1.16.0", "/usr/lib/python3.12/site-packages"),
+            package("six", "1.16.0", "pkg:apk/alpine/six@1.16.0", "/lib/apk/db/installed"),
+            package("six", "1.16.0", "pkg:pypi/six@1.16.0", "/opt/venv/lib/python3.12/site-packages"),
+        ];
+        let paths = ["usr/lib/python3.12/site-packages/requests/__init__.py", "bin/busybox", "lib/apk/db/installed"];
+        let image = |packages: Vec<Package>, paths: &[&str]| Sbom { layers: vec![layer(packages, paths)], ..sbom() };
+
+        let found = outputs(image(packages.clone(), &paths));
+        assert_eq!(found, outputs(image(packages.clone(), &paths)));
+        assert_eq!(found, outputs(image(packages.into_iter().rev().collect(), &[paths[2], paths[0], paths[1]])));
+    }
+
+    #[test]
+    fn signing_payload_sorts_keys_without_whitespace() {
+        let payload = String::from_utf8(signing_payload(&sbom(), SCHEMA_VERSION)).unwrap();
+        assert!(payload.starts_with(r#"{"creation_info":{"created":"2024-06-01T00:00:00Z","creators":["Tool: cbom"]},"dockerfile_analysis":null,"#));
+        assert!(!payload.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        let keys: Vec<&String> = value.as_object().unwrap().keys().collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+    }
+
+    #[test]
+    fn legacy_signing_payload_keeps_field_order() {
+        let payload = String::from_utf8(legacy_signing_payload(&sbom(), SCHEMA_VERSION)).unwrap();
+        assert!(payload.starts_with(&format!(r#"{{"schema_version":{},"sbom_version":"1.0","spdx_id":"SPDXRef-DOCUMENT","#, SCHEMA_VERSION)));
+        assert_ne!(payload.as_bytes(), signing_payload(&sbom(), SCHEMA_VERSION));
+    }
+
+    #[test]
+    fn signing_payload_leaves_out_fields_newer_than_the_schema_version() {
+        let payload = String::from_utf8(signing_payload(&sbom(), 2)).unwrap();
+        assert!(payload.contains(r#""schema_version":2"#));
+        assert!(!payload.contains(r#""platform""#));
+        assert!(String::from_utf8(signing_payload(&sbom(), 3)).unwrap().contains(r#""platform":"linux/amd64""#));
+    }
+
+    // An SBOM of an image whose Dockerfile set `envs`, in that order
+    fn sbom_with_envs(envs: &[(&str, &str)]) -> Sbom {
+        let mut value = serde_json::to_value(sbom()).unwrap();
+        let envs: serde_json::Map<String, serde_json::Value> = envs.iter().map(|(name, value)| (name.to_string(), (*value).into())).collect();
+        value["dockerfile_analysis"] = serde_json::json!({ "envs": envs, "instructions": [], "packages": [] });
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn reverse_keys(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Array(items) => items.iter_mut().for_each(reverse_keys),
+            serde_json::Value::Object(map) => {
+                let entries: Vec<(String, serde_json::Value)> = std::mem::take(map).into_iter().collect();
+                for (key, mut child) in entries.into_iter().rev() {
+                    reverse_keys(&mut child);
+                    map.insert(key, child);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Embeds a signature over `payload`, writes the SBOM as JSON and reads it
+    // back with every object's keys reversed, as another tool might rewrite it
+    fn sign_and_reload(sbom: Sbom, payload: fn(&Sbom, u32) -> Vec<u8>, key_pair: &SigningKey) -> Sbom {
+        let signature = sign_data(key_pair, &payload(&sbom, SCHEMA_VERSION));
+        let signed = Sbom { signature: Some(signature), ..sbom };
+        let mut value: serde_json::Value = serde_json::from_str(&serialize_sbom(&signed, SCHEMA_VERSION, "json")).unwrap();
+        reverse_keys(&mut value);
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn verifies(sbom: Sbom, key: &TrustedKey) -> bool {
+        let signature = sbom.signature.clone().unwrap();
+        let unsigned_sbom = Sbom { signature: None, ..sbom };
+        verification_payloads(&unsigned_sbom).iter().any(|payload| verify_signature(key, payload, &signature))
+    }
+
+    fn trusted_key(key_pair: &SigningKey, algorithm: SignatureAlgorithm) -> TrustedKey {
+        TrustedKey {
+            fingerprint: key_fingerprint(key_pair.public_key()),
+            algorithm,
+            public_key: key_pair.public_key().to_vec(),
+            path: "test.pub".to_string(),
+        }
+    }
+
+    const ENVS: [(&str, &str); 4] = [("PATH", "/usr/local/bin:/usr/bin"), ("LANG", "C.UTF-8"), ("APP_HOME", "/app"), ("TZ", "UTC")];
+
+    #[test]
+    fn signed_sboms_verify_whatever_order_their_env_vars_are_in() {
+        for algorithm in [SignatureAlgorithm::Ed25519, SignatureAlgorithm::EcdsaP256] {
+            let (key_pair, _) = generate_keypair(algorithm);
+            let key = trusted_key(&key_pair, algorithm);
+            let reversed: Vec<(&str, &str)> = ENVS.iter().rev().copied().collect();
+            for envs in [ENVS.to_vec(), reversed.clone()] {
+                assert!(verifies(sign_and_reload(sbom_with_envs(&envs), signing_payload, &key_pair), &key));
+            }
+
+            // Signed with the variables set in one order, verified against a
+            // document that lists them in the other
+            let signed = sign_and_reload(sbom_with_envs(&ENVS), signing_payload, &key_pair);
+            let reordered = Sbom { signature: signed.signature.clone(), ..sbom_with_envs(&reversed) };
+            assert!(verifies(reordered, &key));
+
+            let mut tampered = sbom_with_envs(&ENVS);
+            tampered.dockerfile_analysis.as_mut().unwrap().envs.insert("TZ".to_string(), "Europe/Berlin".to_string());
+            assert!(!verifies(Sbom { signature: signed.signature, ..tampered }, &key));
+        }
+    }
+
+    #[test]
+    fn signatures_over_the_legacy_payload_still_verify() {
+        let (key_pair, _) = generate_keypair(SignatureAlgorithm::Ed25519);
+        let key = trusted_key(&key_pair, SignatureAlgorithm::Ed25519);
+        assert!(verifies(sign_and_reload(sbom_with_envs(&ENVS), legacy_signing_payload, &key_pair), &key));
+
+        let (other_pair, _) = generate_keypair(SignatureAlgorithm::Ed25519);
+        assert!(!verifies(sign_and_reload(sbom_with_envs(&ENVS), legacy_signing_payload, &other_pair), &key));
+    }
+
+    #[test]
+    fn apk_checksum_decodes_q1_digests_to_hex() {
+        // SHA-1 of "abc"
+        assert_eq!(apk_checksum("Q1qZk+NkcGgWq6PiVxeFDCbJzQ2J0="), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn apk_checksum_keeps_other_forms_as_written() {
+        assert_eq!(apk_checksum("Q2qZk+NkcGgWq6PiVxeFDCbJzQ2J0="), "Q2qZk+NkcGgWq6PiVxeFDCbJzQ2J0=");
+        assert_eq!(apk_checksum("Q1not base64"), "Q1not base64");
+        // Valid base64, but not a 20-byte digest
+        assert_eq!(apk_checksum("Q1YWJj"), "Q1YWJj");
+    }
+
+    #[test]
+    fn reads_checksums_and_dependencies_from_an_installed_database() {
+        let extracted = ExtractedFiles {
+            contents: HashMap::from([(APK_INSTALLED.to_string(), include_bytes!("../example/scratch-rootfs/rootfs/lib/apk/db/installed").to_vec())]),
+            ..ExtractedFiles::default()
+        };
+        let found = analyze_layer_for_packages(&extracted, None);
+        let package = |name: &str| found.packages.iter().find(|package| package.name == name).unwrap();
+        // C:Q1bGKxNaD8rRbMnXGpYVWmA8Xn8Ek=
+        assert_eq!(package("musl").checksum, "6c62b135a0fcad16cc9d71a96155a603c5e7f049");
+        assert!(package("musl").depends.is_empty());
+        // C:Q1Lx0H4HgiA9ZCmnxMSYKUHXtiMSk=, depending on so:libc.musl-x86_64.so.1
+        assert_eq!(package("busybox").checksum, "2f1d07e0782203d6429a7c4c4982941d7b623129");
+        assert_eq!(package("busybox").depends, ["musl"]);
+    }
+
+    const P256_PEM: &str = "-----BEGIN PUBLIC KEY-----
+MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAECvh6PPr/8tMQ2dGHp7LNCLtiJn+C
+O4Vn4FQAsh5BxWM1rk8Hha6/lDM+VDGhuqXA/1EUPw7HjiLlMGW81z1ZoA==
+-----END PUBLIC KEY-----
+";
+    const P256_POINT: &str = "040af87a3cfafff2d310d9d187a7b2cd08bb62267f823b8567e05400b21e41c56335ae4f0785aebf94333e5431a1baa5c0ff51143f0ec78e22e53065bcd73d59a0";
+
+    #[test]
+    fn parse_public_key_reads_p256_pem() {
+        let (algorithm, key) = parse_public_key(P256_PEM.as_bytes()).unwrap();
+        assert_eq!(algorithm, SignatureAlgorithm::EcdsaP256);
+        assert_eq!(key, HEXLOWER.decode(P256_POINT.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn parse_public_key_reads_raw_p256_point() {
+        let point = HEXLOWER.decode(P256_POINT.as_bytes()).unwrap();
+        let (algorithm, key) = parse_public_key(&point).unwrap();
+        assert_eq!(algorithm, SignatureAlgorithm::EcdsaP256);
+        assert_eq!(key, point);
+    }
+
+    #[test]
+    fn parse_public_key_rejects_compressed_and_truncated_keys() {
+        let mut compressed = HEXLOWER.decode(P256_POINT.as_bytes()).unwrap();
+        compressed[0] = 0x02;
+        assert!(parse_public_key(&compressed).is_none());
+        let truncated = P256_PEM.replace("O4Vn4FQAsh5BxWM1rk8Hha6/lDM+VDGhuqXA/1EUPw7HjiLlMGW81z1ZoA==", "O4Vn4FQAsh5BxWM1rk8Hha6/lDM+");
+        assert!(parse_public_key(truncated.as_bytes()).is_none());
+    }
+
+    #[test]
+    fn flags_toolchain_packages_still_installed_at_the_end_once() {
+        let gcc = || package("gcc", "13.2.1", "pkg:apk/alpine/gcc@13.2.1", "alpine");
+        let make = || package("make", "4.4.1", "pkg:apk/alpine/make@4.4.1", "alpine");
+        let musl = || package("musl", "1.2.4", "pkg:apk/alpine/musl@1.2.4", "alpine");
+        // gcc and make are installed, a later step rewrites the database
+        // adding musl, and the last one removes gcc
+        let mut layers = vec![
+            layer(vec![gcc(), make()], &[]),
+            layer(vec![gcc(), make(), musl()], &[]),
+            layer(vec![make(), musl()], &[]),
+        ];
+        let toolchain = checks::DEFAULT_TOOLCHAIN_PACKAGES.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        checks::check_toolchain_packages(&mut layers, &toolchain, &checks::Allowlist::default());
+
+        let notices: Vec<(usize, &str)> = layers.iter().enumerate().flat_map(|(index, layer)| layer.notices.iter().map(move |notice| (index, notice.message.as_str()))).collect();
+        assert_eq!(notices.len(), 1);
+        assert_eq!(notices[0].0, 2);
+        assert!(notices[0].1.starts_with("Build toolchain package make 4.4.1 is present"));
+    }
+
+    #[test]
+    fn reports_and_gates_on_vulnerabilities_of_the_final_packages_once() {
+        let openssl = |severity: &str| Package {
+            vulnerabilities: vec![osv::Vulnerability {
+                id: "CVE-2024-0727".to_string(),
+                severity: Some(severity.to_string()),
+                summary: None,
+            }],
+            ..package("openssl", "3.1.4-r5", "pkg:apk/alpine/openssl@3.1.4-r5", "alpine")
+        };
+        // Both layers wrote the package database, and the second removed curl
+        let curl = Package {
+            vulnerabilities: vec![osv::Vulnerability { id: "CVE-2024-2398".to_string(), severity: None, summary: None }],
+            ..package("curl", "8.5.0-r0", "pkg:apk/alpine/curl@8.5.0-r0", "alpine")
+        };
+        let layers = vec![layer(vec![openssl("high"), curl], &[]), layer(vec![openssl("high")], &[])];
+
+        let mut report = sarif::SarifReport::default();
+        report.add_vulnerabilities(&layers, "myapp:1.0", None, None, checks::Severity::Info);
+        let log: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        let results = log["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["properties"]["vulnerability"], "CVE-2024-0727");
+
+        assert_eq!(checks::highest_severity(&layers, &checks::Allowlist::default()), Some(checks::Severity::High));
+        let mut allowlist = checks::Allowlist::default();
+        allowlist.add("openssl");
+        assert_eq!(checks::highest_severity(&layers, &allowlist), None);
+    }
+
+    #[test]
+    fn removes_the_exported_image_when_analysis_fails_partway() {
+        let runtime = Runtime::new().unwrap();
+        let temp_root = tempfile::tempdir().unwrap();
+        let left_behind = || fs::read_dir(temp_root.path()).unwrap().count();
+
+        // The daemon stops sending after the first chunk
+        let export = stream::iter([Ok(Bytes::from_static(&[0; 1024])), Err("connection reset")]);
+        let exported = runtime.block_on(spool_image_export("myapp:1.0", export, temp_root.path()));
+        assert_eq!(exported.unwrap_err().to_string(), "cannot export myapp:1.0: connection reset");
+        assert_eq!(left_behind(), 0);
+
+        // The export completes, but is no image archive
+        let export = stream::iter([Ok::<_, String>(Bytes::from_static(b"not a tarball"))]);
+        let analyzed = runtime.block_on(async {
+            let temp_dir = spool_image_export("myapp:1.0", export, temp_root.path()).await?;
+            assert!(temp_dir.path().join(EXPORTED_IMAGE).is_file());
+            archive::image_archive_layers(&temp_dir.path().join(EXPORTED_IMAGE))
+        });
+        assert!(analyzed.is_err());
+        assert_eq!(left_behind(), 0);
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::checks::normalize_path;
+use crate::{Layer, Notice};
+
+// Every package declaring a license, along with the license texts those
+// packages install. Packages without a declared license (dpkg records none)
+// are grouped under NOASSERTION so their copyright files still show up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LicenseEvidence {
+    pub license: String,
+    pub packages: Vec<String>,
+    pub files: Vec<LicenseFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LicenseFile {
+    pub path: String,
+    pub checksum: String,
+    // Missing when the file was too large to read
+    pub text: Option<String>,
+}
+
+const NOASSERTION: &str = "NOASSERTION";
+
+// License identifiers from the SPDX license list that package databases and
+// ecosystem metadata commonly declare
+const SPDX_LICENSES: &[&str] = &[
+    "0BSD", "AFL-2.1", "AFL-3.0", "AGPL-1.0-only", "AGPL-1.0-or-later", "AGPL-3.0-only", "AGPL-3.0-or-later", "Apache-1.0",
+    "Apache-1.1", "Apache-2.0", "APSL-2.0", "Artistic-1.0", "Artistic-1.0-Perl", "Artistic-2.0", "Beerware", "BlueOak-1.0.0",
+    "BSD-1-Clause", "BSD-2-Clause", "BSD-2-Clause-Patent", "BSD-3-Clause", "BSD-3-Clause-Clear", "BSD-4-Clause",
+    "BSD-Source-Code", "BSL-1.0", "bzip2-1.0.6", "CC-BY-3.0", "CC-BY-4.0", "CC-BY-SA-3.0", "CC-BY-SA-4.0", "CC0-1.0",
+    "CDDL-1.0", "CDDL-1.1", "CPL-1.0", "curl", "ECL-2.0", "EPL-1.0", "EPL-2.0", "EUPL-1.1", "EUPL-1.2", "FSFAP", "FSFUL",
+    "FSFULLR", "FTL", "GFDL-1.1-only", "GFDL-1.1-or-later", "GFDL-1.2-only", "GFDL-1.2-or-later", "GFDL-1.3-only",
+    "GFDL-1.3-or-later", "GPL-1.0-only", "GPL-1.0-or-later", "GPL-2.0-only", "GPL-2.0-or-later", "GPL-3.0-only",
+    "GPL-3.0-or-later", "HPND", "ICU", "IJG", "Info-ZIP", "IPL-1.0", "ISC", "LGPL-2.0-only", "LGPL-2.0-or-later",
+    "LGPL-2.1-only", "LGPL-2.1-or-later", "LGPL-3.0-only", "LGPL-3.0-or-later", "Libpng", "libpng-2.0", "libtiff",
+    "LPPL-1.3c", "MirOS", "MIT", "MIT-0", "MIT-CMU", "MPL-1.0", "MPL-1.1", "MPL-2.0", "MPL-2.0-no-copyleft-exception",
+    "MS-PL", "MS-RL", "NCSA", "NTP", "OFL-1.1", "OLDAP-2.8", "OpenSSL", "OSL-3.0", "PHP-3.0", "PHP-3.01", "PostgreSQL",
+    "PSF-2.0", "Python-2.0", "Ruby", "Sendmail", "Sleepycat", "TCL", "Unicode-3.0", "Unicode-DFS-2016", "Unlicense",
+    "UPL-1.0", "Vim", "W3C", "WTFPL", "X11", "Zlib", "ZPL-2.1",
+];
+
+// License exceptions from the SPDX exceptions list, which follow WITH
+const SPDX_EXCEPTIONS: &[&str] = &[
+    "Autoconf-exception-2.0", "Autoconf-exception-3.0", "Bison-exception-2.2", "Classpath-exception-2.0",
+    "Font-exception-2.0", "GCC-exception-2.0", "GCC-exception-3.1", "Libtool-exception", "Linux-syscall-note",
+    "LLVM-exception", "OCaml-LGPL-linking-exception", "OpenJDK-assembly-exception-1.0", "openvpn-openssl-exception",
+    "Qt-LGPL-exception-1.1", "Universal-FOSS-exception-1.0", "WxWindows-exception-3.1",
+];
+
+// GNU licenses whose bare SPDX identifiers are deprecated in favor of -only,
+// and whose `+` forms in favor of -or-later
+const GNU_LICENSES: &[&str] = &[
+    "GPL-1.0", "GPL-2.0", "GPL-3.0", "LGPL-2.0", "LGPL-2.1", "LGPL-3.0", "AGPL-1.0", "AGPL-3.0", "GFDL-1.1", "GFDL-1.2", "GFDL-1.3",
+];
+
+// Names packages use instead of SPDX identifiers, as (name, identifier, whether
+// the name leaves the version or variant open). Fedora's legacy short names
+// and the spelled-out names of Python classifiers are among them.
+const LICENSE_ALIASES: &[(&str, &str, bool)] = &[
+    ("GPL", "GPL-2.0-or-later", true),
+    ("GPLv2", "GPL-2.0-only", false),
+    ("GPL2", "GPL-2.0-only", false),
+    ("GPLv2+", "GPL-2.0-or-later", false),
+    ("GPL2+", "GPL-2.0-or-later", false),
+    ("GPLv3", "GPL-3.0-only", false),
+    ("GPL3", "GPL-3.0-only", false),
+    ("GPLv3+", "GPL-3.0-or-later", false),
+    ("GPL3+", "GPL-3.0-or-later", false),
+    ("GPL+", "GPL-1.0-or-later", false),
+    ("LGPL", "LGPL-2.1-or-later", true),
+    ("LGPLv2", "LGPL-2.0-only", false),
+    ("LGPLv2+", "LGPL-2.0-or-later", false),
+    ("LGPLv2.1", "LGPL-2.1-only", false),
+    ("LGPLv2.1+", "LGPL-2.1-or-later", false),
+    ("LGPLv3", "LGPL-3.0-only", false),
+    ("LGPLv3+", "LGPL-3.0-or-later", false),
+    ("AGPLv3", "AGPL-3.0-only", false),
+    ("AGPLv3+", "AGPL-3.0-or-later", false),
+    ("BSD", "BSD-3-Clause", true),
+    ("BSD-2", "BSD-2-Clause", false),
+    ("BSD-3", "BSD-3-Clause", false),
+    ("BSD-4", "BSD-4-Clause", false),
+    ("BSD License", "BSD-3-Clause", true),
+    ("MIT License", "MIT", false),
+    ("Expat", "MIT", false),
+    ("Apache", "Apache-2.0", true),
+    ("Apache 2", "Apache-2.0", false),
+    ("Apache 2.0", "Apache-2.0", false),
+    ("Apache2", "Apache-2.0", false),
+    ("Apache-2", "Apache-2.0", false),
+    ("Apache License 2.0", "Apache-2.0", false),
+    ("Apache License, Version 2.0", "Apache-2.0", false),
+    ("Apache Software License", "Apache-2.0", true),
+    ("ASL 2.0", "Apache-2.0", false),
+    ("ISC License", "ISC", false),
+    ("MPL", "MPL-2.0", true),
+    ("MPLv2.0", "MPL-2.0", false),
+    ("MPL 2.0", "MPL-2.0", false),
+    ("Boost", "BSL-1.0", false),
+    ("CC0", "CC0-1.0", false),
+    ("PSF", "PSF-2.0", false),
+    ("PSFL", "PSF-2.0", false),
+    ("Python Software Foundation License", "PSF-2.0", false),
+    ("zlib/libpng", "Zlib", false),
+];
+
+// Values that say no license was declared rather than naming one
+const UNDECLARED: &[&str] = &[NOASSERTION, "unknown"];
+
+enum Token {
+    Open,
+    Close,
+    Operator(&'static str),
+    Name(String),
+}
+
+// License texts are installed per package, in a directory named after it:
+// usr/share/licenses/<package>/ on Alpine, Fedora and Arch, and
+// usr/share/doc/<package>/copyright on Debian.
+pub fn license_text_package(path: &str) -> Option<&str> {
+    let path = normalize_path(path);
+    if let Some(rest) = path.strip_prefix("usr/share/licenses/") {
+        return rest.split_once('/').map(|(package, _)| package);
+    }
+    let (package, file) = path.strip_prefix("usr/share/doc/")?.split_once('/')?;
+    let file = file.to_ascii_lowercase();
+    (file == "copyright" || file.starts_with("license") || file.starts_with("copying")).then_some(package)
+}
+
+pub fn is_license_text_path(path: &str) -> bool {
+    license_text_package(path).is_some()
+}
+
+pub fn license_evidence(layers: &[Layer]) -> Vec<LicenseEvidence> {
+    // Later layers replace license texts of the same path
+    let mut texts: BTreeMap<String, BTreeMap<String, LicenseFile>> = BTreeMap::new();
+    for layer in layers {
+        for file in layer.files.iter().filter(|file| file.file_type == "file") {
+            let Some(rooted) = layer.rooted(&file.path) else { continue };
+            let Some(package) = license_text_package(rooted) else { continue };
+            let text = layer.contents.get(rooted).map(|data| String::from_utf8_lossy(data).into_owned());
+            texts.entry(package.to_string()).or_default().insert(
+                rooted.to_string(),
+                LicenseFile { path: rooted.to_string(), checksum: file.checksum.clone(), text },
+            );
+        }
+    }
+
+    let mut evidence: BTreeMap<String, LicenseEvidence> = BTreeMap::new();
+    for package in layers.iter().flat_map(|layer| &layer.packages) {
+        let license = match package.license.trim() {
+            "" => NOASSERTION,
+            license => license,
+        };
+        let entry = evidence.entry(license.to_string()).or_insert_with(|| LicenseEvidence {
+            license: license.to_string(),
+            packages: Vec::new(),
+            files: Vec::new(),
+        });
+        let id = format!("{}@{}", package.name, package.version);
+        if entry.packages.contains(&id) {
+            continue;
+        }
+        entry.packages.push(id);
+        for file in texts.get(&package.name).into_iter().flat_map(BTreeMap::values) {
+            if !entry.files.iter().any(|seen| seen.path == file.path) {
+                entry.files.push(file.clone());
+            }
+        }
+    }
+    for entry in evidence.values_mut() {
+        entry.packages.sort();
+        entry.files.sort_by(|a, b| a.path.cmp(&b.path));
+    }
+    evidence.into_values().collect()
+}
+
+// Reads a declared license as an SPDX license expression. Identifiers are
+// matched case-insensitively, deprecated GNU identifiers are replaced, and
+// common names such as GPLv2+ or "Apache 2.0" are mapped to identifiers,
+// with a warning for names that leave the version or variant open. A license
+// that cannot be read as an expression yields None and a warning.
+pub fn normalize_license(raw: &str) -> (Option<String>, Vec<Notice>) {
+    let raw = raw.trim();
+    if raw.is_empty() || UNDECLARED.iter().any(|undeclared| raw.eq_ignore_ascii_case(undeclared)) {
+        return (None, Vec::new());
+    }
+
+    // Words between operators and parentheses make up one license name
+    let mut tokens = Vec::new();
+    let mut words: Vec<&str> = Vec::new();
+    let spaced = raw.replace('(', " ( ").replace(')', " ) ");
+    for word in spaced.split_whitespace() {
+        let token = match word {
+            "(" => Token::Open,
+            ")" => Token::Close,
+            _ => match ["AND", "OR", "WITH"].into_iter().find(|operator| word.eq_ignore_ascii_case(operator)) {
+                Some(operator) => Token::Operator(operator),
+                None => {
+                    words.push(word);
+                    continue;
+                }
+            },
+        };
+        if !words.is_empty() {
+            tokens.push(Token::Name(words.join(" ")));
+            words.clear();
+        }
+        tokens.push(token);
+    }
+    if !words.is_empty() {
+        tokens.push(Token::Name(words.join(" ")));
+    }
+
+    let mut notices = Vec::new();
+    let mut expression = String::new();
+    let mut unknown = Vec::new();
+    // Whether a license name may come next, and how many parentheses are open
+    let (mut expect_name, mut depth, mut after_with) = (true, 0usize, false);
+    let mut well_formed = true;
+    for token in &tokens {
+        match token {
+            Token::Open if expect_name && !after_with => {
+                depth += 1;
+                expression.push('(');
+            }
+            Token::Close if !expect_name && depth > 0 => {
+                depth -= 1;
+                expression.push(')');
+            }
+            Token::Operator(operator) if !expect_name => {
+                expect_name = true;
+                after_with = *operator == "WITH";
+                expression.push_str(&format!(" {} ", operator));
+            }
+            Token::Name(name) if expect_name => {
+                let id = if after_with { exception_id(name) } else { license_id(name) };
+                match id {
+                    Some((id, guessed)) => {
+                        if guessed {
+                            notices.push(license_notice(format!("License \"{}\" does not say which version or variant applies; recorded as {}", name, id)));
+                        }
+                        expression.push_str(&id);
+                    }
+                    None => unknown.push(name.as_str()),
+                }
+                expect_name = false;
+                after_with = false;
+            }
+            _ => well_formed = false,
+        }
+    }
+    if !well_formed || expect_name || depth > 0 {
+        notices.push(license_notice(format!("License \"{}\" is not a well-formed SPDX license expression; recorded as NOASSERTION", raw)));
+        return (None, notices);
+    }
+    if !unknown.is_empty() {
+        notices.push(license_notice(format!(
+            "License \"{}\" is not an SPDX license expression ({} not recognized); recorded as NOASSERTION",
+            raw,
+            unknown.join(", ")
+        )));
+        return (None, notices);
+    }
+    (Some(expression), notices)
+}
+
+fn license_id(name: &str) -> Option<(String, bool)> {
+    if name.starts_with("LicenseRef-") {
+        return Some((name.to_string(), false));
+    }
+    if let Some(id) = SPDX_LICENSES.iter().find(|id| id.eq_ignore_ascii_case(name)) {
+        return Some((id.to_string(), false));
+    }
+    let (base, or_later) = match name.strip_suffix('+') {
+        Some(base) => (base, true),
+        None => (name, false),
+    };
+    if let Some(id) = GNU_LICENSES.iter().find(|id| id.eq_ignore_ascii_case(base)) {
+        return Some((format!("{}-{}", id, if or_later { "or-later" } else { "only" }), false));
+    }
+    // Other licenses take the + operator as is
+    if let Some(id) = SPDX_LICENSES.iter().find(|id| or_later && id.eq_ignore_ascii_case(base)) {
+        return Some((format!("{}+", id), false));
+    }
+    LICENSE_ALIASES.iter().find(|(alias, _, _)| alias.eq_ignore_ascii_case(name)).map(|(_, id, guessed)| (id.to_string(), *guessed))
+}
+
+fn exception_id(name: &str) -> Option<(String, bool)> {
+    SPDX_EXCEPTIONS.iter().find(|id| id.eq_ignore_ascii_case(name)).map(|id| (id.to_string(), false))
+}
+
+fn license_notice(message: String) -> Notice {
+    Notice { message, level: "warning".to_string(), location: None, rule: None }
+}
+
+// Records the SPDX license expression of every package, with a notice on the
+// package's layer for each license that had to be guessed or could not be read
+pub fn normalize_package_licenses(layers: &mut [Layer]) {
+    for layer in layers {
+        for package in &mut layer.packages {
+            let (spdx_license, notices) = normalize_license(&package.license);
+            package.spdx_license = spdx_license;
+            layer.notices.extend(notices.into_iter().map(|notice| Notice {
+                message: format!("{} {}: {}", package.name, package.version, notice.message),
+                ..notice
+            }));
+        }
+    }
+}
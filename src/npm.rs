@@ -0,0 +1,130 @@
+use std::collections::BTreeSet;
+use serde::Deserialize;
+use crate::checks::normalize_path;
+use crate::detectors::{resolve_link_target, MAX_LINK_DEPTH};
+use crate::{Confidence, Detection, LayerContents, LayerLinks, Package};
+
+#[derive(Debug, Deserialize)]
+struct PackageJson {
+    name: Option<String>,
+    version: Option<String>,
+    license: Option<License>,
+    // Deprecated form: "licenses": [{"type": "MIT"}]
+    licenses: Option<Vec<License>>,
+    author: Option<Person>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum License {
+    Expression(String),
+    Object {
+        #[serde(rename = "type")]
+        kind: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Person {
+    // "Name <email> (url)"
+    Text(String),
+    Object { name: Option<String> },
+}
+
+// The manifest of an installed package: node_modules/<name>/package.json or
+// node_modules/@scope/<name>/package.json, at any depth, so transitive
+// dependencies nested in a package's own node_modules are included
+pub fn is_package_manifest_path(path: &str) -> bool {
+    let Some(package_dir) = normalize_path(path).strip_suffix("/package.json") else {
+        return false;
+    };
+    let components: Vec<&str> = package_dir.rsplit('/').take(3).collect();
+    match components.as_slice() {
+        [name, "node_modules", ..] => !name.starts_with('.'),
+        [name, scope, "node_modules"] => scope.starts_with('@') && !name.starts_with('.'),
+        _ => false,
+    }
+}
+
+// Lists the npm packages installed in a layer. pnpm keeps each package once in
+// its store (node_modules/.pnpm) and links it into place, so manifests are
+// counted once per resolved directory. The package directory is recorded as
+// the package source.
+pub fn read_npm_packages(contents: &LayerContents, links: &LayerLinks) -> Vec<Package> {
+    let mut paths: Vec<&String> = contents.keys().filter(|path| is_package_manifest_path(path)).collect();
+    paths.sort();
+
+    let mut seen = BTreeSet::new();
+    let mut packages = Vec::new();
+    for path in paths {
+        let package_dir = path.trim_end_matches("/package.json");
+        if !seen.insert(resolve_links(package_dir, links)) {
+            continue;
+        }
+        let Ok(manifest) = serde_json::from_slice::<PackageJson>(&contents[path]) else {
+            continue;
+        };
+        let (Some(name), Some(version)) = (manifest.name, manifest.version) else {
+            continue;
+        };
+        let license = match (manifest.license, manifest.licenses) {
+            (Some(license), _) => license.name(),
+            (None, Some(licenses)) => licenses.into_iter().map(License::name).collect::<Vec<_>>().join(" OR "),
+            (None, None) => String::new(),
+        };
+        let vendor = match manifest.author {
+            Some(Person::Text(author)) => author.split(['<', '(']).next().unwrap_or_default().trim().to_string(),
+            Some(Person::Object { name }) => name.unwrap_or_default(),
+            None => String::new(),
+        };
+
+        let mut package = Package {
+            name,
+            version,
+            source: format!("/{}", package_dir),
+            license,
+            vendor,
+            checksum: String::new(),
+            commands: Vec::new(),
+            purl: None,
+            install_reason: None,
+            depends: Vec::new(),
+            provides: Vec::new(),
+            built_at: None,
+            detection: Some(Detection::new(&format!("/{}", path), "npm", Confidence::High)),
+            spdx_license: None,
+            vulnerabilities: Vec::new(),
+        };
+        package.purl = Some(package.purl("npm"));
+        packages.push(package);
+    }
+    packages
+}
+
+impl License {
+    fn name(self) -> String {
+        match self {
+            License::Expression(expression) => expression,
+            License::Object { kind } => kind,
+        }
+    }
+}
+
+// Follows links on every component of a directory path
+fn resolve_links(path: &str, links: &LayerLinks) -> String {
+    let mut resolved = String::new();
+    for component in path.split('/') {
+        resolved = match resolved.is_empty() {
+            true => component.to_string(),
+            false => format!("{}/{}", resolved, component),
+        };
+        for _ in 0..MAX_LINK_DEPTH {
+            match links.get(&resolved) {
+                Some(target) => resolved = resolve_link_target(&resolved, target, true),
+                None => break,
+            }
+        }
+    }
+    resolved
+}
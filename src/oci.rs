@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, instrument};
+use crate::progress::{Progress, ProgressReporter};
+use crate::registry::{analyze_blob, default_platform, layer_commands, layer_created, platform_name, Descriptor, ImageConfig, ImageReference, Manifest, Result};
+use crate::source::AnalyzedImage;
+use crate::{cache, ExtractOptions, Healthcheck};
+
+// The annotation an OCI layout's index.json names each image by; skopeo and
+// buildah write a tag such as `1.0`, containerd the full reference
+const REF_NAME: &str = "org.opencontainers.image.ref.name";
+const CONTAINERD_NAME: &str = "io.containerd.image.name";
+
+// An image layout directory as written by `skopeo copy ... oci:DIR`, `buildah
+// push ... oci:DIR` or `docker buildx build --output type=oci,tar=false`:
+// index.json at the top and every manifest, config and layer stored by digest
+// under blobs/<algorithm>/<hex>
+struct OciLayout {
+    dir: PathBuf,
+    index: Manifest,
+}
+
+impl OciLayout {
+    fn open(dir: &Path) -> Result<OciLayout> {
+        let index_path = dir.join("index.json");
+        let data = fs::read(&index_path).map_err(|e| format!("cannot read {}: {}; is {} an OCI image layout?", index_path.display(), e, dir.display()))?;
+        let index = serde_json::from_slice(&data).map_err(|e| format!("cannot read {}: {}", index_path.display(), e))?;
+        Ok(OciLayout { dir: dir.to_path_buf(), index })
+    }
+
+    fn blob_path(&self, digest: &str) -> Result<PathBuf> {
+        let (algorithm, hex) = digest.split_once(':').ok_or_else(|| format!("malformed digest {}", digest))?;
+        if [algorithm, hex].iter().any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_alphanumeric())) {
+            return Err(format!("malformed digest {}", digest).into());
+        }
+        let path = self.dir.join("blobs").join(algorithm).join(hex);
+        if !path.is_file() {
+            return Err(format!("blob {} is missing from the OCI layout", digest).into());
+        }
+        Ok(path)
+    }
+
+    fn read_blob(&self, digest: &str) -> Result<Vec<u8>> {
+        let path = self.blob_path(digest)?;
+        Ok(fs::read(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?)
+    }
+
+    // The image a name picks out of index.json: the one whose ref.name is the
+    // name or its tag, or the only image when no name matches
+    fn image(&self, image_name: &str) -> Result<&Descriptor> {
+        let reference = ImageReference::parse(image_name);
+        let canonical = reference.canonical_name();
+        let named = self.index.manifests.iter().find(|descriptor| {
+            descriptor.annotations.get(REF_NAME).is_some_and(|name| name == image_name || *name == reference.reference)
+                || descriptor.annotations.get(CONTAINERD_NAME).is_some_and(|name| ImageReference::parse(name).canonical_name() == canonical)
+        });
+        if let Some(descriptor) = named {
+            return Ok(descriptor);
+        }
+        match self.index.manifests.as_slice() {
+            [descriptor] => Ok(descriptor),
+            [] => Err("the OCI layout's index.json lists no images".into()),
+            descriptors => Err(format!(
+                "the OCI layout holds {} images; name one of them: {}",
+                descriptors.len(),
+                descriptors.iter().filter_map(|descriptor| descriptor.annotations.get(REF_NAME)).cloned().collect::<Vec<_>>().join(", ")
+            )
+            .into()),
+        }
+    }
+
+    // Follows an image index down to the manifest for the requested platform,
+    // or this machine's
+    fn manifest(&self, descriptor: &Descriptor, platform: Option<&str>) -> Result<Manifest> {
+        let manifest: Manifest = serde_json::from_slice(&self.read_blob(&descriptor.digest)?)
+            .map_err(|e| format!("cannot read manifest {}: {}", descriptor.digest, e))?;
+        if manifest.manifests.is_empty() {
+            return Ok(manifest);
+        }
+        let platform = platform.map(str::to_string).unwrap_or_else(default_platform);
+        let descriptor = manifest
+            .manifests
+            .iter()
+            .find(|m| m.platform.as_ref().is_some_and(|p| p.matches(&platform)))
+            .ok_or_else(|| format!("no {} image in the image index {}", platform, descriptor.digest))?;
+        self.manifest(descriptor, Some(&platform))
+    }
+}
+
+// The name a layout's image goes by when none is given: the reference of its
+// first image, with a bare tag qualified by the directory name as skopeo's
+// oci:DIR:TAG does
+pub fn image_name(dir: &Path) -> Result<Option<String>> {
+    let layout = OciLayout::open(dir)?;
+    let Some(descriptor) = layout.index.manifests.first() else {
+        return Ok(None);
+    };
+    if let Some(name) = descriptor.annotations.get(CONTAINERD_NAME) {
+        return Ok(Some(name.clone()));
+    }
+    Ok(descriptor.annotations.get(REF_NAME).map(|name| match (name.contains(['/', ':']), dir.file_name()) {
+        (false, Some(dir_name)) => format!("{}:{}", dir_name.to_string_lossy(), name),
+        _ => name.clone(),
+    }))
+}
+
+// Reads an image from an OCI image layout directory. Layers are identified by
+// their blob digests from the manifest, and the image digest is the digest of
+// the manifest or index the layout's index.json points at.
+#[instrument(name = "analyze", skip_all, fields(image = %image_name, source = "oci-layout"))]
+pub fn analyze_oci_layout(dir: &Path, image_name: &str, platform: Option<&str>, options: ExtractOptions, progress: &ProgressReporter) -> Result<AnalyzedImage> {
+    let layout = OciLayout::open(dir)?;
+    let descriptor = layout.image(image_name)?;
+    let manifest = layout.manifest(descriptor, platform)?;
+    let config_descriptor = manifest
+        .config
+        .as_ref()
+        .ok_or_else(|| format!("unsupported manifest type {:?}", manifest.media_type))?;
+    let config: ImageConfig = serde_json::from_slice(&layout.read_blob(&config_descriptor.digest)?)
+        .map_err(|e| format!("cannot read the image config {}: {}", config_descriptor.digest, e))?;
+
+    let created = config.created.clone().unwrap_or_else(|| "Unknown".to_string());
+    let os_guess = config.os.clone().unwrap_or_else(|| "Unknown".to_string());
+    let platform = config
+        .os
+        .as_deref()
+        .zip(config.architecture.as_deref())
+        .map(|(os, architecture)| platform_name(os, architecture, config.variant.as_deref()));
+
+    let mut layers = Vec::new();
+    let total = manifest.layers.len();
+    for (index, layer) in manifest.layers.iter().enumerate() {
+        progress.report(Progress::LayerStarted { index, total, layer_id: layer.digest.clone() });
+        let analyzed = cache::analyze_layer(options, &layer.digest, &created, &os_guess, || {
+            analyze_blob(&layout.blob_path(&layer.digest)?, &layer.media_type, layer.digest.clone(), created.clone(), os_guess.clone(), options)
+        })?;
+        progress.report(Progress::LayerFinished { index, total, completed: index + 1, files: analyzed.files.len() });
+        layers.push(analyzed);
+    }
+
+    info!(layers = layers.len(), "image analyzed");
+    progress.report(Progress::Finished { layers: layers.len(), cached: false });
+    Ok(AnalyzedImage {
+        platform,
+        layer_commands: layer_commands(&config.history, layers.len()),
+        layer_created: layer_created(&config.history, layers.len()),
+        layers,
+        healthcheck: config.config.healthcheck.as_ref().and_then(Healthcheck::from_config),
+        entrypoint: config.config.entrypoint,
+        cmd: config.config.cmd,
+        labels: config.config.labels.unwrap_or_default(),
+        digest: Some(descriptor.digest.clone()),
+        image_id: Some(config_descriptor.digest.clone()),
+    })
+}
@@ -0,0 +1,281 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+use futures_util::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::{info, instrument};
+use crate::checks::Severity;
+use crate::registry::Result;
+use crate::{Layer, Notice};
+
+pub const OSV_API: &str = "https://api.osv.dev/v1";
+
+// OSV.dev takes at most 1000 queries per batch
+const BATCH_SIZE: usize = 1000;
+// Vulnerability records fetched at once
+const CONCURRENT_FETCHES: usize = 8;
+const TIMEOUT_SECS: u64 = 30;
+
+// A known vulnerability affecting a package, as recorded by OSV.dev
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vulnerability {
+    pub id: String,
+    // critical, high, medium or low: from the CVSS v3 base score when the
+    // record has one, otherwise the rating of the database it comes from
+    pub severity: Option<String>,
+    pub summary: Option<String>,
+}
+
+// Where a vulnerability ranks among findings; one without a rating is taken
+// as medium
+pub fn vulnerability_severity(vulnerability: &Vulnerability) -> Severity {
+    vulnerability.severity.as_deref().map_or(Severity::Medium, Severity::from_level)
+}
+
+#[derive(Deserialize)]
+struct BatchResponse {
+    results: Vec<BatchResult>,
+}
+
+#[derive(Deserialize)]
+struct BatchResult {
+    #[serde(default)]
+    vulns: Vec<VulnerabilityId>,
+}
+
+#[derive(Deserialize)]
+struct VulnerabilityId {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct Record {
+    id: String,
+    summary: Option<String>,
+    details: Option<String>,
+    #[serde(default)]
+    severity: Vec<RecordSeverity>,
+    database_specific: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct RecordSeverity {
+    #[serde(rename = "type")]
+    kind: String,
+    score: String,
+}
+
+// Looks up every package with a purl on OSV.dev and records the
+// vulnerabilities affecting it. When the lookup fails the SBOM is still
+// written, without vulnerability data and with a warning saying why.
+#[instrument(name = "scan", skip_all)]
+pub async fn scan_vulnerabilities(layers: &mut [Layer]) {
+    let purls: BTreeSet<String> = layers.iter().flat_map(|layer| &layer.packages).filter_map(|package| package.purl.clone()).filter(|purl| is_queryable(purl)).collect();
+    let found = match lookup(&purls.into_iter().collect::<Vec<_>>()).await {
+        Ok(found) => found,
+        Err(e) => {
+            if let Some(layer) = layers.last_mut() {
+                layer.notices.push(Notice {
+                    message: format!("Vulnerability scan against {} failed: {}; packages carry no vulnerability data", OSV_API, e),
+                    level: "warning".to_string(),
+                    location: None,
+                    rule: None,
+                });
+            }
+            return;
+        }
+    };
+    for package in layers.iter_mut().flat_map(|layer| &mut layer.packages) {
+        if let Some(vulnerabilities) = package.purl.as_ref().and_then(|purl| found.get(purl)) {
+            package.vulnerabilities = vulnerabilities.clone();
+        }
+    }
+    // Packages every layer rewriting a database lists again are counted once
+    let affected: BTreeSet<(&str, &str)> = crate::final_packages(layers)
+        .into_iter()
+        .flat_map(|(_, package)| package.purl.as_deref().into_iter().flat_map(|purl| package.vulnerabilities.iter().map(move |vulnerability| (purl, vulnerability.id.as_str()))))
+        .collect();
+    let vulnerable = affected.iter().map(|(purl, _)| purl).collect::<BTreeSet<_>>().len();
+    info!(vulnerable, vulnerabilities = affected.len(), "vulnerability scan finished");
+}
+
+// OSV has nothing on generic packages, and a purl without a version would
+// match every vulnerability ever recorded for the package
+fn is_queryable(purl: &str) -> bool {
+    !purl.starts_with("pkg:generic/") && purl.rsplit_once('@').is_some_and(|(_, version)| !version.is_empty())
+}
+
+async fn lookup(purls: &[String]) -> Result<HashMap<String, Vec<Vulnerability>>> {
+    let client = reqwest::Client::builder().timeout(Duration::from_secs(TIMEOUT_SECS)).build()?;
+    let mut ids_by_purl = HashMap::new();
+    for batch in purls.chunks(BATCH_SIZE) {
+        let queries: Vec<_> = batch.iter().map(|purl| json!({ "package": { "purl": purl } })).collect();
+        let response: BatchResponse = client
+            .post(format!("{}/querybatch", OSV_API))
+            .json(&json!({ "queries": queries }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        for (purl, result) in batch.iter().zip(response.results) {
+            ids_by_purl.insert(purl.clone(), result.vulns.into_iter().map(|vulnerability| vulnerability.id).collect::<Vec<_>>());
+        }
+    }
+
+    // The batch API returns only IDs; each record is fetched once however
+    // many packages it affects
+    let ids: BTreeSet<&String> = ids_by_purl.values().flatten().collect();
+    let records: HashMap<String, Vulnerability> = stream::iter(ids)
+        .map(|id| fetch_vulnerability(&client, id))
+        .buffer_unordered(CONCURRENT_FETCHES)
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .map(|record| record.map(|vulnerability| (vulnerability.id.clone(), vulnerability)))
+        .collect::<Result<_>>()?;
+    Ok(ids_by_purl
+        .into_iter()
+        .map(|(purl, ids)| (purl, ids.iter().filter_map(|id| records.get(id).cloned()).collect()))
+        .collect())
+}
+
+async fn fetch_vulnerability(client: &reqwest::Client, id: &str) -> Result<Vulnerability> {
+    let record: Record = client.get(format!("{}/vulns/{}", OSV_API, id)).send().await?.error_for_status()?.json().await?;
+    let score = record.severity.iter().filter(|severity| severity.kind == "CVSS_V3").find_map(|severity| cvss3_base_score(&severity.score));
+    let severity = match score {
+        Some(score) => severity_rating(score),
+        None => record
+            .database_specific
+            .as_ref()
+            .and_then(|database| database.get("severity")?.as_str())
+            .and_then(|severity| match severity.to_ascii_lowercase().as_str() {
+                "critical" => Some("critical"),
+                "high" => Some("high"),
+                "medium" | "moderate" => Some("medium"),
+                "low" => Some("low"),
+                _ => None,
+            }),
+    };
+    Ok(Vulnerability {
+        id: record.id,
+        severity: severity.map(str::to_string),
+        summary: record.summary.or_else(|| record.details.and_then(|details| details.lines().next().map(str::to_string))),
+    })
+}
+
+// The qualitative rating CVSS v3 gives a base score
+fn severity_rating(score: f64) -> Option<&'static str> {
+    match score {
+        score if score >= 9.0 => Some("critical"),
+        score if score >= 7.0 => Some("high"),
+        score if score >= 4.0 => Some("medium"),
+        score if score > 0.0 => Some("low"),
+        _ => None,
+    }
+}
+
+// The base score of a CVSS v3 vector such as
+// CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H, per the CVSS 3.1 specification
+fn cvss3_base_score(vector: &str) -> Option<f64> {
+    let mut parts = vector.split('/');
+    if !parts.next()?.starts_with("CVSS:3") {
+        return None;
+    }
+    let metrics: HashMap<&str, &str> = parts.filter_map(|part| part.split_once(':')).collect();
+    let changed = match *metrics.get("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let attack_vector = match *metrics.get("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        "P" => 0.2,
+        _ => return None,
+    };
+    let attack_complexity = match *metrics.get("AC")? {
+        "L" => 0.77,
+        "H" => 0.44,
+        _ => return None,
+    };
+    let privileges = match (*metrics.get("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let user_interaction = match *metrics.get("UI")? {
+        "N" => 0.85,
+        "R" => 0.62,
+        _ => return None,
+    };
+    let impact_of = |name: &str| match *metrics.get(name)? {
+        "H" => Some(0.56_f64),
+        "L" => Some(0.22),
+        "N" => Some(0.0),
+        _ => None,
+    };
+    let base_impact: f64 = 1.0 - (1.0 - impact_of("C")?) * (1.0 - impact_of("I")?) * (1.0 - impact_of("A")?);
+    let impact = if changed {
+        7.52 * (base_impact - 0.029) - 3.25 * (base_impact - 0.02).powi(15)
+    } else {
+        6.42 * base_impact
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * attack_vector * attack_complexity * privileges * user_interaction;
+    let score: f64 = if changed { 1.08 * (impact + exploitability) } else { impact + exploitability };
+    Some(round_up(score.min(10.0)))
+}
+
+// CVSS 3.1's Roundup: the smallest number with one decimal that is not below
+// the input, computed on integers to avoid floating-point error
+fn round_up(value: f64) -> f64 {
+    let scaled = (value * 100_000.0).round() as i64;
+    if scaled % 10_000 == 0 {
+        scaled as f64 / 100_000.0
+    } else {
+        (scaled / 10_000 + 1) as f64 / 10.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_cvss3_vectors() {
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+        assert_eq!(cvss3_base_score("CVSS:3.0/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+        // Changed scope, where PR:L weighs 0.68 rather than 0.62 and scores are
+        // capped at 10
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:R/S:C/C:L/I:L/A:N"), Some(6.1));
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:L/UI:N/S:C/C:H/I:H/A:H"), Some(9.9));
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:C/C:H/I:H/A:H"), Some(10.0));
+        // No impact scores 0 whatever the exploitability
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:N"), Some(0.0));
+    }
+
+    #[test]
+    fn rejects_other_cvss_versions_and_incomplete_vectors() {
+        assert_eq!(cvss3_base_score("AV:N/AC:L/Au:N/C:P/I:P/A:P"), None);
+        assert_eq!(cvss3_base_score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N/VC:H/VI:H/VA:H/SC:N/SI:N/SA:N"), None);
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H"), None);
+        assert_eq!(cvss3_base_score("CVSS:3.1/AV:X/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), None);
+    }
+
+    #[test]
+    fn rounds_up_to_one_decimal() {
+        assert_eq!(round_up(4.02), 4.1);
+        assert_eq!(round_up(4.0), 4.0);
+        // The specification's floating-point case: 4.000 000 000 000 001 is
+        // 4.0, not the 4.1 a plain ceiling of value * 10 gives
+        assert_eq!(round_up(4.000_000_000_000_001), 4.0);
+        assert_eq!(round_up(0.1 + 0.2), 0.3);
+    }
+}
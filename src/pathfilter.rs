@@ -0,0 +1,106 @@
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use crate::checks;
+use crate::registry::Result;
+
+// Paths left out unless --no-default-excludes is given: compiled Python,
+// documentation, man and info pages
+pub const DEFAULT_EXCLUDES: &[&str] = &["**/__pycache__", "**/*.pyc", "**/*.pyo", "usr/share/doc", "usr/share/man", "usr/share/info"];
+
+// Which layer entries are recorded, by glob patterns over their path in the
+// image. An entry matches a pattern when its path or one of its parent
+// directories does, so `usr/share/doc` covers everything below it. With
+// include patterns, only entries matching one of them are recorded; entries
+// matching an exclude pattern never are.
+#[derive(Debug)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    // The patterns as given, which decide whether a cached analysis applies
+    pub include_patterns: Vec<String>,
+    pub exclude_patterns: Vec<String>,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<PathFilter> {
+        let include_patterns: Vec<String> = include.iter().map(|pattern| normalize_pattern(pattern)).collect();
+        let exclude_patterns: Vec<String> = exclude.iter().map(|pattern| normalize_pattern(pattern)).collect();
+        Ok(PathFilter {
+            include: match include_patterns.is_empty() {
+                true => None,
+                false => Some(glob_set(&include_patterns)?),
+            },
+            exclude: glob_set(&exclude_patterns)?,
+            include_patterns,
+            exclude_patterns,
+        })
+    }
+
+    pub fn skips(&self, path: &str) -> bool {
+        let path = checks::normalize_path(path).trim_end_matches('/');
+        self.include.as_ref().is_some_and(|include| !matches(include, path)) || matches(&self.exclude, path)
+    }
+}
+
+fn normalize_pattern(pattern: &str) -> String {
+    checks::normalize_path(pattern).trim_end_matches('/').to_string()
+}
+
+// `*` and `?` stay within a path component; `**` spans directories
+fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|e| format!("invalid glob {:?}: {}", pattern, e))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
+fn matches(set: &GlobSet, path: &str) -> bool {
+    set.is_match(path) || path.match_indices('/').any(|(end, _)| set.is_match(&path[..end]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> PathFilter {
+        let strings = |patterns: &[&str]| patterns.iter().map(|pattern| pattern.to_string()).collect::<Vec<_>>();
+        PathFilter::new(&strings(include), &strings(exclude)).unwrap()
+    }
+
+    #[test]
+    fn default_excludes_skip_everything_below_them() {
+        let filter = filter(&[], DEFAULT_EXCLUDES);
+        assert!(filter.skips("usr/share/doc/bash/copyright"));
+        assert!(filter.skips("./usr/share/man/"));
+        assert!(filter.skips("/usr/lib/python3.12/__pycache__/os.cpython-312.pyc"));
+        assert!(filter.skips("app/main.pyc"));
+        assert!(!filter.skips("usr/share/docs/readme"));
+        assert!(!filter.skips("usr/bin/python3"));
+    }
+
+    #[test]
+    fn include_patterns_skip_everything_else() {
+        let filter = filter(&["app/"], &["app/tests"]);
+        assert!(!filter.skips("app"));
+        assert!(!filter.skips("app/main.py"));
+        assert!(filter.skips("etc/passwd"));
+        assert!(filter.skips("app/tests/test_main.py"));
+    }
+
+    #[test]
+    fn single_stars_stay_within_a_component() {
+        let filter = filter(&[], &["var/log/*.log", "**/*.tmp"]);
+        assert!(filter.skips("var/log/dpkg.log"));
+        assert!(!filter.skips("var/log/apt/history.log"));
+        assert!(filter.skips("tmp/build/a.tmp"));
+    }
+
+    #[test]
+    fn invalid_globs_are_errors() {
+        assert!(PathFilter::new(&[], &["usr/[".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::checks::normalize_path;
+use crate::{Confidence, Detection, LayerContents, Package};
+
+// pip (and setuptools) leave the core metadata of every installed
+// distribution in the site-packages directory of its interpreter, e.g.
+// usr/local/lib/python3.11/site-packages/requests-2.31.0.dist-info/METADATA.
+// Debian's interpreters use dist-packages instead. Each interpreter has its
+// own directory, so every Python version in the image is covered.
+pub fn is_python_metadata_path(path: &str) -> bool {
+    let path = normalize_path(path);
+    match path.rsplit_once('/') {
+        Some((dir, "METADATA" | "INSTALLER")) if dir.ends_with(".dist-info") => in_packages_dir(dir),
+        Some((dir, "PKG-INFO")) if dir.ends_with(".egg-info") => in_packages_dir(dir),
+        // Older setuptools writes egg-info as a single file
+        _ => path.ends_with(".egg-info") && in_packages_dir(path),
+    }
+}
+
+fn in_packages_dir(path: &str) -> bool {
+    let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+    matches!(parent.rsplit('/').next(), Some("site-packages" | "dist-packages"))
+}
+
+// Lists the distributions whose metadata a layer carries. The dist-info or
+// egg-info directory is recorded as the package source.
+pub fn read_python_packages(contents: &LayerContents) -> Vec<Package> {
+    let mut paths: Vec<&String> = contents
+        .keys()
+        .filter(|path| is_python_metadata_path(path) && !path.ends_with("/INSTALLER"))
+        .collect();
+    paths.sort();
+
+    let mut packages = Vec::new();
+    for path in paths {
+        let dir = path.strip_suffix("/METADATA").or_else(|| path.strip_suffix("/PKG-INFO")).unwrap_or(path);
+        // Conda writes dist-info for its Python packages too; the conda
+        // detector lists those
+        if contents
+            .get(&format!("{}/INSTALLER", dir))
+            .is_some_and(|installer| String::from_utf8_lossy(installer).trim() == "conda")
+        {
+            continue;
+        }
+
+        let fields = metadata_fields(&String::from_utf8_lossy(&contents[path]));
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.as_str())
+                .filter(|value| !value.is_empty() && *value != "UNKNOWN")
+        };
+        let (Some(name), Some(version)) = (field("Name"), field("Version")) else {
+            continue;
+        };
+        // License may hold the whole license text; its first line is usually
+        // the name. Trove classifiers are the last resort.
+        let license = field("License-Expression")
+            .or_else(|| field("License").and_then(|license| license.lines().next()))
+            .or_else(|| {
+                fields
+                    .iter()
+                    .filter(|(key, _)| key.eq_ignore_ascii_case("Classifier"))
+                    .find_map(|(_, classifier)| classifier.strip_prefix("License :: "))
+                    .map(|classifier| classifier.rsplit(" :: ").next().unwrap_or(classifier))
+            })
+            .unwrap_or_default();
+
+        let mut package = Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            source: format!("/{}", dir),
+            license: license.trim().to_string(),
+            vendor: field("Author").unwrap_or_default().to_string(),
+            checksum: String::new(),
+            commands: Vec::new(),
+            purl: None,
+            install_reason: None,
+            depends: Vec::new(),
+            provides: Vec::new(),
+            built_at: None,
+            detection: Some(Detection::new(&format!("/{}", path), "pip", Confidence::High)),
+            spdx_license: None,
+            vulnerabilities: Vec::new(),
+        };
+        package.purl = Some(package.purl("pypi"));
+        packages.push(package);
+    }
+    packages
+}
+
+// The header part of a core metadata file (RFC 822 style, continuation lines
+// indented); the description body after the first blank line is ignored
+fn metadata_fields(metadata: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = Vec::new();
+    for line in metadata.lines() {
+        if line.trim().is_empty() {
+            break;
+        }
+        match (line.starts_with([' ', '\t']), fields.last_mut()) {
+            (true, Some((_, value))) => {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+            _ => {
+                if let Some((key, value)) = line.split_once(':') {
+                    fields.push((key.trim().to_string(), value.trim().to_string()));
+                }
+            }
+        }
+    }
+    fields
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use data_encoding::BASE64;
+use serde::Deserialize;
+use tracing::{info, instrument};
+use crate::progress::{Progress, ProgressReporter};
+use crate::registry::{layer_commands, layer_created, HistoryEntry, ImageReference, Result, RuntimeConfig};
+use crate::source::AnalyzedImage;
+use crate::{analyze_layer_for_packages, cache, checks, detectors, read_contents, record_file, ExtractOptions, ExtractedFiles, Inspection, Healthcheck, Layer};
+
+const ROOTFUL_STORAGE: &str = "/var/lib/containers/storage";
+
+#[derive(Debug, Deserialize)]
+struct StoredImage {
+    id: String,
+    #[serde(default)]
+    digest: String,
+    #[serde(default)]
+    names: Vec<String>,
+    layer: String,
+    created: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StoredLayer {
+    id: String,
+    parent: Option<String>,
+    created: Option<String>,
+    #[serde(rename = "diff-digest")]
+    diff_digest: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageConfig {
+    os: Option<String>,
+    architecture: Option<String>,
+    variant: Option<String>,
+    #[serde(default)]
+    config: RuntimeConfig,
+    #[serde(default)]
+    history: Vec<HistoryEntry>,
+}
+
+// Finds podman's containers/storage, preferring the rootless location under
+// $XDG_DATA_HOME (or ~/.local/share) over the rootful one.
+pub fn default_storage_root() -> Result<PathBuf> {
+    let mut candidates = Vec::new();
+    if let Ok(data_home) = env::var("XDG_DATA_HOME") {
+        candidates.push(PathBuf::from(data_home).join("containers/storage"));
+    }
+    if let Ok(home) = env::var("HOME") {
+        candidates.push(PathBuf::from(home).join(".local/share/containers/storage"));
+    }
+    candidates.push(PathBuf::from(ROOTFUL_STORAGE));
+
+    match candidates.iter().find(|root| root.join("overlay-images/images.json").exists()) {
+        Some(root) => Ok(root.clone()),
+        None => Err(format!(
+            "podman storage not found; looked in {}",
+            candidates.iter().map(|root| root.display().to_string()).collect::<Vec<_>>().join(", ")
+        )
+        .into()),
+    }
+}
+
+// The repo digest recorded when the image was pulled; locally built images
+// have none
+pub fn repo_digest(storage_root: &Path, image_name: &str) -> Result<Option<String>> {
+    let images: Vec<StoredImage> = read_json(&storage_root.join("overlay-images/images.json"))?;
+    Ok(images
+        .into_iter()
+        .find(|image| matches_image(image, image_name))
+        .map(|image| image.digest)
+        .filter(|digest| !digest.is_empty()))
+}
+
+// Reads an image straight out of podman's overlay storage, without going
+// through a daemon or API socket.
+#[instrument(name = "analyze", skip_all, fields(image = %image_name, source = "podman"))]
+pub fn analyze_podman_image(storage_root: &Path, image_name: &str, options: ExtractOptions, progress: &ProgressReporter) -> Result<AnalyzedImage> {
+    if !storage_root.join("overlay").is_dir() {
+        return Err(format!("unsupported podman storage driver in {}; only overlay is supported", storage_root.display()).into());
+    }
+
+    let images: Vec<StoredImage> = read_json(&storage_root.join("overlay-images/images.json"))?;
+    let image = images
+        .iter()
+        .find(|image| matches_image(image, image_name))
+        .ok_or_else(|| format!("image {} not found in podman storage at {}", image_name, storage_root.display()))?;
+
+    let stored_layers: Vec<StoredLayer> = read_json(&storage_root.join("overlay-layers/layers.json"))?;
+    let stored_layers: HashMap<&str, &StoredLayer> = stored_layers.iter().map(|layer| (layer.id.as_str(), layer)).collect();
+
+    // Walk from the top layer down through the parents, then analyze bottom-up
+    let mut chain = Vec::new();
+    let mut next = Some(image.layer.as_str());
+    while let Some(id) = next {
+        let layer = stored_layers.get(id).ok_or_else(|| format!("layer {} missing from podman storage", id))?;
+        chain.push(*layer);
+        next = layer.parent.as_deref();
+    }
+    chain.reverse();
+
+    let config = read_image_config(storage_root, image);
+    let os_guess = config.as_ref().and_then(|config| config.os.clone()).unwrap_or_else(|| "Unknown".to_string());
+    let platform = config.as_ref().and_then(|config| {
+        let platform = format!("{}/{}", config.os.as_deref()?, config.architecture.as_deref()?);
+        Some(match &config.variant {
+            Some(variant) => format!("{}/{}", platform, variant),
+            None => platform,
+        })
+    });
+
+    let mut layers = Vec::new();
+    let total = chain.len();
+    for (index, stored) in chain.into_iter().enumerate() {
+        let layer_id = stored.diff_digest.clone().unwrap_or_else(|| stored.id.clone());
+        progress.report(Progress::LayerStarted { index, total, layer_id: layer_id.clone() });
+        let created = stored.created.clone().or_else(|| image.created.clone()).unwrap_or_else(|| "Unknown".to_string());
+        let layer = cache::analyze_layer(options, &layer_id, &created, &os_guess, || {
+            let diff_dir = storage_root.join("overlay").join(&stored.id).join("diff");
+            let extracted = analyze_layer_dir(&diff_dir, options)?;
+            let detected = analyze_layer_for_packages(&extracted, options.root_prefix);
+            Ok(Layer {
+                layer_id: layer_id.clone(),
+                created: created.clone(),
+                os_guess: os_guess.clone(),
+                pkg_format: detected.format.to_string(),
+                packages: detected.packages,
+                files: extracted.files,
+                notices: detected.notices,
+                analyzed_output: String::new(),
+                created_by: None,
+                contents: extracted.contents,
+                links: extracted.links,
+                dir_modes: extracted.dir_modes,
+                root_prefix: options.root_prefix,
+            })
+        })?;
+        progress.report(Progress::LayerFinished { index, total, completed: index + 1, files: layer.files.len() });
+        layers.push(layer);
+    }
+
+    info!(layers = layers.len(), "image analyzed");
+    progress.report(Progress::Finished { layers: layers.len(), cached: false });
+    Ok(AnalyzedImage {
+        platform,
+        layer_commands: layer_commands(config.as_ref().map_or(&[], |config| &config.history), layers.len()),
+        layer_created: layer_created(config.as_ref().map_or(&[], |config| &config.history), layers.len()),
+        layers,
+        healthcheck: config.as_ref().and_then(|config| config.config.healthcheck.as_ref().and_then(Healthcheck::from_config)),
+        entrypoint: config.as_ref().and_then(|config| config.config.entrypoint.clone()),
+        cmd: config.as_ref().and_then(|config| config.config.cmd.clone()),
+        labels: config.and_then(|config| config.config.labels).unwrap_or_default(),
+        digest: Some(image.digest.clone()).filter(|digest| !digest.is_empty()),
+        image_id: Some(format!("sha256:{}", image.id)),
+    })
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T> {
+    let data = fs::read(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+fn matches_image(image: &StoredImage, wanted: &str) -> bool {
+    if (wanted.len() >= 12 && image.id.starts_with(wanted)) || image.digest == wanted {
+        return true;
+    }
+    // Images built locally by podman are named localhost/<name>
+    let canonical = ImageReference::parse(wanted).canonical_name();
+    let local = if wanted.contains(':') { format!("localhost/{}", wanted) } else { format!("localhost/{}:latest", wanted) };
+    image.names.iter().any(|name| name == wanted || *name == canonical || *name == local)
+}
+
+// The image config is stored as "big data" keyed by the image digest, under a
+// file name that containers/storage base64-encodes when it has characters
+// outside [0-9a-z.].
+fn read_image_config(storage_root: &Path, image: &StoredImage) -> Option<ImageConfig> {
+    let key = format!("sha256:{}", image.id);
+    let file_name = if key.chars().all(|c| c == '.' || c.is_ascii_digit() || c.is_ascii_lowercase()) {
+        key
+    } else {
+        format!("={}", BASE64.encode(key.as_bytes()))
+    };
+    let path = storage_root.join("overlay-images").join(&image.id).join(file_name);
+    read_json(&path).ok()
+}
+
+// Hard links are recognised by inode: every path after the first one seen for
+// an inode is recorded as a link to it. Overlay whiteouts (0:0 character
+// devices) are recorded as the .wh.<name> entries an image tarball carries.
+fn analyze_layer_dir(root: &Path, options: ExtractOptions) -> Result<ExtractedFiles> {
+    let mut extracted = ExtractedFiles::default();
+    let mut inodes: HashMap<(u64, u64), String> = HashMap::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir)
+            .map_err(|e| format!("cannot read {}: {}", dir.display(), e))?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let metadata = fs::symlink_metadata(&path)?;
+            let mut relative = path.strip_prefix(root).unwrap_or(&path).display().to_string();
+            if metadata.file_type().is_char_device() && metadata.rdev() == 0 {
+                relative = match relative.rsplit_once('/') {
+                    Some((dir, name)) => format!("{}/.wh.{}", dir, name),
+                    None => format!(".wh.{}", relative),
+                };
+            }
+            if metadata.is_dir() {
+                pending.push(path.clone());
+                if let Some(rooted) = checks::strip_root(&relative, options.root_prefix) {
+                    extracted.dir_modes.insert(rooted.to_string(), metadata.mode() & 0o7777);
+                }
+            }
+            let retain = metadata.is_file() && options.retains(checks::strip_root(&relative, options.root_prefix));
+            if options.skips(&relative, retain) {
+                continue;
+            }
+
+            let contents = if !metadata.is_file() {
+                Some(read_contents(std::io::empty(), false, Inspection::Nothing)?)
+            } else {
+                let inspection = Inspection::of(checks::strip_root(&relative, options.root_prefix), metadata.mode());
+                match options.reads(metadata.len(), retain, inspection) {
+                    true => {
+                        let file = fs::File::open(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+                        Some(read_contents(file, retain, inspection).map_err(|e| format!("cannot read {}: {}", path.display(), e))?)
+                    }
+                    false => None,
+                }
+            };
+            let link = if metadata.file_type().is_symlink() {
+                let target = fs::read_link(&path)?;
+                Some(detectors::resolve_link_target(&relative, &target.display().to_string(), false))
+            } else if metadata.is_file() && metadata.nlink() > 1 {
+                match inodes.get(&(metadata.dev(), metadata.ino())) {
+                    Some(first) => Some(first.clone()),
+                    None => {
+                        inodes.insert((metadata.dev(), metadata.ino()), relative.clone());
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            record_file(&mut extracted, relative, metadata.len(), metadata.is_file(), link, contents, options);
+        }
+    }
+    Ok(extracted)
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock};
+use bollard::models::CreateImageInfo;
+
+// Set by --quiet: no progress bars are drawn
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+pub fn set_quiet(quiet: bool) {
+    let _ = QUIET.set(quiet);
+}
+
+pub fn quiet() -> bool {
+    QUIET.get().copied().unwrap_or(false)
+}
+
+// Progress of an image analysis, for front ends that draw their own display
+// instead of the CLI's terminal bars. Layers are numbered from 0 in image
+// order; registry layers are fetched in parallel, so their events can arrive
+// out of order.
+#[derive(Debug, Clone)]
+pub enum Progress {
+    // Layer `index` of `total` started downloading or exporting
+    LayerStarted { index: usize, total: usize, layer_id: String },
+    // Layer `index` of `total` was extracted and hashed; `completed` layers are done
+    LayerFinished { index: usize, total: usize, completed: usize, files: usize },
+    // Every layer was analyzed; `cached` is set when the result came from --cache-dir
+    Finished { layers: usize, cached: bool },
+}
+
+impl Progress {
+    // Share of the layers analyzed so far, from 0 to 100
+    pub fn percent(&self) -> u8 {
+        match self {
+            Progress::LayerStarted { index, total, .. } => (index * 100 / (*total).max(1)) as u8,
+            Progress::LayerFinished { completed, total, .. } => (completed * 100 / (*total).max(1)) as u8,
+            Progress::Finished { .. } => 100,
+        }
+    }
+}
+
+pub type ProgressCallback = Arc<dyn Fn(Progress) + Send + Sync>;
+
+// Hands events to the callback, if one was given. Cloning shares the callback,
+// so it can be moved into blocking tasks.
+#[derive(Clone, Default)]
+pub struct ProgressReporter {
+    callback: Option<ProgressCallback>,
+}
+
+impl ProgressReporter {
+    pub fn new(callback: ProgressCallback) -> ProgressReporter {
+        ProgressReporter { callback: Some(callback) }
+    }
+
+    pub fn report(&self, event: Progress) {
+        if let Some(callback) = &self.callback {
+            callback(event);
+        }
+    }
+}
+
+// Bytes downloaded so far of an image the daemon is pulling, summed over its
+// layers from the progress details of the pull stream. A layer's size is known
+// once it starts downloading; layers already present are not counted.
+#[derive(Debug, Default)]
+pub struct PullProgress {
+    // (downloaded, size) by layer id
+    layers: BTreeMap<String, (u64, u64)>,
+}
+
+impl PullProgress {
+    pub fn update(&mut self, info: &CreateImageInfo) {
+        let Some(id) = &info.id else {
+            return;
+        };
+        let detail = info.progress_detail.as_ref();
+        let current = detail.and_then(|detail| detail.current).and_then(|current| u64::try_from(current).ok());
+        let total = detail.and_then(|detail| detail.total).and_then(|total| u64::try_from(total).ok()).filter(|total| *total > 0);
+        match info.status.as_deref() {
+            Some("Downloading") => {
+                if let (Some(current), Some(total)) = (current, total) {
+                    self.layers.insert(id.clone(), (current.min(total), total));
+                }
+            }
+            // Extraction reports progress too, over the same bytes
+            Some("Verifying Checksum" | "Download complete" | "Extracting" | "Pull complete") => {
+                if let Some((downloaded, size)) = self.layers.get_mut(id) {
+                    *downloaded = *size;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.layers.values().map(|(downloaded, _)| downloaded).sum()
+    }
+
+    pub fn size(&self) -> u64 {
+        self.layers.values().map(|(_, size)| size).sum()
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+// SLSA-style build provenance recovered from image labels. Builders that
+// follow the OCI annotation conventions stamp where the image was built from;
+// nothing here is verified, it is only as trustworthy as the builder.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct Provenance {
+    pub builder_id: Option<String>,
+    pub source_uri: Option<String>,
+    pub source_revision: Option<String>,
+    pub finished_on: Option<String>,
+    pub base_image: Option<String>,
+}
+
+// Labels checked for each field, most specific convention first
+const SOURCE_URI_LABELS: &[&str] = &[
+    "org.opencontainers.image.source",
+    "org.label-schema.vcs-url",
+    "io.openshift.build.source-location",
+    "vcs-url",
+];
+const SOURCE_REVISION_LABELS: &[&str] = &[
+    "org.opencontainers.image.revision",
+    "org.label-schema.vcs-ref",
+    "io.openshift.build.commit.id",
+    "vcs-ref",
+];
+const FINISHED_ON_LABELS: &[&str] = &["org.opencontainers.image.created", "org.label-schema.build-date", "build-date"];
+
+// Cloud Native Buildpacks record the project source as JSON in a label
+// instead of the OCI annotations
+#[derive(Debug, Deserialize)]
+struct BuildpacksProject {
+    source: Option<BuildpacksSource>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildpacksSource {
+    #[serde(default)]
+    version: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    metadata: BTreeMap<String, serde_json::Value>,
+}
+
+// Fills in what the labels provide and returns the names of the fields that
+// stayed empty
+pub fn provenance_from_labels(labels: &BTreeMap<String, String>) -> (Provenance, Vec<&'static str>) {
+    let first = |names: &[&str]| names.iter().find_map(|name| labels.get(*name)).filter(|value| !value.is_empty()).cloned();
+    let buildpacks_source = labels
+        .get("io.buildpacks.project.metadata")
+        .and_then(|metadata| serde_json::from_str::<BuildpacksProject>(metadata).ok())
+        .and_then(|project| project.source);
+    let buildpacks_field = |field: Option<&serde_json::Value>| field.and_then(|value| value.as_str()).map(str::to_string);
+
+    let provenance = Provenance {
+        builder_id: builder_id(labels),
+        source_uri: first(SOURCE_URI_LABELS)
+            .or_else(|| buildpacks_field(buildpacks_source.as_ref()?.metadata.get("repository"))),
+        source_revision: first(SOURCE_REVISION_LABELS)
+            .or_else(|| buildpacks_field(buildpacks_source.as_ref()?.version.get("commit"))),
+        finished_on: first(FINISHED_ON_LABELS),
+        base_image: match (first(&["org.opencontainers.image.base.name"]), first(&["org.opencontainers.image.base.digest"])) {
+            (Some(name), Some(digest)) => Some(format!("{}@{}", name.split('@').next().unwrap_or(&name), digest)),
+            (name, digest) => name.or(digest),
+        },
+    };
+
+    let mut missing = Vec::new();
+    for (field, value) in [
+        ("builder_id", &provenance.builder_id),
+        ("source_uri", &provenance.source_uri),
+        ("source_revision", &provenance.source_revision),
+        ("finished_on", &provenance.finished_on),
+        ("base_image", &provenance.base_image),
+    ] {
+        if value.is_none() {
+            missing.push(field);
+        }
+    }
+    (provenance, missing)
+}
+
+// Builders rarely label themselves, but some leave recognizable labels behind
+fn builder_id(labels: &BTreeMap<String, String>) -> Option<String> {
+    let has_prefix = |prefix: &str| labels.keys().any(|name| name.starts_with(prefix));
+    if let Some(metadata) = labels.get("io.buildpacks.lifecycle.metadata") {
+        let version = serde_json::from_str::<serde_json::Value>(metadata)
+            .ok()
+            .and_then(|metadata| metadata["lifecycleVersion"].as_str().map(str::to_string));
+        return Some(match version {
+            Some(version) => format!("https://buildpacks.io/lifecycle@{}", version),
+            None => "https://buildpacks.io/lifecycle".to_string(),
+        });
+    }
+    if has_prefix("io.buildpacks.") {
+        return Some("https://buildpacks.io/lifecycle".to_string());
+    }
+    if has_prefix("io.openshift.build.") {
+        return Some("https://openshift.io/build".to_string());
+    }
+    labels.get("com.redhat.build-host").map(|host| format!("https://{}", host))
+}
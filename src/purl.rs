@@ -0,0 +1,89 @@
+use crate::Package;
+
+// Package types of packages that come from the OS package database
+const OS_TYPES: &[&str] = &["apk", "deb", "rpm", "generic"];
+
+// Distributions recognized from the vendor an rpm package records, as
+// (substring, purl namespace)
+const RPM_VENDORS: &[(&str, &str)] = &[
+    ("fedora", "fedora"),
+    ("centos", "centos"),
+    ("red hat", "redhat"),
+    ("rocky", "rocky"),
+    ("almalinux", "almalinux"),
+    ("amazon", "amazon"),
+    ("oracle", "oracle"),
+    ("suse", "opensuse"),
+];
+
+impl Package {
+    // The package URL of the package, given the format of the database or
+    // ecosystem it was read from (a layer's pkg_format for OS packages)
+    pub fn purl(&self, pkg_format: &str) -> String {
+        let vendor = self.vendor.to_ascii_lowercase();
+        let (kind, namespace, name) = match pkg_format {
+            "apk" => ("apk", Some("alpine"), encode(&self.name)),
+            // Ubuntu's own packages name Ubuntu as their maintainer
+            "deb" => ("deb", Some(if vendor.contains("ubuntu") { "ubuntu" } else { "debian" }), encode(&self.name)),
+            "rpm" => {
+                let distro = RPM_VENDORS.iter().find(|(name, _)| vendor.contains(name)).map(|(_, namespace)| *namespace);
+                ("rpm", distro, encode(&self.name))
+            }
+            "pip" | "pypi" => ("pypi", None, encode(&pep503_name(&self.name))),
+            // Scoped packages (@scope/name) keep the scope as the namespace
+            "npm" => ("npm", None, self.name.split('/').map(encode).collect::<Vec<_>>().join("/")),
+            // Module paths keep their slashes
+            "go" | "golang" => ("golang", None, self.name.split('/').map(encode).collect::<Vec<_>>().join("/")),
+            "composer" => ("composer", None, self.name.to_ascii_lowercase().split('/').map(encode).collect::<Vec<_>>().join("/")),
+            _ => ("generic", None, encode(&self.name)),
+        };
+        match namespace {
+            Some(namespace) => format!("pkg:{}/{}/{}@{}", kind, namespace, name, encode(&self.version)),
+            None => format!("pkg:{}/{}@{}", kind, name, encode(&self.version)),
+        }
+    }
+
+    // Whether the package comes from the OS package database rather than an
+    // ecosystem such as pypi or npm
+    pub fn is_os_package(&self) -> bool {
+        match &self.purl {
+            Some(purl) => purl.strip_prefix("pkg:").and_then(|purl| purl.split('/').next()).is_some_and(|kind| OS_TYPES.contains(&kind)),
+            None => true,
+        }
+    }
+}
+
+// Maven artifacts are named by their artifactId; the groupId, which packages
+// do not record, is the namespace
+pub fn maven_purl(group_id: Option<&str>, artifact_id: &str, version: &str) -> String {
+    match group_id {
+        Some(group_id) => format!("pkg:maven/{}/{}@{}", encode(group_id), encode(artifact_id), encode(version)),
+        None => format!("pkg:maven/{}@{}", encode(artifact_id), encode(version)),
+    }
+}
+
+// Percent-encodes everything but unreserved characters, so versions such as
+// Debian's 1:2.36-9+deb12u4 stay a single purl component
+fn encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// PEP 503: names compare case-insensitively with runs of -, _ and . alike
+pub fn pep503_name(name: &str) -> String {
+    let mut normalized = String::new();
+    for c in name.chars() {
+        match c {
+            '-' | '_' | '.' if normalized.ends_with('-') => {}
+            '-' | '_' | '.' => normalized.push('-'),
+            c => normalized.push(c.to_ascii_lowercase()),
+        }
+    }
+    normalized
+}
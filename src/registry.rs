@@ -0,0 +1,571 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use data_encoding::BASE64;
+use futures_util::stream::{self, StreamExt};
+use reqwest::header::{ACCEPT, AUTHORIZATION, RETRY_AFTER, WWW_AUTHENTICATE};
+use reqwest::{Response, StatusCode};
+use serde::Deserialize;
+use sha2::{Sha256, Digest};
+use tempfile::tempdir;
+use tracing::{info, instrument, warn};
+use bollard::models::HealthConfig;
+use crate::credentials::{self, Credentials};
+use crate::progress::{Progress, ProgressReporter};
+use crate::source::AnalyzedImage;
+use crate::{analyze_layer_files, analyze_layer_for_packages, cache, ExtractOptions, ExtractedFiles, Healthcheck, Layer, Notice};
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+const DOCKER_HUB_REGISTRY: &str = "registry-1.docker.io";
+const MAX_ATTEMPTS: u32 = 4;
+
+const MANIFEST_MEDIA_TYPES: &[&str] = &[
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+    "application/vnd.docker.distribution.manifest.v2+json",
+];
+
+#[derive(Debug, Clone)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    pub reference: String,
+}
+
+impl ImageReference {
+    // Splits an image name the way the Docker CLI does: the first path component
+    // is a registry only if it looks like a host, and bare Docker Hub names live
+    // under `library/`.
+    pub fn parse(image: &str) -> ImageReference {
+        let (name, reference) = match image.split_once('@') {
+            Some((name, digest)) => (name, digest.to_string()),
+            None => match image.rsplit_once(':') {
+                Some((name, tag)) if !tag.contains('/') => (name, tag.to_string()),
+                _ => (image, "latest".to_string()),
+            },
+        };
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((host, rest)) if host.contains('.') || host.contains(':') || host == "localhost" => {
+                (host.to_string(), rest.to_string())
+            }
+            Some(_) => (DOCKER_HUB_REGISTRY.to_string(), name.to_string()),
+            None => (DOCKER_HUB_REGISTRY.to_string(), format!("library/{}", name)),
+        };
+        let registry = if registry == "docker.io" { DOCKER_HUB_REGISTRY.to_string() } else { registry };
+
+        ImageReference { registry, repository, reference }
+    }
+
+    // Whether the reference names an immutable manifest digest rather than a tag
+    pub fn is_digest_pinned(&self) -> bool {
+        self.reference
+            .strip_prefix("sha256:")
+            .is_some_and(|digest| digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()))
+    }
+
+    // The fully qualified name as shown by podman and `docker image ls
+    // --digests`, e.g. docker.io/library/alpine:latest
+    pub fn canonical_name(&self) -> String {
+        let registry = if self.registry == DOCKER_HUB_REGISTRY { "docker.io" } else { &self.registry };
+        let separator = if self.reference.contains(':') { '@' } else { ':' };
+        format!("{}/{}{}{}", registry, self.repository, separator, self.reference)
+    }
+}
+
+// An image manifest or an image index; an OCI layout's index.json is one too
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub media_type: Option<String>,
+    pub config: Option<Descriptor>,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+    #[serde(default)]
+    pub manifests: Vec<Descriptor>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub platform: Option<Platform>,
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    pub fn name(&self) -> String {
+        platform_name(&self.os, &self.architecture, self.variant.as_deref())
+    }
+
+    pub fn matches(&self, requested: &str) -> bool {
+        platform_matches(&self.name(), requested)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImageConfig {
+    pub created: Option<String>,
+    pub os: Option<String>,
+    pub architecture: Option<String>,
+    pub variant: Option<String>,
+    pub rootfs: RootFs,
+    #[serde(default)]
+    pub config: RuntimeConfig,
+    #[serde(default)]
+    pub history: Vec<HistoryEntry>,
+}
+
+// The part of an image config's runtime settings recorded in the SBOM
+#[derive(Debug, Default, Deserialize)]
+pub struct RuntimeConfig {
+    #[serde(rename = "Healthcheck")]
+    pub healthcheck: Option<HealthConfig>,
+    #[serde(rename = "Labels", default)]
+    pub labels: Option<BTreeMap<String, String>>,
+    #[serde(rename = "Entrypoint", default)]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(rename = "Cmd", default)]
+    pub cmd: Option<Vec<String>>,
+}
+
+// One build step in an image config's history. Steps that only change
+// metadata (ENV, CMD, ...) are marked empty_layer and have no layer.
+#[derive(Debug, Deserialize)]
+pub struct HistoryEntry {
+    pub created: Option<String>,
+    pub created_by: Option<String>,
+    #[serde(default)]
+    pub empty_layer: bool,
+}
+
+// The command that created each of the image's `layers` layers, or nothing
+// when the history does not account for every layer
+pub fn layer_commands(history: &[HistoryEntry], layers: usize) -> Vec<Option<String>> {
+    per_layer(history, layers, |entry| entry.created_by.clone())
+}
+
+// When each of the image's `layers` layers was created, likewise
+pub fn layer_created(history: &[HistoryEntry], layers: usize) -> Vec<Option<String>> {
+    per_layer(history, layers, |entry| entry.created.clone())
+}
+
+fn per_layer(history: &[HistoryEntry], layers: usize, field: impl Fn(&HistoryEntry) -> Option<String>) -> Vec<Option<String>> {
+    let values: Vec<Option<String>> = history.iter().filter(|entry| !entry.empty_layer).map(field).collect();
+    match values.len() == layers {
+        true => values,
+        false => vec![None; layers],
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RootFs {
+    pub diff_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+pub struct RegistryClient {
+    http: reqwest::Client,
+    image: ImageReference,
+    // None to pull anonymously
+    credentials: Option<Credentials>,
+    // The Authorization header value sent with every request once the
+    // registry asked for authentication
+    authorization: Mutex<Option<String>>,
+}
+
+impl RegistryClient {
+    pub fn new(image: ImageReference) -> RegistryClient {
+        let credentials = credentials::lookup(&image.registry);
+        let authorization = credentials.as_ref().and_then(|credentials| credentials.registry_token.as_ref()).map(|token| format!("Bearer {}", token));
+        RegistryClient {
+            http: reqwest::Client::new(),
+            image,
+            credentials,
+            authorization: Mutex::new(authorization),
+        }
+    }
+
+    fn url(&self, kind: &str, reference: &str) -> String {
+        format!("https://{}/v2/{}/{}/{}", self.image.registry, self.image.repository, kind, reference)
+    }
+
+    // Issues a GET, authenticating on the first 401 and retrying rate-limited
+    // or transient failures with backoff.
+    async fn get(&self, url: &str, accept: &[&str]) -> Result<Response> {
+        let mut authenticated = false;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let mut request = self.http.get(url);
+            if !accept.is_empty() {
+                request = request.header(ACCEPT, accept.join(", "));
+            }
+            let authorization = self.authorization.lock().unwrap().clone();
+            if let Some(authorization) = authorization {
+                request = request.header(AUTHORIZATION, authorization);
+            }
+
+            match request.send().await {
+                Ok(response) if response.status() == StatusCode::UNAUTHORIZED && !authenticated => {
+                    authenticated = true;
+                    self.authenticate(&response).await?;
+                }
+                Ok(response) if is_transient(response.status()) && attempt < MAX_ATTEMPTS => {
+                    tokio::time::sleep(retry_delay(&response, attempt)).await;
+                }
+                Ok(response) => return Ok(response.error_for_status()?),
+                Err(e) if (e.is_connect() || e.is_timeout()) && attempt < MAX_ATTEMPTS => {
+                    tokio::time::sleep(backoff(attempt)).await;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    async fn authenticate(&self, challenge: &Response) -> Result<()> {
+        let header = challenge
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|value| value.to_str().ok())
+            .ok_or("registry requested authentication without a challenge")?;
+        if header.starts_with("Basic ") {
+            let (username, password) = match &self.credentials {
+                Some(Credentials { username: Some(username), password, .. }) => (username, password.as_deref().unwrap_or("")),
+                _ => return Err(format!("registry {} requires a username and password", self.image.registry).into()),
+            };
+            let basic = BASE64.encode(format!("{}:{}", username, password).as_bytes());
+            *self.authorization.lock().unwrap() = Some(format!("Basic {}", basic));
+            return Ok(());
+        }
+        let params = header
+            .strip_prefix("Bearer ")
+            .ok_or_else(|| format!("unsupported registry authentication scheme: {}", header))?;
+
+        let mut realm = None;
+        let mut query = Vec::new();
+        for param in params.split(',') {
+            if let Some((key, value)) = param.trim().split_once('=') {
+                let value = value.trim_matches('"').to_string();
+                match key {
+                    "realm" => realm = Some(value),
+                    _ => query.push((key.to_string(), value)),
+                }
+            }
+        }
+        if !query.iter().any(|(key, _)| key == "scope") {
+            query.push(("scope".to_string(), format!("repository:{}:pull", self.image.repository)));
+        }
+
+        let realm = realm.ok_or("registry authentication challenge has no realm")?;
+        // Identity tokens are exchanged for an access token with OAuth;
+        // passwords are sent to the token service with basic authentication
+        let request = match &self.credentials {
+            Some(Credentials { identity_token: Some(refresh_token), .. }) => {
+                query.extend([
+                    ("grant_type".to_string(), "refresh_token".to_string()),
+                    ("refresh_token".to_string(), refresh_token.clone()),
+                    ("client_id".to_string(), "cbom".to_string()),
+                ]);
+                self.http.post(&realm).form(&query)
+            }
+            Some(Credentials { username: Some(username), password, .. }) => self.http.get(&realm).query(&query).basic_auth(username, password.as_ref()),
+            _ => self.http.get(&realm).query(&query),
+        };
+        let response: TokenResponse = request.send().await?.error_for_status()?.json().await?;
+        let token = response.token.or(response.access_token).ok_or("registry returned no token")?;
+        *self.authorization.lock().unwrap() = Some(format!("Bearer {}", token));
+        Ok(())
+    }
+
+    async fn fetch_manifest(&self, reference: &str) -> Result<Manifest> {
+        let response = self.get(&self.url("manifests", reference), MANIFEST_MEDIA_TYPES).await?;
+        Ok(response.json().await?)
+    }
+
+    async fn fetch_blob_bytes(&self, digest: &str) -> Result<Vec<u8>> {
+        let response = self.get(&self.url("blobs", digest), &[]).await?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    // Streams a blob to disk, checking it against its digest. Failures while
+    // streaming the body restart the download.
+    #[instrument(name = "fetch", level = "debug", skip_all, fields(digest = %digest))]
+    async fn fetch_blob_to_file(&self, digest: &str, path: &Path) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.download_blob(digest, path).await {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt < MAX_ATTEMPTS => {
+                    warn!(digest = %digest, attempt, error = %e, "retrying blob download");
+                    tokio::time::sleep(backoff(attempt)).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    async fn download_blob(&self, digest: &str, path: &Path) -> Result<()> {
+        let response = self.get(&self.url("blobs", digest), &[]).await?;
+        let mut file = File::create(path)?;
+        let mut hasher = Sha256::new();
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            hasher.update(&chunk);
+            file.write_all(&chunk)?;
+        }
+
+        let actual = format!("sha256:{:x}", hasher.finalize());
+        if digest.starts_with("sha256:") && actual != digest {
+            return Err(format!("digest mismatch for blob {}: got {}", digest, actual).into());
+        }
+        Ok(())
+    }
+
+    // Resolves the image reference to a single-platform manifest, picking the
+    // requested platform (or the host platform) out of an image index.
+    async fn resolve_manifest(&self, platform: Option<&str>) -> Result<Manifest> {
+        let manifest = self.fetch_manifest(&self.image.reference).await?;
+        if manifest.manifests.is_empty() {
+            return Ok(manifest);
+        }
+
+        let platform = platform.map(str::to_string).unwrap_or_else(default_platform);
+        let descriptor = manifest
+            .manifests
+            .iter()
+            .find(|m| m.platform.as_ref().is_some_and(|p| p.matches(&platform)))
+            .ok_or_else(|| format!("no {} image in manifest list for {}", platform, self.image.reference))?;
+        self.fetch_manifest(&descriptor.digest).await
+    }
+}
+
+fn is_transient(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_millis(500 * 2u64.pow(attempt - 1))
+}
+
+// Honors the registry's Retry-After header when it is rate limiting us.
+fn retry_delay(response: &Response, attempt: u32) -> Duration {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_else(|| backoff(attempt))
+}
+
+pub fn platform_name(os: &str, architecture: &str, variant: Option<&str>) -> String {
+    match variant {
+        Some(variant) => format!("{}/{}/{}", os, architecture, variant),
+        None => format!("{}/{}", os, architecture),
+    }
+}
+
+// Whether `platform`, such as linux/arm64/v8, is the requested one. A
+// requested platform without a variant matches any variant.
+pub fn platform_matches(platform: &str, requested: &str) -> bool {
+    let mut parts = platform.split('/');
+    let mut requested = requested.split('/');
+    let (os, architecture, variant) = (requested.next(), requested.next(), requested.next());
+    os == parts.next() && architecture == parts.next() && (variant.is_none() || variant == parts.next())
+}
+
+// The platform picked from an image index when none is requested
+pub fn default_platform() -> String {
+    format!("linux/{}", host_architecture())
+}
+
+fn host_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "x86" => "386",
+        "powerpc64" => "ppc64le",
+        arch => arch,
+    }
+}
+
+// The digest of the manifest or image index the reference points at, as shown
+// in `docker image ls --digests`. It covers every platform of an index.
+pub async fn repo_digest(image_name: &str) -> Result<String> {
+    let client = RegistryClient::new(ImageReference::parse(image_name));
+    if client.image.reference.contains(':') {
+        return Ok(client.image.reference.clone());
+    }
+    let response = client.get(&client.url("manifests", &client.image.reference), MANIFEST_MEDIA_TYPES).await?;
+    let manifest = response.bytes().await?;
+    Ok(format!("sha256:{:x}", Sha256::digest(&manifest)))
+}
+
+// Lists the platforms of a multi-arch image index. Entries without a real
+// platform, such as attestation manifests, are skipped. A single-platform
+// image yields an empty list.
+pub async fn list_platforms(image_name: &str) -> Result<Vec<String>> {
+    let client = RegistryClient::new(ImageReference::parse(image_name));
+    let manifest = client.fetch_manifest(&client.image.reference).await?;
+    Ok(manifest
+        .manifests
+        .iter()
+        .filter_map(|m| m.platform.as_ref())
+        .filter(|p| p.os != "unknown")
+        .map(Platform::name)
+        .collect())
+}
+
+// Analyzes an image straight from its registry without a Docker daemon. Up to
+// `jobs` layer blobs are downloaded at once and each is analyzed as soon as it
+// arrives; layers are returned in image order along with the resolved platform.
+#[instrument(name = "analyze", skip_all, fields(image = %image_name, source = "registry"))]
+pub async fn analyze_registry_image(
+    image_name: &str,
+    platform: Option<&str>,
+    jobs: usize,
+    options: ExtractOptions,
+    progress: &ProgressReporter,
+) -> Result<AnalyzedImage> {
+    let client = RegistryClient::new(ImageReference::parse(image_name));
+    let manifest = client.resolve_manifest(platform).await?;
+    let config_descriptor = manifest
+        .config
+        .as_ref()
+        .ok_or_else(|| format!("unsupported manifest type {:?}", manifest.media_type))?;
+    let config: ImageConfig = serde_json::from_slice(&client.fetch_blob_bytes(&config_descriptor.digest).await?)?;
+
+    let created = config.created.clone().unwrap_or_else(|| "Unknown".to_string());
+    let os_guess = config.os.clone().unwrap_or_else(|| "Unknown".to_string());
+    let resolved_platform = platform_name(
+        &os_guess,
+        config.architecture.as_deref().unwrap_or("unknown"),
+        config.variant.as_deref(),
+    );
+    let temp_dir = tempdir()?;
+    let total = manifest.layers.len();
+    let completed = AtomicUsize::new(0);
+
+    let mut layers: Vec<(usize, Layer)> = stream::iter(manifest.layers.iter().enumerate())
+        .map(|(index, descriptor)| {
+            let client = &client;
+            let blob_path = temp_dir.path().join(descriptor.digest.replace(':', "_"));
+            let layer_id = config.rootfs.diff_ids.get(index).cloned().unwrap_or_else(|| descriptor.digest.clone());
+            let created = created.clone();
+            let os_guess = os_guess.clone();
+            let completed = &completed;
+            async move {
+                progress.report(Progress::LayerStarted { index, total, layer_id: layer_id.clone() });
+                // Layers analyzed before are not downloaded again
+                let layer = match cache::cached_layer(options, &layer_id, &created, &os_guess) {
+                    Some(layer) => layer,
+                    None => {
+                        client.fetch_blob_to_file(&descriptor.digest, &blob_path).await?;
+                        let media_type = descriptor.media_type.clone();
+                        let layer = tokio::task::spawn_blocking(move || {
+                            analyze_blob(&blob_path, &media_type, layer_id, created, os_guess, options)
+                        })
+                        .await??;
+                        cache::store_layer(options, &layer);
+                        layer
+                    }
+                };
+                progress.report(Progress::LayerFinished {
+                    index,
+                    total,
+                    completed: completed.fetch_add(1, Ordering::SeqCst) + 1,
+                    files: layer.files.len(),
+                });
+                Ok::<_, Box<dyn Error + Send + Sync>>((index, layer))
+            }
+        })
+        .buffer_unordered(jobs.max(1))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect::<Result<_>>()?;
+
+    layers.sort_by_key(|(index, _)| *index);
+    info!(layers = layers.len(), platform = %resolved_platform, "image analyzed");
+    progress.report(Progress::Finished { layers: layers.len(), cached: false });
+    Ok(AnalyzedImage {
+        platform: Some(resolved_platform),
+        layers: layers.into_iter().map(|(_, layer)| layer).collect(),
+        healthcheck: config.config.healthcheck.as_ref().and_then(Healthcheck::from_config),
+        labels: config.config.labels.unwrap_or_default(),
+        entrypoint: config.config.entrypoint,
+        cmd: config.config.cmd,
+        layer_commands: layer_commands(&config.history, total),
+        layer_created: layer_created(&config.history, total),
+        digest: repo_digest(image_name).await.ok(),
+        image_id: Some(config_descriptor.digest.clone()),
+    })
+}
+
+// Analyzes a downloaded layer blob, compressed or not, as its media type says
+pub fn analyze_blob(
+    blob_path: &Path,
+    media_type: &str,
+    layer_id: String,
+    created: String,
+    os_guess: String,
+    options: ExtractOptions,
+) -> Result<Layer> {
+    let blob = File::open(blob_path)?;
+    let mut notices = Vec::new();
+    let extracted = if media_type.ends_with("gzip") {
+        analyze_layer_files(flate2::read::GzDecoder::new(blob), options)?
+    } else if media_type.ends_with("tar") {
+        analyze_layer_files(blob, options)?
+    } else {
+        notices.push(Notice {
+            message: format!("Unsupported layer media type {}; layer contents were not analyzed", media_type),
+            level: "warning".to_string(),
+            location: None,
+            rule: None,
+        });
+        ExtractedFiles::default()
+    };
+    let detected = analyze_layer_for_packages(&extracted, options.root_prefix);
+    notices.extend(detected.notices);
+
+    Ok(Layer {
+        layer_id,
+        created,
+        os_guess,
+        pkg_format: detected.format.to_string(),
+        packages: detected.packages,
+        files: extracted.files,
+        notices,
+        analyzed_output: String::new(),
+        created_by: None,
+        contents: extracted.contents,
+        links: extracted.links,
+        dir_modes: extracted.dir_modes,
+        root_prefix: options.root_prefix,
+    })
+}
@@ -0,0 +1,99 @@
+use std::io::Write;
+use rusqlite::{Connection, OpenFlags};
+use crate::registry::Result;
+use crate::{epoch_to_rfc3339, Confidence, Detection, Package};
+
+// Newer RPM distributions (Fedora 33+, RHEL 9, UBI 9) keep the package
+// database in SQLite; older ones (RHEL 8, CentOS 7) use Berkeley DB, and SUSE
+// its own ndb format. Only SQLite can be read.
+pub const RPMDB_SQLITE: &str = "var/lib/rpm/rpmdb.sqlite";
+pub const RPMDB_UNSUPPORTED: &[&str] = &["var/lib/rpm/Packages", "var/lib/rpm/Packages.db", "usr/lib/sysimage/rpm/Packages.db"];
+
+const TAG_NAME: u32 = 1000;
+const TAG_VERSION: u32 = 1001;
+const TAG_RELEASE: u32 = 1002;
+const TAG_BUILDTIME: u32 = 1006;
+const TAG_VENDOR: u32 = 1011;
+const TAG_LICENSE: u32 = 1014;
+const TAG_URL: u32 = 1020;
+
+const TYPE_INT32: u32 = 4;
+const TYPE_STRING: u32 = 6;
+const TYPE_I18NSTRING: u32 = 9;
+
+// Reads every installed package from an rpmdb.sqlite. SQLite needs a file to
+// open, so the database is copied out of the layer first.
+pub fn read_sqlite_packages(database: &[u8]) -> Result<Vec<Package>> {
+    let mut file = tempfile::NamedTempFile::new()?;
+    file.write_all(database)?;
+    file.flush()?;
+
+    let connection = Connection::open_with_flags(file.path(), OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare("SELECT blob FROM Packages")?;
+    let blobs = statement.query_map([], |row| row.get::<_, Vec<u8>>(0))?;
+
+    let mut packages = Vec::new();
+    for blob in blobs {
+        // gpg-pubkey entries are imported signing keys, not packages
+        if let Some(package) = header_package(&blob?).filter(|package| package.name != "gpg-pubkey") {
+            packages.push(package);
+        }
+    }
+    Ok(packages)
+}
+
+// An RPM header as stored in the database: the number of index entries and
+// the size of the data store, then the index entries (tag, type, offset,
+// count, all big-endian) and the data store they point into.
+fn header_package(blob: &[u8]) -> Option<Package> {
+    let be32 = |offset: usize| -> Option<u32> { Some(u32::from_be_bytes(blob.get(offset..offset + 4)?.try_into().ok()?)) };
+    let entries = be32(0)? as usize;
+    let data_size = be32(4)? as usize;
+    let data_start = 8 + entries.checked_mul(16)?;
+    let data = blob.get(data_start..data_start.checked_add(data_size)?)?;
+
+    let mut tags = std::collections::HashMap::new();
+    for index in 0..entries {
+        let entry = 8 + index * 16;
+        tags.insert(be32(entry)?, (be32(entry + 4)?, be32(entry + 8)? as usize));
+    }
+    let string = |tag: u32| -> Option<String> {
+        match tags.get(&tag)? {
+            (TYPE_STRING | TYPE_I18NSTRING, offset) => {
+                let value = data.get(*offset..)?;
+                let end = value.iter().position(|byte| *byte == 0)?;
+                Some(String::from_utf8_lossy(&value[..end]).into_owned())
+            }
+            _ => None,
+        }
+    };
+    let int32 = |tag: u32| -> Option<u32> {
+        match tags.get(&tag)? {
+            (TYPE_INT32, offset) => Some(u32::from_be_bytes(data.get(*offset..*offset + 4)?.try_into().ok()?)),
+            _ => None,
+        }
+    };
+
+    let version = match (string(TAG_VERSION), string(TAG_RELEASE)) {
+        (Some(version), Some(release)) => format!("{}-{}", version, release),
+        (Some(version), None) => version,
+        (None, _) => "unknown".to_string(),
+    };
+    Some(Package {
+        name: string(TAG_NAME)?,
+        version,
+        source: string(TAG_URL).unwrap_or_default(),
+        license: string(TAG_LICENSE).unwrap_or_default(),
+        vendor: string(TAG_VENDOR).unwrap_or_default(),
+        checksum: String::new(),
+        commands: Vec::new(),
+        purl: None,
+        install_reason: None,
+        depends: Vec::new(),
+        provides: Vec::new(),
+        built_at: int32(TAG_BUILDTIME).and_then(|seconds| epoch_to_rfc3339(&seconds.to_string())),
+        detection: Some(Detection::new(&format!("/{}", RPMDB_SQLITE), "rpm", Confidence::High)),
+        spdx_license: None,
+        vulnerabilities: Vec::new(),
+    })
+}
@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use serde::Serialize;
+use crate::checks::{self, Severity};
+use crate::osv::{vulnerability_severity, Vulnerability};
+use crate::registry::Result;
+use crate::sbomdiff::SbomDiff;
+use crate::{Layer, TOOL_VERSION};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+// Checks that raise findings, as (rule id, description). Findings from
+// detectors and other informational notices carry no rule and are reported
+// under "notice". Vulnerabilities and the changes `diff` reports have rules
+// of their own.
+const RULES: &[(&str, &str)] = &[
+    ("toolchain-package", "Build toolchain package present in the final image"),
+    ("malware", "Package or file listed in the malware database"),
+    ("healthcheck", "Healthcheck depends on external hosts or dynamic commands"),
+    ("remote-source", "Content fetched from a remote URL at build time"),
+    ("world-writable-dir", "World-writable directory without the sticky bit"),
+    ("package-cache", "Package manager cache left in the image"),
+    ("certificate-expiry", "Certificate expired or about to expire"),
+    ("pid1-signals", "Shell-form entrypoint runs as PID 1 without an init"),
+    ("systemd-service", "Enabled systemd service runs as root or listens on the network"),
+    ("notice", "Informational notice from image analysis"),
+    ("vulnerability", "Package with a known vulnerability on OSV.dev"),
+    ("vulnerability-introduced", "Added or upgraded package brings in a known vulnerability"),
+    ("package-added", "Package added since the earlier SBOM"),
+    ("package-removed", "Package removed since the earlier SBOM"),
+    ("package-changed", "Package version changed since the earlier SBOM"),
+];
+
+// A SARIF 2.1.0 log with one run of cbom. Findings of several images or
+// platforms go into the same run and are told apart by their properties.
+#[derive(Debug, Default)]
+pub struct SarifReport {
+    results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct SarifLog<'a> {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<SarifRun<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct SarifRun<'a> {
+    tool: SarifTool,
+    results: &'a [SarifResult],
+}
+
+#[derive(Debug, Serialize)]
+struct SarifTool {
+    driver: SarifDriver,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifDriver {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: Vec<SarifRule>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRule {
+    id: &'static str,
+    short_description: SarifMessage,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: String,
+    level: &'static str,
+    message: SarifMessage,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    locations: Vec<SarifLocation>,
+    properties: BTreeMap<&'static str, String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SarifMessage {
+    text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifLocation {
+    physical_location: SarifPhysicalLocation,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifPhysicalLocation {
+    artifact_location: SarifArtifactLocation,
+    region: SarifRegion,
+}
+
+#[derive(Debug, Serialize)]
+struct SarifArtifactLocation {
+    uri: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRegion {
+    start_line: usize,
+}
+
+impl SarifReport {
+    // Adds every finding of an analyzed image at or above `min_severity`. Code
+    // scanning only shows results with a location, so findings that do not
+    // point at a line of a file are placed on the first line of
+    // `fallback_file` (the Dockerfile) when one is given.
+    pub fn add_findings(&mut self, layers: &[Layer], image: &str, platform: Option<&str>, fallback_file: Option<&str>, min_severity: Severity) {
+        let findings = layers.iter().flat_map(|layer| layer.notices.iter().map(move |notice| (layer, notice)));
+        for (layer, notice) in findings.filter(|(_, notice)| checks::is_shown(notice, min_severity)) {
+            let location = match (&notice.location, fallback_file) {
+                (Some(location), _) => Some((location.file.clone(), location.line)),
+                (None, Some(file)) => Some((file.to_string(), 1)),
+                (None, None) => None,
+            };
+            let mut properties = BTreeMap::from([
+                ("image", image.to_string()),
+                ("layer", layer.layer_id.clone()),
+                ("severity", notice.level.to_ascii_lowercase()),
+            ]);
+            if let Some(platform) = platform {
+                properties.insert("platform", platform.to_string());
+            }
+            let rule_id = notice.rule.clone().unwrap_or_else(|| "notice".to_string());
+            self.push(rule_id, Severity::from_level(&notice.level), notice.message.clone(), location, properties);
+        }
+    }
+
+    // Adds a result for each known vulnerability of each package in the final
+    // image, at or above `min_severity`, placed like findings without a line.
+    // A package listed by several layers is reported once.
+    pub fn add_vulnerabilities(&mut self, layers: &[Layer], image: &str, platform: Option<&str>, fallback_file: Option<&str>, min_severity: Severity) {
+        let mut reported = BTreeSet::new();
+        for (index, package) in crate::final_packages(layers) {
+            for vulnerability in &package.vulnerabilities {
+                let severity = vulnerability_severity(vulnerability);
+                if severity < min_severity || !reported.insert((package.purl.as_deref(), vulnerability.id.as_str())) {
+                    continue;
+                }
+                let mut properties = vulnerability_properties(vulnerability, &package.name, &package.version, package.purl.as_deref());
+                properties.insert("image", image.to_string());
+                properties.insert("layer", layers[index].layer_id.clone());
+                if let Some(platform) = platform {
+                    properties.insert("platform", platform.to_string());
+                }
+                let message = vulnerability_message(vulnerability, &format!("{} {} is affected by", package.name, package.version));
+                self.push("vulnerability".to_string(), severity, message, fallback_file.map(|file| (file.to_string(), 1)), properties);
+            }
+        }
+    }
+
+    // Adds the packages `diff` found added, removed and changed as notes,
+    // and each vulnerability an added or upgraded package brings in at its
+    // severity. Results are placed on the first line of `sbom_file`, the
+    // later SBOM.
+    pub fn add_diff(&mut self, diff: &SbomDiff, sbom_file: &str) {
+        let location = || Some((sbom_file.to_string(), 1));
+        let images = || BTreeMap::from([("old-image", diff.old.image.clone()), ("new-image", diff.new.image.clone())]);
+        for package in &diff.added {
+            let mut properties = images();
+            properties.extend(package.purl.clone().map(|purl| ("purl", purl)));
+            self.push("package-added".to_string(), Severity::Info, format!("{} {} was added", package.name, package.version), location(), properties);
+        }
+        for package in &diff.removed {
+            let mut properties = images();
+            properties.extend(package.purl.clone().map(|purl| ("purl", purl)));
+            self.push("package-removed".to_string(), Severity::Info, format!("{} {} was removed", package.name, package.version), location(), properties);
+        }
+        for change in &diff.changed {
+            let mut properties = images();
+            properties.extend(change.purl.clone().map(|purl| ("purl", purl)));
+            let message = format!("{} changed from {} to {}", change.name, change.old_version, change.new_version);
+            self.push("package-changed".to_string(), Severity::Info, message, location(), properties);
+        }
+
+        let introduced = diff
+            .added
+            .iter()
+            .map(|package| (&package.name, &package.version, &package.purl, &package.vulnerabilities))
+            .chain(diff.changed.iter().map(|change| (&change.name, &change.new_version, &change.purl, &change.vulnerabilities)));
+        for (name, version, purl, vulnerabilities) in introduced {
+            for vulnerability in vulnerabilities {
+                let mut properties = vulnerability_properties(vulnerability, name, version, purl.as_deref());
+                properties.extend(images());
+                let message = vulnerability_message(vulnerability, &format!("{} {} brings in", name, version));
+                self.push("vulnerability-introduced".to_string(), vulnerability_severity(vulnerability), message, location(), properties);
+            }
+        }
+    }
+
+    fn push(&mut self, rule_id: String, severity: Severity, message: String, location: Option<(String, usize)>, mut properties: BTreeMap<&'static str, String>) {
+        let level = match severity {
+            Severity::Critical | Severity::High => "error",
+            Severity::Medium => "warning",
+            Severity::Low | Severity::Info => "note",
+        };
+        // GitHub ranks security results by this CVSS-like score
+        if let Some(score) = security_severity(severity) {
+            properties.insert("security-severity", score.to_string());
+        }
+        self.results.push(SarifResult {
+            rule_id,
+            level,
+            message: SarifMessage { text: message },
+            locations: location
+                .map(|(file, line)| SarifLocation {
+                    physical_location: SarifPhysicalLocation {
+                        artifact_location: SarifArtifactLocation { uri: file },
+                        region: SarifRegion { start_line: line.max(1) },
+                    },
+                })
+                .into_iter()
+                .collect(),
+            properties,
+        });
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> String {
+        let rules = RULES
+            .iter()
+            .map(|(id, description)| SarifRule {
+                id,
+                short_description: SarifMessage { text: description.to_string() },
+            })
+            .collect();
+        let log = SarifLog {
+            schema: SARIF_SCHEMA,
+            version: "2.1.0",
+            runs: vec![SarifRun {
+                tool: SarifTool {
+                    driver: SarifDriver {
+                        name: "cbom",
+                        version: TOOL_VERSION,
+                        information_uri: "https://github.com/sangam14/ContainerBOM",
+                        rules,
+                    },
+                },
+                results: &self.results,
+            }],
+        };
+        serde_json::to_string_pretty(&log).expect("Failed to serialize SARIF log")
+    }
+}
+
+// Vulnerabilities OSV has no rating for are reported as medium
+fn vulnerability_message(vulnerability: &Vulnerability, prefix: &str) -> String {
+    match &vulnerability.summary {
+        Some(summary) => format!("{} {}: {}", prefix, vulnerability.id, summary),
+        None => format!("{} {}", prefix, vulnerability.id),
+    }
+}
+
+fn vulnerability_properties(vulnerability: &Vulnerability, name: &str, version: &str, purl: Option<&str>) -> BTreeMap<&'static str, String> {
+    let mut properties = BTreeMap::from([
+        ("vulnerability", vulnerability.id.clone()),
+        ("package", name.to_string()),
+        ("version", version.to_string()),
+        ("severity", vulnerability.severity.clone().unwrap_or_else(|| "unrated".to_string())),
+    ]);
+    properties.extend(purl.map(|purl| ("purl", purl.to_string())));
+    properties
+}
+
+fn security_severity(severity: Severity) -> Option<f32> {
+    match severity {
+        Severity::Critical => Some(9.5),
+        Severity::High => Some(8.0),
+        Severity::Medium => Some(5.5),
+        Severity::Low => Some(3.0),
+        Severity::Info => None,
+    }
+}
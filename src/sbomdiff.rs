@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, BTreeSet};
+use prettytable::{row, Table};
+use serde::Serialize;
+use crate::layerdiff::{is_under, whiteout, Whiteout};
+use crate::osv::Vulnerability;
+use crate::{Layer, Package, Sbom};
+
+// The packages that differ between two SBOMs of an image, such as before and
+// after a base image update
+#[derive(Debug, Serialize)]
+pub struct SbomDiff {
+    pub old: DiffedImage,
+    pub new: DiffedImage,
+    pub added: Vec<DiffedPackage>,
+    pub removed: Vec<DiffedPackage>,
+    pub changed: Vec<VersionChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffedImage {
+    pub image: String,
+    pub digest: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DiffedPackage {
+    pub name: String,
+    pub version: String,
+    pub purl: Option<String>,
+    // Known vulnerabilities of the package, when its SBOM was written with
+    // --scan
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub vulnerabilities: Vec<Vulnerability>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionChange {
+    pub name: String,
+    pub purl: Option<String>,
+    pub old_version: String,
+    pub new_version: String,
+    // Vulnerabilities of the new version the old one did not have
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub vulnerabilities: Vec<Vulnerability>,
+}
+
+// A package is the same package across SBOMs when its name and its purl
+// without the version, qualifiers and subpath match: those change with the
+// version or the base image's distribution release
+type PackageKey = (String, Option<String>);
+
+// The versions, purls and vulnerabilities, by id, of one package in an image
+#[derive(Default)]
+struct Listed {
+    versions: BTreeSet<String>,
+    purls: BTreeSet<String>,
+    vulnerabilities: BTreeMap<String, Vulnerability>,
+}
+
+impl SbomDiff {
+    pub fn new(old: &Sbom, new: &Sbom) -> SbomDiff {
+        let old_packages = packages(old);
+        let new_packages = packages(new);
+        let listed = |(name, _): &PackageKey, package: &Listed| DiffedPackage {
+            name: name.clone(),
+            version: join(&package.versions),
+            purl: package.purls.iter().next().cloned(),
+            vulnerabilities: package.vulnerabilities.values().cloned().collect(),
+        };
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        for (key, package) in &new_packages {
+            match old_packages.get(key) {
+                None => added.push(listed(key, package)),
+                Some(old_package) if old_package.versions != package.versions => changed.push(VersionChange {
+                    name: key.0.clone(),
+                    purl: package.purls.iter().next().cloned(),
+                    old_version: join(&old_package.versions),
+                    new_version: join(&package.versions),
+                    vulnerabilities: package
+                        .vulnerabilities
+                        .iter()
+                        .filter(|(id, _)| !old_package.vulnerabilities.contains_key(*id))
+                        .map(|(_, vulnerability)| vulnerability.clone())
+                        .collect(),
+                }),
+                Some(_) => {}
+            }
+        }
+        let removed = old_packages
+            .iter()
+            .filter(|(key, _)| !new_packages.contains_key(*key))
+            .map(|(key, package)| listed(key, package))
+            .collect();
+
+        SbomDiff {
+            old: DiffedImage { image: old.image_name.clone(), digest: old.image_digest.clone() },
+            new: DiffedImage { image: new.image_name.clone(), digest: new.image_digest.clone() },
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut report = format!(
+            "{} ({}) -> {} ({}): {} added, {} removed, {} changed\n",
+            self.old.image,
+            self.old.digest,
+            self.new.image,
+            self.new.digest,
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        );
+        if self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() {
+            return report;
+        }
+        let mut table = Table::new();
+        table.add_row(row!["Change", "Package", "Version", "Purl", "New Vulnerabilities"]);
+        for package in &self.added {
+            table.add_row(row!["added", package.name, package.version, package.purl.as_deref().unwrap_or(""), ids(&package.vulnerabilities)]);
+        }
+        for package in &self.removed {
+            table.add_row(row!["removed", package.name, package.version, package.purl.as_deref().unwrap_or(""), ""]);
+        }
+        for change in &self.changed {
+            table.add_row(row![
+                "changed",
+                change.name,
+                format!("{} → {}", change.old_version, change.new_version),
+                change.purl.as_deref().unwrap_or(""),
+                ids(&change.vulnerabilities)
+            ]);
+        }
+        report.push_str(&table.to_string());
+        report
+    }
+}
+
+// Every package in the final image, as the merged filesystem has them. Each
+// layer that writes a package database carries all of it, so its packages
+// replace those lower layers read from the same database, and a layer
+// deleting the database removes them: a package that was uninstalled in a
+// later layer is not in the image.
+fn packages(sbom: &Sbom) -> BTreeMap<PackageKey, Listed> {
+    let mut databases: BTreeMap<&str, Vec<&Package>> = BTreeMap::new();
+    for layer in &sbom.layers {
+        let entries: Vec<(&str, &str)> = layer
+            .files
+            .iter()
+            .flat_map(|file| std::iter::once(&file.path).chain(&file.duplicate_paths).map(|path| (path.as_str(), file.file_type.as_str())))
+            .filter_map(|(path, file_type)| Some((layer.rooted(path)?, file_type)))
+            .collect();
+        for (path, _) in &entries {
+            match whiteout(path) {
+                Some(Whiteout::Path(deleted)) => databases.retain(|database, _| *database != deleted && !is_under(database, &deleted)),
+                Some(Whiteout::Opaque(dir)) => databases.retain(|database, _| !is_under(database, &dir)),
+                None => {}
+            }
+        }
+        // A database file the layer rewrote holds only what it lists, if
+        // anything
+        let rewritten: BTreeSet<&str> = entries.iter().filter(|(_, file_type)| *file_type == "file").map(|(path, _)| *path).collect();
+        databases.retain(|database, _| !rewritten.contains(database));
+        databases.extend(layer_databases(layer));
+    }
+
+    let mut packages: BTreeMap<PackageKey, Listed> = BTreeMap::new();
+    for package in databases.into_values().flatten() {
+        let key = (package.name.clone(), package.purl.as_deref().map(unversioned_purl));
+        let listed = packages.entry(key).or_default();
+        listed.versions.insert(package.version.clone());
+        listed.purls.extend(package.purl.clone());
+        listed.vulnerabilities.extend(package.vulnerabilities.iter().map(|vulnerability| (vulnerability.id.clone(), vulnerability.clone())));
+    }
+    packages
+}
+
+// A layer's packages by the database they were read from
+fn layer_databases(layer: &Layer) -> BTreeMap<&str, Vec<&Package>> {
+    let mut databases: BTreeMap<&str, Vec<&Package>> = BTreeMap::new();
+    for package in &layer.packages {
+        databases.entry(database(package)).or_default().push(package);
+    }
+    databases
+}
+
+// The file a package was read from, as a path in the image, when --explain
+// recorded it; archives nested in Java archives count as the outer archive.
+// Otherwise the directory language packages are installed in, or, for
+// system packages, which each package manager keeps in one database, their
+// purl type.
+fn database(package: &Package) -> &str {
+    let path = match &package.detection {
+        Some(detection) => detection.source_file.as_str(),
+        None if package.source.starts_with('/') => package.source.as_str(),
+        None => {
+            let purl = package.purl.as_deref().unwrap_or_default();
+            return purl.find('/').map_or(purl, |end| &purl[..end]);
+        }
+    };
+    path.split("!/").next().unwrap_or_default().trim_start_matches('/')
+}
+
+// pkg:npm/%40types/node@20.1.0?arch=x64#lib is pkg:npm/%40types/node; the
+// namespace and name are percent-encoded, so the first `@` starts the version
+fn unversioned_purl(purl: &str) -> String {
+    let end = purl.find(['@', '?', '#']).unwrap_or(purl.len());
+    purl[..end].to_string()
+}
+
+fn ids(vulnerabilities: &[Vulnerability]) -> String {
+    vulnerabilities.iter().map(|vulnerability| vulnerability.id.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+fn join(versions: &BTreeSet<String>) -> String {
+    versions.iter().cloned().collect::<Vec<_>>().join(", ")
+}
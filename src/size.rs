@@ -0,0 +1,128 @@
+use std::collections::HashSet;
+use prettytable::{row, Table};
+use serde::Serialize;
+use crate::checks::{format_size, normalize_path};
+use crate::layerdiff::{is_under, whiteout, Whiteout};
+use crate::Layer;
+
+const LARGEST_FILES: usize = 5;
+
+// Where an image's bytes come from: every layer with the bytes it adds, how
+// many of those a later layer overwrites or deletes again (still shipped, but
+// invisible in the final filesystem), and its largest files. Layers are
+// ranked by their unique bytes, the ones still visible in the final
+// filesystem, largest first.
+#[derive(Debug, Serialize)]
+pub struct SizeReport {
+    pub image: String,
+    pub platform: Option<String>,
+    pub total_size: u64,
+    pub layers: Vec<LayerSize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LayerSize {
+    // Position in the image, starting at 1 for the base layer
+    pub index: usize,
+    pub layer_id: String,
+    pub created_by: Option<String>,
+    pub size: u64,
+    pub shadowed_size: u64,
+    // Size less shadowed size
+    pub unique_size: u64,
+    pub files: usize,
+    pub largest_files: Vec<SizedFile>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SizedFile {
+    pub path: String,
+    pub size: u64,
+}
+
+impl SizeReport {
+    pub fn new(image: &str, platform: Option<&str>, layers: &[Layer], layer_commands: &[Option<String>]) -> SizeReport {
+        // Walk from the top layer down, collecting what later layers replace
+        let mut later_paths: HashSet<String> = HashSet::new();
+        let mut deleted_dirs: Vec<String> = Vec::new();
+        let mut sizes = Vec::new();
+        for (index, layer) in layers.iter().enumerate().rev() {
+            let mut files: Vec<SizedFile> = Vec::new();
+            let mut shadowed_size = 0;
+            for file in layer.files.iter().filter(|file| file.file_type == "file") {
+                let path = normalize_path(&file.path).to_string();
+                if whiteout(&path).is_some() {
+                    continue;
+                }
+                if later_paths.contains(&path) || deleted_dirs.iter().any(|dir| path == *dir || is_under(&path, dir)) {
+                    shadowed_size += file.size;
+                }
+                files.push(SizedFile { path, size: file.size });
+            }
+
+            for file in &layer.files {
+                let path = normalize_path(&file.path);
+                match whiteout(path) {
+                    Some(Whiteout::Path(deleted)) => deleted_dirs.push(deleted),
+                    Some(Whiteout::Opaque(dir)) => deleted_dirs.push(dir),
+                    None => {
+                        later_paths.insert(path.trim_end_matches('/').to_string());
+                    }
+                }
+            }
+
+            files.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+            let size = files.iter().map(|file| file.size).sum();
+            sizes.push(LayerSize {
+                index: index + 1,
+                layer_id: layer.layer_id.clone(),
+                created_by: layer_commands.get(index).cloned().flatten(),
+                size,
+                shadowed_size,
+                unique_size: size - shadowed_size,
+                files: files.len(),
+                largest_files: files.into_iter().take(LARGEST_FILES).collect(),
+            });
+        }
+
+        sizes.sort_by(|a, b| b.unique_size.cmp(&a.unique_size).then_with(|| a.index.cmp(&b.index)));
+        SizeReport {
+            image: image.to_string(),
+            platform: platform.map(str::to_string),
+            total_size: sizes.iter().map(|layer| layer.size).sum(),
+            layers: sizes,
+        }
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = Table::new();
+        table.add_row(row!["Layer", "Size", "Share", "Shadowed", "Unique", "Created By"]);
+        for layer in &self.layers {
+            let share = match self.total_size {
+                0 => 0.0,
+                total => layer.size as f64 * 100.0 / total as f64,
+            };
+            table.add_row(row![
+                format!("{} ({})", layer.index, layer.layer_id.chars().take(19).collect::<String>()),
+                format_size(layer.size),
+                format!("{:.1}%", share),
+                format_size(layer.shadowed_size),
+                format_size(layer.unique_size),
+                layer.created_by.as_deref().map(truncate_command).unwrap_or_else(|| "unknown".to_string())
+            ]);
+            for file in &layer.largest_files {
+                table.add_row(row!["", format_size(file.size), "", "", "", format!("  /{}", file.path)]);
+            }
+        }
+        let platform = self.platform.as_deref().map(|platform| format!(" ({})", platform)).unwrap_or_default();
+        format!("Size report for {}{}: {} in {} layers\n{}", self.image, platform, format_size(self.total_size), self.layers.len(), table)
+    }
+}
+
+fn truncate_command(command: &str) -> String {
+    let command = command.split_whitespace().collect::<Vec<_>>().join(" ");
+    match command.char_indices().nth(80) {
+        Some((end, _)) => format!("{}...", &command[..end]),
+        None => command,
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+#[cfg(unix)]
+use crate::podman;
+use crate::progress::ProgressReporter;
+use crate::registry::Result;
+use crate::{analyze_image, archive, ensure_image_exists, image_history, inspect_healthcheck, inspect_labels, inspect_platform, inspect_process, inspect_repo_digest, oci, registry, ExtractOptions, Healthcheck, Layer};
+
+// Where image layers are read from. Every source produces the same analyzed
+// layers so the rest of the pipeline does not care how the image was obtained.
+pub enum ImageSource {
+    Docker { jobs: usize, platform: Option<String> },
+    Registry { platform: Option<String>, jobs: usize },
+    #[cfg(unix)]
+    Podman { storage_root: PathBuf },
+    Archive { path: PathBuf },
+    OciLayout { dir: PathBuf, platform: Option<String> },
+}
+
+pub struct AnalyzedImage {
+    pub platform: Option<String>,
+    pub layers: Vec<Layer>,
+    pub healthcheck: Option<Healthcheck>,
+    pub labels: BTreeMap<String, String>,
+    // The process the image runs: ENTRYPOINT, then CMD as its arguments
+    pub entrypoint: Option<Vec<String>>,
+    pub cmd: Option<Vec<String>>,
+    // The build step that created each layer, and when, in layer order
+    pub layer_commands: Vec<Option<String>>,
+    pub layer_created: Vec<Option<String>>,
+    // The registry manifest digest, and the digest of the image config (the
+    // image ID), which locally built images have even without a manifest
+    pub digest: Option<String>,
+    pub image_id: Option<String>,
+}
+
+impl ImageSource {
+    // Picks the source selected by the --from-archive, --oci-layout,
+    // --registry, --runtime and --podman-storage options. `platform` picks an
+    // image out of an image index; sources holding a single image ignore it.
+    pub fn from_options(
+        from_archive: Option<&String>,
+        oci_layout: Option<&String>,
+        from_registry: bool,
+        runtime: &str,
+        podman_storage: Option<&String>,
+        platform: Option<&String>,
+        jobs: usize,
+    ) -> Result<ImageSource> {
+        let platform = platform.cloned();
+        if let Some(path) = from_archive {
+            return Ok(ImageSource::Archive { path: PathBuf::from(path) });
+        }
+        if let Some(dir) = oci_layout {
+            return Ok(ImageSource::OciLayout { dir: PathBuf::from(dir), platform });
+        }
+        if from_registry {
+            return Ok(ImageSource::Registry { platform, jobs });
+        }
+        if runtime == "podman" {
+            return podman_source(podman_storage);
+        }
+        Ok(ImageSource::Docker { jobs, platform })
+    }
+
+    // Identifies the exact image content, for caching. None for images that
+    // were never pulled from or pushed to a registry, which have no repo digest.
+    pub async fn cache_key(&self, image_name: &str) -> Result<Option<String>> {
+        match self {
+            // As for the registry, the digest of an index is qualified with
+            // the platform the daemon holds
+            ImageSource::Docker { platform, .. } => {
+                let image_inspect = ensure_image_exists(image_name, platform.as_deref()).await?;
+                let digest = inspect_repo_digest(image_name, &image_inspect);
+                Ok(match inspect_platform(&image_inspect) {
+                    Some(platform) => digest.map(|digest| format!("{}-{}", digest, platform)),
+                    None => digest,
+                })
+            }
+            // An index digest covers every platform, so qualify it with the one analyzed
+            ImageSource::Registry { platform, .. } => {
+                let digest = registry::repo_digest(image_name).await?;
+                Ok(Some(format!("{}-{}", digest, platform.clone().unwrap_or_else(registry::default_platform))))
+            }
+            #[cfg(unix)]
+            ImageSource::Podman { storage_root } => podman::repo_digest(storage_root, image_name),
+            // Saved archives carry no repo digest, and local layouts are read
+            // directly from disk
+            ImageSource::Archive { .. } | ImageSource::OciLayout { .. } => Ok(None),
+        }
+    }
+
+    pub async fn analyze(&self, image_name: &str, options: ExtractOptions, progress: &ProgressReporter) -> Result<AnalyzedImage> {
+        match self {
+            // The image is inspected once, and everything but its layers and
+            // history is read from that
+            ImageSource::Docker { jobs, platform } => {
+                let image_inspect = ensure_image_exists(image_name, platform.as_deref()).await?;
+                let (entrypoint, cmd) = inspect_process(&image_inspect);
+                let layers = analyze_image(image_name, &image_inspect, *jobs, options, progress).await?;
+                let history = image_history(image_name).await;
+                Ok(AnalyzedImage {
+                    platform: inspect_platform(&image_inspect),
+                    layer_commands: registry::layer_commands(&history, layers.len()),
+                    layer_created: registry::layer_created(&history, layers.len()),
+                    layers,
+                    healthcheck: inspect_healthcheck(&image_inspect),
+                    labels: inspect_labels(&image_inspect),
+                    entrypoint,
+                    cmd,
+                    digest: inspect_repo_digest(image_name, &image_inspect),
+                    image_id: image_inspect.id,
+                })
+            }
+            ImageSource::Registry { platform, jobs } => registry::analyze_registry_image(image_name, platform.as_deref(), *jobs, options, progress).await,
+            #[cfg(unix)]
+            ImageSource::Podman { storage_root } => {
+                let storage_root = storage_root.clone();
+                let image_name = image_name.to_string();
+                let progress = progress.clone();
+                tokio::task::spawn_blocking(move || podman::analyze_podman_image(&storage_root, &image_name, options, &progress)).await?
+            }
+            ImageSource::Archive { path } => {
+                let path = path.clone();
+                let image_name = image_name.to_string();
+                let progress = progress.clone();
+                tokio::task::spawn_blocking(move || archive::analyze_image_archive(&path, &image_name, options, &progress)).await?
+            }
+            ImageSource::OciLayout { dir, platform } => {
+                let dir = dir.clone();
+                let platform = platform.clone();
+                let image_name = image_name.to_string();
+                let progress = progress.clone();
+                tokio::task::spawn_blocking(move || oci::analyze_oci_layout(&dir, &image_name, platform.as_deref(), options, &progress)).await?
+            }
+        }
+    }
+}
+
+// Podman's storage is read straight from disk, with Unix file metadata
+#[cfg(unix)]
+fn podman_source(podman_storage: Option<&String>) -> Result<ImageSource> {
+    let storage_root = match podman_storage {
+        Some(dir) => PathBuf::from(dir),
+        None => podman::default_storage_root()?,
+    };
+    Ok(ImageSource::Podman { storage_root })
+}
+
+#[cfg(not(unix))]
+fn podman_source(_podman_storage: Option<&String>) -> Result<ImageSource> {
+    Err("--runtime podman is only supported on Unix; export the image with `podman save` and pass --from-archive instead".into())
+}
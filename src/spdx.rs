@@ -0,0 +1,299 @@
+use std::collections::HashSet;
+use serde::Serialize;
+use crate::{Package, Sbom};
+
+const SPDX_VERSION: &str = "SPDX-2.3";
+const DATA_LICENSE: &str = "CC0-1.0";
+const NOASSERTION: &str = "NOASSERTION";
+
+// An SPDX 2.3 document, serialized as the JSON schema names its fields. The
+// document describes the image, which contains its layers, which contain
+// their packages and files; a package found in several layers appears once
+// per layer. Tag-value output is written from the same packages and
+// relationships.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Document {
+    spdx_version: &'static str,
+    data_license: &'static str,
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    name: String,
+    document_namespace: String,
+    creation_info: CreationInfo,
+    packages: Vec<SpdxPackage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    files: Vec<SpdxFile>,
+    relationships: Vec<Relationship>,
+}
+
+#[derive(Debug, Serialize)]
+struct CreationInfo {
+    created: String,
+    creators: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxPackage {
+    name: String,
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    version_info: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    supplier: Option<String>,
+    download_location: String,
+    files_analyzed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license_concluded: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    checksums: Vec<Checksum>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    external_refs: Vec<ExternalRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    built_date: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    primary_package_purpose: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SpdxFile {
+    file_name: String,
+    #[serde(rename = "SPDXID")]
+    spdx_id: String,
+    checksums: Vec<Checksum>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Checksum {
+    algorithm: &'static str,
+    checksum_value: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ExternalRef {
+    reference_category: &'static str,
+    reference_type: &'static str,
+    reference_locator: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Relationship {
+    spdx_element_id: String,
+    relationship_type: &'static str,
+    related_spdx_element: String,
+}
+
+impl Relationship {
+    fn new(element: &str, kind: &'static str, related: &str) -> Relationship {
+        Relationship { spdx_element_id: element.to_string(), relationship_type: kind, related_spdx_element: related.to_string() }
+    }
+}
+
+pub fn generate_spdx(sbom: &Sbom) -> String {
+    let document = document(sbom, false);
+    let mut spdx = format!(
+        "SPDXVersion: {}\nDataLicense: {}\nSPDXID: {}\nDocumentName: {}\nDocumentNamespace: {}\n",
+        document.spdx_version, document.data_license, document.spdx_id, document.name, document.document_namespace
+    );
+    for creator in &document.creation_info.creators {
+        spdx.push_str(&format!("Creator: {}\n", creator));
+    }
+    spdx.push_str(&format!("Created: {}\n\n", document.creation_info.created));
+    for package in &document.packages {
+        spdx.push_str(&format!("PackageName: {}\nSPDXID: {}\nPackageVersion: {}\n", package.name, package.spdx_id, package.version_info));
+        if let Some(supplier) = &package.supplier {
+            spdx.push_str(&format!("PackageSupplier: {}\n", supplier));
+        }
+        spdx.push_str(&format!("PackageDownloadLocation: {}\nFilesAnalyzed: {}\n", package.download_location, package.files_analyzed));
+        if let Some(license) = &package.license_concluded {
+            spdx.push_str(&format!("PackageLicenseConcluded: {}\n", license));
+        }
+        for checksum in &package.checksums {
+            spdx.push_str(&format!("PackageChecksum: {}: {}\n", checksum.algorithm, checksum.checksum_value));
+        }
+        for external_ref in &package.external_refs {
+            spdx.push_str(&format!("ExternalRef: {} {} {}\n", external_ref.reference_category, external_ref.reference_type, external_ref.reference_locator));
+        }
+        if let Some(built_date) = &package.built_date {
+            spdx.push_str(&format!("BuiltDate: {}\n", built_date));
+        }
+        if let Some(purpose) = package.primary_package_purpose {
+            spdx.push_str(&format!("PrimaryPackagePurpose: {}\n", purpose));
+        }
+        if let Some(comment) = &package.comment {
+            spdx.push_str(&format!("PackageComment: {}\n", comment));
+        }
+        spdx.push('\n');
+    }
+    for relationship in &document.relationships {
+        spdx.push_str(&format!(
+            "Relationship: {} {} {}\n",
+            relationship.spdx_element_id, relationship.relationship_type, relationship.related_spdx_element
+        ));
+    }
+    spdx
+}
+
+// The JSON serialization also lists the files of every layer
+pub fn generate_spdx_json(sbom: &Sbom) -> String {
+    serde_json::to_string_pretty(&document(sbom, true)).expect("Failed to serialize SPDX document")
+}
+
+fn document(sbom: &Sbom, with_files: bool) -> Document {
+    let mut packages = vec![SpdxPackage {
+        name: sbom.image_name.clone(),
+        spdx_id: "SPDXRef-Image".to_string(),
+        version_info: sbom.image_digest.clone(),
+        supplier: None,
+        download_location: NOASSERTION.to_string(),
+        files_analyzed: false,
+        license_concluded: None,
+        checksums: Vec::new(),
+        external_refs: Vec::new(),
+        built_date: None,
+        primary_package_purpose: Some("CONTAINER"),
+        comment: None,
+    }];
+    let mut files = Vec::new();
+    let mut relationships = vec![Relationship::new(&sbom.spdx_id, "DESCRIBES", "SPDXRef-Image")];
+
+    let mut ids = SpdxIds::default();
+    ids.reserve(&sbom.spdx_id);
+    ids.reserve("SPDXRef-Image");
+    for index in 0..sbom.layers.len() {
+        ids.reserve(&format!("SPDXRef-Layer-{}", index + 1));
+    }
+    for (index, layer) in sbom.layers.iter().enumerate() {
+        let layer_ref = format!("SPDXRef-Layer-{}", index + 1);
+        relationships.push(Relationship::new("SPDXRef-Image", "CONTAINS", &layer_ref));
+        packages.push(SpdxPackage {
+            name: layer.layer_id.clone(),
+            spdx_id: layer_ref.clone(),
+            version_info: layer.layer_id.clone(),
+            supplier: None,
+            download_location: NOASSERTION.to_string(),
+            files_analyzed: false,
+            license_concluded: None,
+            checksums: Vec::new(),
+            external_refs: Vec::new(),
+            built_date: None,
+            primary_package_purpose: None,
+            comment: Some(format!("Layer {} of {}", index + 1, sbom.image_name)),
+        });
+        for package in &layer.packages {
+            let package_ref = ids.assign(&package.name);
+            relationships.push(Relationship::new(&layer_ref, "CONTAINS", &package_ref));
+            packages.push(spdx_package(package, package_ref));
+        }
+        if !with_files {
+            continue;
+        }
+        // Files that were too large to hash have no checksum, which SPDX requires
+        for file in layer.files.iter().filter(|file| file.file_type == "file") {
+            let (Some(path), Some(algorithm)) = (layer.rooted(&file.path), checksum_algorithm(&file.checksum)) else { continue };
+            let file_ref = format!("SPDXRef-File-{}", files.len() + 1);
+            relationships.push(Relationship::new(&layer_ref, "CONTAINS", &file_ref));
+            files.push(SpdxFile {
+                file_name: format!("./{}", path),
+                spdx_id: file_ref,
+                checksums: vec![Checksum { algorithm, checksum_value: file.checksum.to_ascii_lowercase() }],
+            });
+        }
+    }
+
+    Document {
+        spdx_version: SPDX_VERSION,
+        data_license: DATA_LICENSE,
+        spdx_id: sbom.spdx_id.clone(),
+        name: sbom.name.clone(),
+        document_namespace: sbom.namespace.clone(),
+        creation_info: CreationInfo { created: sbom.creation_info.created.clone(), creators: sbom.creation_info.creators.clone() },
+        packages,
+        files,
+        relationships,
+    }
+}
+
+// SPDX wants a supplier named as a person or organization and a download
+// location that is a URL; vendors are organizations, and the source of a
+// package is often the path it was found at, which is left out
+fn spdx_package(package: &Package, spdx_id: String) -> SpdxPackage {
+    let download_location = match package.source.as_str() {
+        source if ["http://", "https://", "git+"].iter().any(|scheme| source.starts_with(scheme)) => source.to_string(),
+        _ => NOASSERTION.to_string(),
+    };
+    SpdxPackage {
+        name: package.name.clone(),
+        spdx_id,
+        version_info: package.version.clone(),
+        supplier: Some(package.vendor.trim()).filter(|vendor| !vendor.is_empty()).map(|vendor| format!("Organization: {}", vendor)),
+        download_location,
+        files_analyzed: false,
+        license_concluded: Some(package.spdx_license.clone().unwrap_or_else(|| NOASSERTION.to_string())),
+        checksums: checksum_algorithm(&package.checksum)
+            .map(|algorithm| Checksum { algorithm, checksum_value: package.checksum.to_ascii_lowercase() })
+            .into_iter()
+            .collect(),
+        external_refs: package
+            .purl
+            .iter()
+            .map(|purl| ExternalRef { reference_category: "PACKAGE-MANAGER", reference_type: "purl", reference_locator: purl.clone() })
+            .collect(),
+        built_date: package.built_at.clone(),
+        primary_package_purpose: None,
+        comment: None,
+    }
+}
+
+// The SPDX name of a checksum's algorithm, told apart by digest length:
+// package databases record SHA-1 (apk, composer), MD5 (conda) and SHA-256
+// digests, and file checksums are SHA-256
+fn checksum_algorithm(checksum: &str) -> Option<&'static str> {
+    if !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    match checksum.len() {
+        32 => Some("MD5"),
+        40 => Some("SHA1"),
+        64 => Some("SHA256"),
+        _ => None,
+    }
+}
+
+// SPDX identifiers for packages: the name with anything but letters, digits,
+// `.` and `-` replaced, and a numeric suffix for identifiers already taken,
+// whether by the same package in another layer, by a name that sanitizes the
+// same or by the image and its layers
+#[derive(Default)]
+struct SpdxIds {
+    taken: HashSet<String>,
+}
+
+impl SpdxIds {
+    fn reserve(&mut self, id: &str) {
+        self.taken.insert(id.to_string());
+    }
+
+    fn assign(&mut self, name: &str) -> String {
+        let sanitized: String = name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '-' }).collect();
+        let base = match sanitized.trim_matches('-') {
+            "" => "SPDXRef-package".to_string(),
+            sanitized => format!("SPDXRef-{}", sanitized),
+        };
+        let mut id = base.clone();
+        let mut suffix = 1;
+        while self.taken.contains(&id) {
+            suffix += 1;
+            id = format!("{}-{}", base, suffix);
+        }
+        self.taken.insert(id.clone());
+        id
+    }
+}
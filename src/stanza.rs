@@ -0,0 +1,95 @@
+// Package databases such as dpkg's status file, apt's extended_states and
+// apk's installed database are lists of stanzas separated by blank lines, each
+// a run of `Key: value` fields. A line starting with a space or tab continues
+// the field above it, as dpkg's multi-line Description and Conffiles do; its
+// text is appended to the value on a new line.
+
+// The fields of one stanza in file order. Keys may repeat: apk lists one R:
+// line per file a package installs.
+pub struct Stanza {
+    fields: Vec<(String, String)>,
+}
+
+impl Stanza {
+    // The first value of the field. Names match exactly, since apk's P: (name)
+    // and p: (provides) are different fields.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    // For Debian's control fields, whose names are case-insensitive
+    pub fn field_ignore_case(&self, name: &str) -> Option<&str> {
+        self.fields.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+pub fn read_stanzas(text: &str) -> Vec<Stanza> {
+    let mut stanzas = Vec::new();
+    let mut fields: Vec<(String, String)> = Vec::new();
+    // The last stanza need not end with a blank line
+    for line in text.lines().chain(std::iter::once("")) {
+        if line.trim().is_empty() {
+            if !fields.is_empty() {
+                stanzas.push(Stanza { fields: std::mem::take(&mut fields) });
+            }
+        } else if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            fields.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    stanzas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_stanzas_separated_by_blank_lines() {
+        let stanzas = read_stanzas("Package: zlib1g\nVersion: 1:1.2.13\n\n\nPackage: libc6\nVersion: 2.36-9\n");
+        assert_eq!(stanzas.len(), 2);
+        assert_eq!(stanzas[0].field("Package"), Some("zlib1g"));
+        assert_eq!(stanzas[0].field("Version"), Some("1:1.2.13"));
+        assert_eq!(stanzas[1].field("Package"), Some("libc6"));
+    }
+
+    #[test]
+    fn reads_the_last_stanza_without_a_trailing_blank_line() {
+        let stanzas = read_stanzas("P:musl\nV:1.2.5-r0\n\nP:busybox\nV:1.36.1-r29");
+        assert_eq!(stanzas.len(), 2);
+        assert_eq!(stanzas[1].field("V"), Some("1.36.1-r29"));
+    }
+
+    #[test]
+    fn appends_continuation_lines_on_new_lines() {
+        let stanzas = read_stanzas("Package: bash\nDescription: GNU Bourne Again SHell\n Bash is an sh-compatible\n\tcommand language interpreter.\nStatus: install ok installed\n");
+        assert_eq!(stanzas[0].field("Description"), Some("GNU Bourne Again SHell\nBash is an sh-compatible\ncommand language interpreter."));
+        assert_eq!(stanzas[0].field("Status"), Some("install ok installed"));
+    }
+
+    #[test]
+    fn returns_the_first_of_repeated_fields() {
+        let stanzas = read_stanzas("P:busybox\nR:busybox\nR:busybox-extras\n");
+        assert_eq!(stanzas[0].field("R"), Some("busybox"));
+    }
+
+    #[test]
+    fn matches_field_names_exactly_unless_asked_not_to() {
+        let stanzas = read_stanzas("P:busybox\np:cmd:sh\n");
+        assert_eq!(stanzas[0].field("P"), Some("busybox"));
+        assert_eq!(stanzas[0].field("p"), Some("cmd:sh"));
+        let stanzas = read_stanzas("package: bash\n");
+        assert_eq!(stanzas[0].field("Package"), None);
+        assert_eq!(stanzas[0].field_ignore_case("Package"), Some("bash"));
+    }
+
+    #[test]
+    fn reads_nothing_from_blank_text() {
+        assert!(read_stanzas("").is_empty());
+        assert!(read_stanzas("\n  \n\n").is_empty());
+    }
+}
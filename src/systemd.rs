@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::checks::normalize_path;
+use crate::Layer;
+
+// Where unit files live, in order of precedence: units in /etc override the
+// ones packages install. /lib/systemd/system is the pre-usrmerge location.
+const UNIT_DIRS: &[&str] = &["etc/systemd/system", "usr/lib/systemd/system", "lib/systemd/system"];
+
+// Daemons that accept connections from the network when started
+const NETWORK_DAEMONS: &[&str] = &[
+    "sshd", "dropbear", "nginx", "httpd", "apache2", "lighttpd", "caddy", "haproxy", "squid", "named", "dnsmasq",
+    "unbound", "postfix", "master", "exim", "exim4", "dovecot", "vsftpd", "proftpd", "smbd", "nmbd", "mysqld",
+    "mariadbd", "postgres", "redis-server", "mongod", "memcached", "slapd", "rpcbind", "xinetd", "telnetd",
+    "in.telnetd", "cupsd", "avahi-daemon", "snmpd", "ntpd", "chronyd", "rsyncd", "nfsd", "tftpd", "in.tftpd",
+];
+
+// A systemd service unit and how it would run when the image boots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Service {
+    pub unit: String,
+    pub path: String,
+    pub exec_start: Vec<String>,
+    pub user: Option<String>,
+    // enabled, disabled or masked
+    pub state: String,
+    // Targets (or units) whose .wants/.requires links enable the service
+    pub wanted_by: Vec<String>,
+    // Addresses the service's socket unit listens on
+    pub listens: Vec<String>,
+    pub runs_as_root: bool,
+    pub network_facing: bool,
+}
+
+pub fn is_unit_path(path: &str) -> bool {
+    let path = normalize_path(path);
+    let Some((dir, name)) = path.rsplit_once('/') else {
+        return false;
+    };
+    UNIT_DIRS.contains(&dir) && (name.ends_with(".service") || name.ends_with(".socket"))
+}
+
+// Lists every service unit in the merged filesystem, enabled or not
+pub fn detect_services(layers: &[Layer]) -> Vec<Service> {
+    // Unit name -> (precedence, path); the newest layer's copy of a path wins
+    let mut units: BTreeMap<&str, (usize, &str)> = BTreeMap::new();
+    for layer in layers {
+        for path in layer.contents.keys().filter(|path| is_unit_path(path)) {
+            let (dir, name) = path.rsplit_once('/').unwrap();
+            let precedence = UNIT_DIRS.iter().position(|unit_dir| *unit_dir == dir).unwrap();
+            let entry = units.entry(name).or_insert((precedence, path.as_str()));
+            if precedence <= entry.0 {
+                *entry = (precedence, path.as_str());
+            }
+        }
+    }
+    let latest = |path: &str| layers.iter().rev().find_map(|layer| layer.contents.get(path)).map(|data| String::from_utf8_lossy(data).into_owned());
+
+    // Enablement is a link in a .wants or .requires directory named after
+    // the unit (or its template, for instances such as getty@tty1.service);
+    // masking is a link to /dev/null in place of the unit
+    let mut wanted_by: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut masked = Vec::new();
+    for (path, target) in layers.iter().flat_map(|layer| &layer.links) {
+        let Some((dir, name)) = path.rsplit_once('/') else { continue };
+        if dir == UNIT_DIRS[0] && target == "dev/null" {
+            masked.push(name.to_string());
+        }
+        let Some((unit_dir, wants)) = dir.rsplit_once('/') else { continue };
+        let Some(wanting) = wants.strip_suffix(".wants").or_else(|| wants.strip_suffix(".requires")) else { continue };
+        if UNIT_DIRS.contains(&unit_dir) {
+            let wanted_by = wanted_by.entry(template_name(name)).or_default();
+            if !wanted_by.iter().any(|unit| unit == wanting) {
+                wanted_by.push(wanting.to_string());
+            }
+        }
+    }
+
+    let mut services = Vec::new();
+    for (unit, (_, path)) in units.iter().filter(|(unit, _)| unit.ends_with(".service")) {
+        let Some(text) = latest(path) else { continue };
+        let service = unit_section(&text, "Service");
+        let exec_start: Vec<String> = service
+            .iter()
+            .filter(|(key, _)| key == "ExecStart")
+            // Prefixes such as "-" (ignore failure) or "+" (full privileges) are not part of the command
+            .map(|(_, value)| value.trim_start_matches(['-', '@', ':', '+', '!']).to_string())
+            .filter(|command| !command.is_empty())
+            .collect();
+        let value = |name: &str| service.iter().rev().find(|(key, _)| key == name).map(|(_, value)| value.clone());
+        let user = value("User");
+        let dynamic_user = value("DynamicUser").is_some_and(|value| matches!(value.as_str(), "yes" | "true" | "1" | "on"));
+
+        // A socket unit of the same name (or naming this service) listens on its behalf
+        let socket_name = format!("{}.socket", unit.trim_end_matches(".service"));
+        let mut listens = Vec::new();
+        let mut socket_wanted_by = Vec::new();
+        for (socket, (_, socket_path)) in units.iter().filter(|(name, _)| name.ends_with(".socket")) {
+            let Some(socket_text) = latest(socket_path) else { continue };
+            let section = unit_section(&socket_text, "Socket");
+            let activates = section.iter().find(|(key, _)| key == "Service").map(|(_, value)| value.as_str());
+            if activates == Some(*unit) || (activates.is_none() && *socket == socket_name) {
+                listens.extend(section.iter().filter(|(key, _)| key.starts_with("Listen")).map(|(_, value)| value.clone()));
+                socket_wanted_by.extend(wanted_by.get(*socket).cloned().unwrap_or_default());
+            }
+        }
+
+        let mut enabled_by = wanted_by.get(&template_name(unit)).cloned().unwrap_or_default();
+        enabled_by.extend(socket_wanted_by);
+        enabled_by.sort();
+        enabled_by.dedup();
+        let state = if masked.iter().any(|name| name == unit) {
+            "masked"
+        } else if enabled_by.is_empty() {
+            "disabled"
+        } else {
+            "enabled"
+        };
+        let network_facing = !listens.is_empty()
+            || exec_start.iter().any(|command| {
+                let binary = command.split_whitespace().next().unwrap_or_default();
+                NETWORK_DAEMONS.contains(&binary.rsplit('/').next().unwrap_or(binary))
+            });
+
+        services.push(Service {
+            unit: unit.to_string(),
+            path: format!("/{}", path),
+            exec_start,
+            runs_as_root: !dynamic_user && user.as_deref().is_none_or(|user| matches!(user, "root" | "0")),
+            user,
+            state: state.to_string(),
+            wanted_by: enabled_by,
+            listens,
+            network_facing,
+        });
+    }
+    services
+}
+
+// getty@tty1.service -> getty@.service
+fn template_name(unit: &str) -> String {
+    match (unit.split_once('@'), unit.rsplit_once('.')) {
+        (Some((prefix, _)), Some((_, suffix))) => format!("{}@.{}", prefix, suffix),
+        _ => unit.to_string(),
+    }
+}
+
+// Key/value pairs of one [Section] of a unit file, in order. Backslash
+// continuations are joined; keys may repeat.
+fn unit_section(text: &str, section: &str) -> Vec<(String, String)> {
+    let mut entries = Vec::new();
+    let mut current = "";
+    let mut pending = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if pending.is_empty() && (line.starts_with('#') || line.starts_with(';')) {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            current = name;
+            continue;
+        }
+        if let Some(line) = line.strip_suffix('\\') {
+            pending.push_str(line.trim_end());
+            pending.push(' ');
+            continue;
+        }
+        pending.push_str(line);
+        let entry = std::mem::take(&mut pending);
+        if current == section {
+            if let Some((key, value)) = entry.split_once('=') {
+                entries.push((key.trim().to_string(), value.trim().to_string()));
+            }
+        }
+    }
+    entries
+}
@@ -0,0 +1,60 @@
+use serde::Serialize;
+use crate::{Package, Sbom};
+
+// Lengths of the hex digests package databases record: MD5, SHA-1, SHA-256
+// and SHA-512
+const DIGEST_LENGTHS: &[usize] = &[32, 40, 64, 128];
+
+// Something wrong with an SBOM, at the JSON path of the offending field
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub path: String,
+    pub message: String,
+}
+
+impl Problem {
+    fn new(path: impl Into<String>, message: impl Into<String>) -> Problem {
+        Problem { path: path.into(), message: message.into() }
+    }
+}
+
+// Checks that an SBOM has what consumers rely on: a document identifier, the
+// image it describes, at least one layer, and package checksums that are
+// hex digests. Packages without a checksum record none, as an empty string or,
+// for packages guessed from a Dockerfile, "unknown".
+pub fn validate_sbom(sbom: &Sbom) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if sbom.spdx_id.trim().is_empty() {
+        problems.push(Problem::new("spdx_id", "is empty"));
+    }
+    if sbom.image_name.trim().is_empty() {
+        problems.push(Problem::new("image_name", "is empty"));
+    }
+    if sbom.layers.is_empty() {
+        problems.push(Problem::new("layers", "lists no layers"));
+    }
+    for (index, layer) in sbom.layers.iter().enumerate() {
+        check_packages(&mut problems, &format!("layers[{}].packages", index), &layer.packages);
+    }
+    if let Some(analysis) = &sbom.dockerfile_analysis {
+        check_packages(&mut problems, "dockerfile_analysis.packages", &analysis.packages);
+    }
+    problems
+}
+
+fn check_packages(problems: &mut Vec<Problem>, path: &str, packages: &[Package]) {
+    for (index, package) in packages.iter().enumerate() {
+        let checksum = package.checksum.as_str();
+        if checksum.is_empty() || checksum == "unknown" || is_hex_digest(checksum) {
+            continue;
+        }
+        problems.push(Problem::new(
+            format!("{}[{}].checksum", path, index),
+            format!("{:?} of {} {} is not a hex MD5, SHA-1, SHA-256 or SHA-512 digest", checksum, package.name, package.version),
+        ));
+    }
+}
+
+fn is_hex_digest(checksum: &str) -> bool {
+    DIGEST_LENGTHS.contains(&checksum.len()) && checksum.bytes().all(|b| b.is_ascii_hexdigit())
+}